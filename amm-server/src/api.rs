@@ -1,11 +1,75 @@
-use axum::Router;
-use sea_orm::Database;
+use async_graphql::http::GraphiQLSource;
+use async_graphql_axum::{GraphQLRequest, GraphQLResponse, GraphQLSubscription};
+use axum::{
+    extract::State,
+    http::HeaderMap,
+    response::{Html, IntoResponse},
+    routing::get,
+    Router,
+};
+use migration::{Migrator, MigratorTrait};
+use sea_orm::{Database, DatabaseConnection};
 
-use crate::config::Opts;
+use crate::{
+    auth::Tokens,
+    config::Opts,
+    events::Events,
+    graphql::{self, AppSchema},
+    rest::{self, AppState},
+};
 
-pub async fn setup(opts: &Opts) -> Router {
-    let conn = Database::connect(opts.get_db_url())
+pub const GRAPHQL_PATH: &str = "/graphql";
+pub const GRAPHQL_WS_PATH: &str = "/graphql/ws";
+
+/// Connect to the database and bring its schema up to date.
+pub async fn connect_db(opts: &Opts) -> DatabaseConnection {
+    let options = opts
+        .get_connect_options()
+        .expect("invalid database options");
+    let conn = Database::connect(options)
         .await
         .expect("failed to connect to db");
-    todo!()
+    Migrator::up(&conn, None)
+        .await
+        .expect("failed to apply db migrations");
+    conn
+}
+
+/// GraphQL endpoint, with GraphiQL served on `GET`, and its subscriptions
+/// over WebSocket. The REST API is served alongside, see `rest`.
+///
+/// Requests are authorized by their bearer token, see `auth`.
+pub async fn setup(conn: DatabaseConnection, tokens: Tokens) -> Router {
+    let events = Events::default();
+    let schema = graphql::build_schema(conn.clone(), events.clone());
+    Router::new()
+        .route(GRAPHQL_PATH, get(graphiql).post(graphql_handler))
+        .route_service(GRAPHQL_WS_PATH, GraphQLSubscription::new(schema.clone()))
+        .with_state((schema, tokens.clone()))
+        .merge(rest::router(AppState {
+            db: conn,
+            events,
+            tokens,
+        }))
+}
+
+async fn graphiql() -> impl IntoResponse {
+    Html(
+        GraphiQLSource::build()
+            .endpoint(GRAPHQL_PATH)
+            .subscription_endpoint(GRAPHQL_WS_PATH)
+            .finish(),
+    )
+}
+
+async fn graphql_handler(
+    State((schema, tokens)): State<(AppSchema, Tokens)>,
+    headers: HeaderMap,
+    req: GraphQLRequest,
+) -> GraphQLResponse {
+    let mut req = req.into_inner();
+    if let Some(caller) = tokens.caller(&headers) {
+        req = req.data(caller);
+    }
+    schema.execute(req).await.into()
 }
@@ -0,0 +1,96 @@
+//! Callers of the APIs, identified by the bearer token of the request.
+//!
+//! The operator creates markets, moves them through their lifecycle and
+//! changes their liquidity. Traders can only trade, and always on their own
+//! behalf: the trader of a trade is the one the token was issued to, never a
+//! value sent by the client.
+
+use std::{collections::HashMap, sync::Arc};
+
+use axum::http::{header::AUTHORIZATION, HeaderMap};
+
+use crate::service::Error;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Caller {
+    Operator,
+    Trader(String),
+}
+
+impl Caller {
+    /// Trader the caller trades as.
+    pub fn trader(caller: Option<&Caller>) -> Result<String, Error> {
+        match caller {
+            Some(Caller::Trader(trader)) => Ok(trader.clone()),
+            _ => Err(Error::Unauthorized),
+        }
+    }
+
+    /// Fails unless the caller is the operator.
+    pub fn operator(caller: Option<&Caller>) -> Result<(), Error> {
+        match caller {
+            Some(Caller::Operator) => Ok(()),
+            _ => Err(Error::Unauthorized),
+        }
+    }
+}
+
+/// Caller of each token.
+#[derive(Clone, Debug, Default)]
+pub struct Tokens(Arc<HashMap<String, Caller>>);
+
+impl Tokens {
+    /// `traders` are pairs of a trader and its token.
+    pub fn new(operator: Option<String>, traders: Vec<(String, String)>) -> Self {
+        let mut tokens: HashMap<_, _> = traders
+            .into_iter()
+            .map(|(trader, token)| (token, Caller::Trader(trader)))
+            .collect();
+        if let Some(token) = operator {
+            tokens.insert(token, Caller::Operator);
+        }
+        Tokens(Arc::new(tokens))
+    }
+
+    /// Caller of the `Authorization: Bearer` token of the request, if any.
+    pub fn caller(&self, headers: &HeaderMap) -> Option<Caller> {
+        let token = headers
+            .get(AUTHORIZATION)?
+            .to_str()
+            .ok()?
+            .strip_prefix("Bearer ")?;
+        self.0.get(token).cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::http::{header::AUTHORIZATION, HeaderMap};
+
+    use super::{Caller, Tokens};
+
+    #[test]
+    fn callers_must_be_found_by_bearer_token() {
+        let tokens = Tokens::new(
+            Some("op".to_owned()),
+            vec![("alice".to_owned(), "a".to_owned())],
+        );
+        let caller = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(AUTHORIZATION, value.parse().unwrap());
+            tokens.caller(&headers)
+        };
+        assert_eq!(caller("Bearer op"), Some(Caller::Operator));
+        assert_eq!(caller("Bearer a"), Some(Caller::Trader("alice".to_owned())));
+        assert_eq!(caller("Bearer b"), None);
+        assert_eq!(caller("a"), None);
+        assert_eq!(tokens.caller(&HeaderMap::new()), None);
+
+        assert_eq!(
+            Caller::trader(caller("Bearer a").as_ref()).unwrap(),
+            "alice"
+        );
+        assert!(Caller::trader(caller("Bearer op").as_ref()).is_err());
+        assert!(Caller::operator(None).is_err());
+    }
+}
@@ -0,0 +1,101 @@
+//! Changes of markets, pushed to live subscribers.
+//!
+//! Events go through a bounded broadcast channel: publishing never waits for
+//! subscribers, and a subscriber which falls more than `CAPACITY` events
+//! behind skips the events it missed rather than holding up trades.
+
+use amm::entity::{market, trade};
+use tokio::sync::broadcast;
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
+
+/// Number of events kept for subscribers which have not received them yet.
+pub const CAPACITY: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// Price of every outcome of the market, by outcome index.
+    Prices {
+        market_id: i32,
+        prices: Vec<f64>,
+    },
+    Trade(trade::Model),
+    /// Market was created or changed status.
+    Market(market::Model),
+}
+
+impl Event {
+    pub fn market_id(&self) -> i32 {
+        match self {
+            Event::Prices { market_id, .. } => *market_id,
+            Event::Trade(trade) => trade.market_id,
+            Event::Market(market) => market.id,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Events(broadcast::Sender<Event>);
+
+impl Default for Events {
+    fn default() -> Self {
+        Events(broadcast::channel(CAPACITY).0)
+    }
+}
+
+impl Events {
+    /// Send the event to every subscriber. Only ever called once the change
+    /// is committed.
+    pub fn publish(&self, event: Event) {
+        // Having no subscriber is not an error.
+        let _ = self.0.send(event);
+    }
+
+    /// Events published from now on, of the market if one is given.
+    pub fn subscribe(&self, market_id: Option<i32>) -> impl Stream<Item = Event> {
+        BroadcastStream::new(self.0.subscribe()).filter_map(move |event| {
+            // A lagging subscriber gets an error in place of the events it
+            // missed, and carries on with the next ones.
+            event
+                .ok()
+                .filter(|event| market_id.is_none() || market_id == Some(event.market_id()))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_stream::StreamExt;
+
+    use super::{Event, Events, CAPACITY};
+
+    fn prices(market_id: i32, price: f64) -> Event {
+        Event::Prices {
+            market_id,
+            prices: vec![price, 1. - price],
+        }
+    }
+
+    #[tokio::test]
+    async fn slow_subscribers_must_skip_events_instead_of_blocking() {
+        let events = Events::default();
+        let mut all = Box::pin(events.subscribe(None));
+        let mut second = Box::pin(events.subscribe(Some(2)));
+        for _ in 0..CAPACITY {
+            events.publish(prices(1, 0.5));
+        }
+        events.publish(prices(2, 0.25));
+        events.publish(prices(1, 0.75));
+
+        assert_eq!(second.next().await, Some(prices(2, 0.25)));
+        // The first event was dropped from the channel, without waiting for
+        // the subscriber to receive it.
+        let mut received = 0;
+        while let Some(event) = all.next().await {
+            received += 1;
+            if event == prices(1, 0.75) {
+                break;
+            }
+        }
+        assert_eq!(received, CAPACITY);
+    }
+}
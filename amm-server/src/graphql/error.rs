@@ -0,0 +1,265 @@
+//! Failures of mutations, as union members with a machine readable `code`
+//! and a human readable `message`.
+//!
+//! Only failures the client can act on are part of the unions. Database
+//! errors and the like are returned as regular GraphQL errors.
+
+use amm::{
+    cfmm::Error as CFMMError,
+    cost_function::{AMMError, PurchaseError},
+    lifecycle, outcomes, repository,
+};
+use async_graphql::{Enum, InputType, SimpleObject, Union};
+
+use crate::service;
+
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum AmmErrorCode {
+    OutcomeLessThanTwo,
+    BogusLiquidityParam,
+    BogusFeeParam,
+    NoLiquidity,
+    InsufficientShares,
+}
+
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+#[graphql(remote = "amm::cost_function::PurchaseError")]
+pub enum PurchaseErrorCode {
+    NegativePurchase,
+    NonNormalPurchase,
+    TooSmall,
+    WrongPurchaseLength,
+    CannotPurchaseWithSameAsset,
+    UnknownOutcome,
+    InsufficientLiquidity,
+    SlippageExceeded,
+}
+
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum PoolErrorCode {
+    InvalidAssetCount,
+    UnknownAssetId,
+    InvalidOrderAmount,
+    InsufficientLiquidity,
+    NoRouteFound,
+    SlippageExceeded,
+    InvalidTimestamp,
+    ObservationTooOld,
+    BatchNotReady,
+    ClearingPriceNotFound,
+    InvalidFeeParam,
+    InvalidCurveParam,
+    PayoffNotReplicable,
+    InvalidTradingFunction,
+    RootNotFound,
+    UnknownOrder,
+    InvalidAmount,
+}
+
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MarketErrorCode {
+    NotFound,
+    NoMarketMaker,
+    /// Market was updated concurrently too many times, the mutation can be
+    /// retried.
+    StaleState,
+    NotOpen,
+    /// Status of the market does not allow the mutation.
+    WrongStatus,
+    IllegalTransition,
+    UnsupportedMarketMaker,
+    InsufficientPosition,
+    InvalidHex,
+}
+
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+pub enum OutcomeErrorCode {
+    OutcomeLessThanTwo,
+    WrongValueKind,
+    OverlappingOutcomes,
+    UncoveredOracleValue,
+    UnknownOracleValue,
+    InvalidRange,
+    InvalidDigits,
+    NoMatchingOutcome,
+    UnknownDecision,
+    InvalidStoredOutcome,
+    InvalidAttestation,
+    InvalidSignature,
+    NotResolving,
+}
+
+/// Market maker rejected its parameters or the operation.
+#[derive(SimpleObject, Clone, Debug, PartialEq)]
+pub struct AmmFailure {
+    pub code: AmmErrorCode,
+    pub message: String,
+}
+
+/// Market maker rejected the trade.
+#[derive(SimpleObject, Clone, Debug, PartialEq)]
+pub struct PurchaseFailure {
+    pub code: PurchaseErrorCode,
+    pub message: String,
+}
+
+/// Pool of a constant function market maker rejected the operation.
+#[derive(SimpleObject, Clone, Debug, PartialEq)]
+pub struct PoolFailure {
+    pub code: PoolErrorCode,
+    pub message: String,
+}
+
+/// Market is missing, or not in a state which allows the operation.
+#[derive(SimpleObject, Clone, Debug, PartialEq)]
+pub struct MarketFailure {
+    pub code: MarketErrorCode,
+    pub message: String,
+}
+
+/// Outcomes do not match what the oracle announced or attested.
+#[derive(SimpleObject, Clone, Debug, PartialEq)]
+pub struct OutcomeFailure {
+    pub code: OutcomeErrorCode,
+    pub message: String,
+}
+
+#[derive(Union, Clone, Debug, PartialEq)]
+pub enum Failure {
+    Amm(AmmFailure),
+    Purchase(PurchaseFailure),
+    Pool(PoolFailure),
+    Market(MarketFailure),
+    Outcome(OutcomeFailure),
+}
+
+impl Failure {
+    /// `code` of the failure, as it is serialized in the API. The REST API
+    /// uses the same codes.
+    pub fn code(&self) -> String {
+        let code = match self {
+            Failure::Amm(failure) => failure.code.to_value(),
+            Failure::Purchase(failure) => failure.code.to_value(),
+            Failure::Pool(failure) => failure.code.to_value(),
+            Failure::Market(failure) => failure.code.to_value(),
+            Failure::Outcome(failure) => failure.code.to_value(),
+        };
+        code.to_string()
+    }
+}
+
+fn amm(err: AMMError) -> Failure {
+    let code = match err {
+        AMMError::OutcomeLessThanTwo => AmmErrorCode::OutcomeLessThanTwo,
+        AMMError::BogusLiquidityParam => AmmErrorCode::BogusLiquidityParam,
+        AMMError::BogusFeeParam => AmmErrorCode::BogusFeeParam,
+        AMMError::NoLiquidity => AmmErrorCode::NoLiquidity,
+        AMMError::InsufficientShares => AmmErrorCode::InsufficientShares,
+        AMMError::PurchaseError(err) => return purchase(err),
+        AMMError::FundingError(err) => return pool(err),
+    };
+    Failure::Amm(AmmFailure {
+        code,
+        message: err.to_string(),
+    })
+}
+
+fn purchase(err: PurchaseError) -> Failure {
+    Failure::Purchase(PurchaseFailure {
+        message: err.to_string(),
+        code: err.into(),
+    })
+}
+
+fn pool(err: CFMMError) -> Failure {
+    let code = match err {
+        CFMMError::InvalidAssetCount => PoolErrorCode::InvalidAssetCount,
+        CFMMError::UnknownAssetId => PoolErrorCode::UnknownAssetId,
+        CFMMError::InvalidOrderAmount => PoolErrorCode::InvalidOrderAmount,
+        CFMMError::InsufficientLiquidity => PoolErrorCode::InsufficientLiquidity,
+        CFMMError::NoRouteFound => PoolErrorCode::NoRouteFound,
+        CFMMError::SlippageExceeded => PoolErrorCode::SlippageExceeded,
+        CFMMError::InvalidTimestamp => PoolErrorCode::InvalidTimestamp,
+        CFMMError::ObservationTooOld => PoolErrorCode::ObservationTooOld,
+        CFMMError::BatchNotReady => PoolErrorCode::BatchNotReady,
+        CFMMError::ClearingPriceNotFound => PoolErrorCode::ClearingPriceNotFound,
+        CFMMError::InvalidFeeParam => PoolErrorCode::InvalidFeeParam,
+        CFMMError::InvalidCurveParam => PoolErrorCode::InvalidCurveParam,
+        CFMMError::PayoffNotReplicable => PoolErrorCode::PayoffNotReplicable,
+        CFMMError::InvalidTradingFunction => PoolErrorCode::InvalidTradingFunction,
+        CFMMError::RootNotFound => PoolErrorCode::RootNotFound,
+        CFMMError::UnknownOrder => PoolErrorCode::UnknownOrder,
+        CFMMError::Amount(_) => PoolErrorCode::InvalidAmount,
+    };
+    Failure::Pool(PoolFailure {
+        code,
+        message: err.to_string(),
+    })
+}
+
+fn market(code: MarketErrorCode, message: String) -> Failure {
+    Failure::Market(MarketFailure { code, message })
+}
+
+fn outcome(code: OutcomeErrorCode, message: String) -> Failure {
+    Failure::Outcome(OutcomeFailure { code, message })
+}
+
+/// Split the error of a service into the failure to return to the client,
+/// or the error to return as a GraphQL error.
+pub fn failure(err: service::Error) -> Result<Failure, async_graphql::Error> {
+    let message = err.to_string();
+    let failure = match err {
+        service::Error::Amm(err) => amm(err),
+        service::Error::Purchase(err) => purchase(err),
+        service::Error::Pool(err) => pool(err),
+        service::Error::Repository(err) => match err {
+            repository::Error::NotFound => market(MarketErrorCode::NotFound, message),
+            repository::Error::NoMarketMaker => market(MarketErrorCode::NoMarketMaker, message),
+            repository::Error::StaleState => market(MarketErrorCode::StaleState, message),
+            repository::Error::NotOpen => market(MarketErrorCode::NotOpen, message),
+            repository::Error::WrongStatus => market(MarketErrorCode::WrongStatus, message),
+            repository::Error::Db(_) | repository::Error::Serialization(_) => {
+                return Err(message.into())
+            }
+        },
+        service::Error::Lifecycle(err) => match err {
+            lifecycle::Error::NotFound => market(MarketErrorCode::NotFound, message),
+            lifecycle::Error::IllegalTransition => {
+                market(MarketErrorCode::IllegalTransition, message)
+            }
+            lifecycle::Error::StaleState => market(MarketErrorCode::StaleState, message),
+            lifecycle::Error::Db(_) => return Err(message.into()),
+        },
+        service::Error::Outcomes(err) => {
+            let code = match err {
+                outcomes::Error::OutcomeLessThanTwo => OutcomeErrorCode::OutcomeLessThanTwo,
+                outcomes::Error::WrongValueKind => OutcomeErrorCode::WrongValueKind,
+                outcomes::Error::OverlappingOutcomes => OutcomeErrorCode::OverlappingOutcomes,
+                outcomes::Error::UncoveredOracleValue => OutcomeErrorCode::UncoveredOracleValue,
+                outcomes::Error::UnknownOracleValue => OutcomeErrorCode::UnknownOracleValue,
+                outcomes::Error::InvalidRange => OutcomeErrorCode::InvalidRange,
+                outcomes::Error::InvalidDigits => OutcomeErrorCode::InvalidDigits,
+                outcomes::Error::NoMatchingOutcome => OutcomeErrorCode::NoMatchingOutcome,
+                outcomes::Error::UnknownDecision => OutcomeErrorCode::UnknownDecision,
+                outcomes::Error::InvalidStoredOutcome => OutcomeErrorCode::InvalidStoredOutcome,
+                outcomes::Error::InvalidAttestation => OutcomeErrorCode::InvalidAttestation,
+                outcomes::Error::InvalidSignature => OutcomeErrorCode::InvalidSignature,
+                outcomes::Error::NotResolving => OutcomeErrorCode::NotResolving,
+                outcomes::Error::Db(_) => return Err(message.into()),
+            };
+            outcome(code, message)
+        }
+        service::Error::UnsupportedMarketMaker => {
+            market(MarketErrorCode::UnsupportedMarketMaker, message)
+        }
+        service::Error::InsufficientPosition => {
+            market(MarketErrorCode::InsufficientPosition, message)
+        }
+        service::Error::InvalidHex => market(MarketErrorCode::InvalidHex, message),
+        service::Error::Unauthorized | service::Error::History(_) | service::Error::Db(_) => {
+            return Err(message.into())
+        }
+    };
+    Ok(failure)
+}
@@ -0,0 +1,70 @@
+//! Helpers of the tests of the GraphQL API.
+
+use async_graphql::Request;
+use bitcoin::{
+    hashes::hex::ToHex,
+    secp256k1::{KeyPair, Secp256k1, XOnlyPublicKey},
+};
+use serde_json::Value;
+
+use super::AppSchema;
+use crate::auth::Caller;
+
+/// Execute the query as the caller, it must not fail.
+pub async fn execute_as(schema: &AppSchema, caller: Caller, query: &str) -> Value {
+    let response = schema.execute(Request::new(query).data(caller)).await;
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    response.data.into_json().unwrap()
+}
+
+/// Execute the query as the operator.
+pub async fn execute(schema: &AppSchema, query: &str) -> Value {
+    execute_as(schema, Caller::Operator, query).await
+}
+
+/// Oracle of the decisions of `create_market`.
+pub fn oracle() -> KeyPair {
+    KeyPair::from_seckey_slice(&Secp256k1::new(), &[1; 32]).unwrap()
+}
+
+/// Create a yes/no market, `market_maker` are the fields of its market
+/// maker, e.g. `kind: LMSR, liquidity: 10`. Returns the `MarketCreated`.
+pub async fn create_market(schema: &AppSchema, market_maker: &str) -> Value {
+    let created = execute(
+        schema,
+        &r#"mutation {
+            createMarket(input: {
+                name: "m", MARKET_MAKER,
+                decision: {
+                    name: "d", oraclePublicKey: "ORACLE",
+                    announced: { enumerated: ["yes", "no"] },
+                    outcomes: [
+                        { label: "Yes", value: { exact: "yes" } },
+                        { label: "No", value: { exact: "no" } }
+                    ]
+                }
+            }) {
+                __typename
+                ... on MarketCreated { market { id status } decision { id } }
+            }
+        }"#
+        .replace("MARKET_MAKER", market_maker)
+        .replace(
+            "ORACLE",
+            &XOnlyPublicKey::from_keypair(&oracle())
+                .0
+                .serialize()
+                .to_hex(),
+        ),
+    )
+    .await;
+    created["createMarket"].clone()
+}
+
+/// Mutation which opens the market.
+pub fn open(market_id: &Value) -> String {
+    format!(
+        "mutation {{ transitionMarket(marketId: {}, status: OPEN) {{ __typename }} }}",
+        market_id
+    )
+}
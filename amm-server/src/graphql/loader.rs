@@ -0,0 +1,119 @@
+use std::{collections::HashMap, sync::Arc};
+
+use amm::entity::{decision, market, outcome};
+use async_graphql::dataloader::Loader;
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder};
+
+/// Loads related rows for every node of a list in a single query, instead of
+/// one query per node.
+pub struct DbLoader {
+    db: DatabaseConnection,
+}
+
+impl DbLoader {
+    pub fn new(db: DatabaseConnection) -> Self {
+        Self { db }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MarketById(pub i32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DecisionsOfMarket(pub i32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct OutcomeById(pub i32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct OutcomesOfDecision(pub i32);
+
+#[async_trait::async_trait]
+impl Loader<MarketById> for DbLoader {
+    type Value = market::Model;
+    type Error = Arc<DbErr>;
+
+    async fn load(
+        &self,
+        keys: &[MarketById],
+    ) -> Result<HashMap<MarketById, Self::Value>, Self::Error> {
+        let markets = market::Entity::find()
+            .filter(market::Column::Id.is_in(keys.iter().map(|key| key.0)))
+            .all(&self.db)
+            .await?;
+        Ok(markets
+            .into_iter()
+            .map(|market| (MarketById(market.id), market))
+            .collect())
+    }
+}
+
+#[async_trait::async_trait]
+impl Loader<DecisionsOfMarket> for DbLoader {
+    type Value = Vec<decision::Model>;
+    type Error = Arc<DbErr>;
+
+    async fn load(
+        &self,
+        keys: &[DecisionsOfMarket],
+    ) -> Result<HashMap<DecisionsOfMarket, Self::Value>, Self::Error> {
+        let decisions = decision::Entity::find()
+            .filter(decision::Column::MarketId.is_in(keys.iter().map(|key| key.0)))
+            .order_by_asc(decision::Column::Id)
+            .all(&self.db)
+            .await?;
+        let mut grouped: HashMap<_, Vec<_>> = keys.iter().map(|key| (*key, vec![])).collect();
+        for decision in decisions {
+            grouped
+                .entry(DecisionsOfMarket(decision.market_id))
+                .or_default()
+                .push(decision);
+        }
+        Ok(grouped)
+    }
+}
+
+#[async_trait::async_trait]
+impl Loader<OutcomeById> for DbLoader {
+    type Value = outcome::Model;
+    type Error = Arc<DbErr>;
+
+    async fn load(
+        &self,
+        keys: &[OutcomeById],
+    ) -> Result<HashMap<OutcomeById, Self::Value>, Self::Error> {
+        let outcomes = outcome::Entity::find()
+            .filter(outcome::Column::Id.is_in(keys.iter().map(|key| key.0)))
+            .all(&self.db)
+            .await?;
+        Ok(outcomes
+            .into_iter()
+            .map(|outcome| (OutcomeById(outcome.id), outcome))
+            .collect())
+    }
+}
+
+#[async_trait::async_trait]
+impl Loader<OutcomesOfDecision> for DbLoader {
+    type Value = Vec<outcome::Model>;
+    type Error = Arc<DbErr>;
+
+    async fn load(
+        &self,
+        keys: &[OutcomesOfDecision],
+    ) -> Result<HashMap<OutcomesOfDecision, Self::Value>, Self::Error> {
+        let outcomes = outcome::Entity::find()
+            .filter(outcome::Column::DecisionId.is_in(keys.iter().map(|key| key.0)))
+            .order_by_asc(outcome::Column::Index)
+            .all(&self.db)
+            .await?;
+        let mut grouped: HashMap<_, Vec<_>> = keys.iter().map(|key| (*key, vec![])).collect();
+        for outcome in outcomes {
+            grouped
+                .entry(OutcomesOfDecision(outcome.decision_id))
+                .or_default()
+                .push(outcome);
+        }
+        Ok(grouped)
+    }
+}
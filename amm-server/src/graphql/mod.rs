@@ -0,0 +1,144 @@
+//! GraphQL API.
+//!
+//! Lists are Relay cursor connections, see `pagination`. Relations of the
+//! nodes go through `loader::DbLoader`, so a page of nodes costs one query per
+//! relation rather than one query per node.
+//!
+//! Every mutation returns a union of its result and the failures listed in
+//! `error`, so clients can branch on `__typename` and `code`.
+//!
+//! Subscriptions push the `Events` published by the mutations, and are served
+//! over WebSocket.
+
+pub mod error;
+#[cfg(test)]
+mod fixtures;
+mod loader;
+mod mutation;
+mod pagination;
+mod query;
+mod subscription;
+mod types;
+
+use async_graphql::{dataloader::DataLoader, Schema};
+use sea_orm::DatabaseConnection;
+
+use self::loader::DbLoader;
+pub use self::{mutation::Mutation, query::Query, subscription::Subscription};
+use crate::events::Events;
+
+pub type AppSchema = Schema<Query, Mutation, Subscription>;
+
+pub fn build_schema(db: DatabaseConnection, events: Events) -> AppSchema {
+    Schema::build(Query, Mutation, Subscription)
+        .data(DataLoader::new(DbLoader::new(db.clone()), tokio::spawn))
+        .data(db)
+        .data(events)
+        .finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use amm::{
+        cost_function::lmsr::LMScoringRule,
+        entity::{market::Status, trade::Side},
+        history::{self, NewTrade},
+        lifecycle, outcomes,
+        repository::{self, MarketMakerState},
+    };
+    use migration::memory_db;
+    use serde_json::json;
+
+    use super::build_schema;
+    use crate::{events::Events, service::NewDecision};
+
+    #[tokio::test]
+    async fn markets_must_be_paginated_with_their_outcomes() {
+        let db = memory_db().await.unwrap();
+        let mut ids = vec![];
+        for name in ["first", "second"] {
+            let lmsr = LMScoringRule::try_create(2, 10.).unwrap();
+            let market = repository::create(&db, name.to_owned(), MarketMakerState::Lmsr(lmsr))
+                .await
+                .unwrap();
+            lifecycle::transition(&db, market.market_id, Status::Open, None, 0)
+                .await
+                .unwrap();
+            ids.push(market.market_id);
+        }
+        let decision = NewDecision::yes_no();
+        let (_, created) = outcomes::create_decision(
+            &db,
+            ids[0],
+            decision.name,
+            decision.oracle_public_key,
+            &decision.announced,
+            &decision.outcomes,
+        )
+        .await
+        .unwrap();
+        history::record_trade(
+            &db,
+            NewTrade {
+                market_id: ids[0],
+                outcome_id: created[0].id,
+                trader: "alice".to_owned(),
+                side: Side::Buy,
+                amount: 1.,
+                cost: 0.5,
+                prices: vec![0.5, 0.5],
+                created_at: 1,
+            },
+        )
+        .await
+        .unwrap();
+
+        let schema = build_schema(db, Events::default());
+        let response = schema
+            .execute(
+                r#"{
+                    markets(first: 1) {
+                        edges { node {
+                            name status prices
+                            quote(input: { purchaseVector: [1, 1] })
+                            decisions { oraclePublicKey outcomes { label } }
+                        } }
+                        pageInfo { hasNextPage endCursor }
+                    }
+                }"#,
+            )
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        let data = response.data.into_json().unwrap();
+        let market = &data["markets"]["edges"][0]["node"];
+        assert_eq!(market["name"], "first");
+        assert_eq!(market["status"], "OPEN");
+        assert_eq!(market["prices"], json!([0.5, 0.5]));
+        // Buying one of each outcome always costs 1.
+        assert!((market["quote"].as_f64().unwrap() - 1.).abs() < 1e-9);
+        assert_eq!(market["decisions"][0]["oraclePublicKey"], "02");
+        assert_eq!(
+            market["decisions"][0]["outcomes"],
+            json!([{ "label": "yes" }, { "label": "no" }])
+        );
+        assert_eq!(data["markets"]["pageInfo"]["hasNextPage"], true);
+
+        let cursor = data["markets"]["pageInfo"]["endCursor"].as_str().unwrap();
+        let response = schema
+            .execute(format!(
+                r#"{{
+                    markets(after: "{}") {{ edges {{ node {{ name }} }} }}
+                    trades(marketId: {}) {{ edges {{ node {{ trader side outcome {{ label }} }} }} }}
+                }}"#,
+                cursor, ids[0]
+            ))
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        let data = response.data.into_json().unwrap();
+        assert_eq!(data["markets"]["edges"][0]["node"]["name"], "second");
+        assert_eq!(
+            data["trades"]["edges"][0]["node"],
+            json!({ "trader": "alice", "side": "BUY", "outcome": { "label": "yes" } })
+        );
+    }
+}
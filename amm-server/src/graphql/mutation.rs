@@ -0,0 +1,604 @@
+use amm::{
+    dto::{Purchase, Sell},
+    entity::trade::Side,
+    outcomes::{AnnouncedOutcomes, Attestation, OracleValue, OutcomeSpec},
+};
+use async_graphql::{
+    Context, Enum, Guard, InputObject, Object, OneofObject, Result, SimpleObject, Union,
+};
+use bitcoin::hashes::hex::FromHex;
+use sea_orm::DatabaseConnection;
+
+use super::{
+    error::{failure, Failure},
+    types::{Decision, Market, MarketStatus, Trade},
+};
+use crate::{
+    auth::Caller,
+    events::Events,
+    service::{self, NewDecision},
+};
+
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+#[graphql(remote = "crate::service::MarketMakerKind")]
+pub enum MarketMakerKind {
+    Lmsr,
+    LogSumExpLmsr,
+    LsLmsr,
+    Fpmm,
+}
+
+#[derive(InputObject)]
+pub struct CreateMarketInput {
+    pub name: String,
+    pub kind: MarketMakerKind,
+    /// Liquidity parameter `b` of an LMSR, or `alpha` of an LS-LMSR.
+    pub liquidity: Option<f64>,
+    /// Fee of an FPMM, in `[0, 1)`.
+    pub fee: Option<f64>,
+    pub decision: DecisionInput,
+}
+
+#[derive(InputObject)]
+pub struct DecisionInput {
+    pub name: String,
+    /// Hex encoded public key of the oracle.
+    pub oracle_public_key: String,
+    pub announced: AnnouncedOutcomesInput,
+    /// Outcomes in the order of their index.
+    pub outcomes: Vec<OutcomeInput>,
+}
+
+/// Values the oracle announced it will sign.
+#[derive(OneofObject)]
+pub enum AnnouncedOutcomesInput {
+    Enumerated(Vec<String>),
+    DigitDecomposition(DigitDecompositionInput),
+}
+
+#[derive(InputObject)]
+pub struct DigitDecompositionInput {
+    pub base: u32,
+    pub nb_digits: u32,
+    pub is_signed: bool,
+}
+
+#[derive(InputObject)]
+pub struct OutcomeInput {
+    pub label: String,
+    pub value: OracleValueInput,
+}
+
+#[derive(OneofObject)]
+pub enum OracleValueInput {
+    Exact(String),
+    Range(RangeInput),
+}
+
+/// Inclusive range of values.
+#[derive(InputObject)]
+pub struct RangeInput {
+    pub start: i64,
+    pub end: i64,
+}
+
+fn from_hex(value: &str) -> Result<Vec<u8>, service::Error> {
+    Vec::from_hex(value).map_err(|_| service::Error::InvalidHex)
+}
+
+impl TryFrom<DecisionInput> for NewDecision {
+    type Error = service::Error;
+
+    fn try_from(input: DecisionInput) -> Result<Self, Self::Error> {
+        let announced = match input.announced {
+            AnnouncedOutcomesInput::Enumerated(values) => AnnouncedOutcomes::Enumerated(values),
+            AnnouncedOutcomesInput::DigitDecomposition(digits) => {
+                AnnouncedOutcomes::digit_decomposition(
+                    digits.base,
+                    digits.nb_digits,
+                    digits.is_signed,
+                )?
+            }
+        };
+        let outcomes = input
+            .outcomes
+            .into_iter()
+            .map(|outcome| OutcomeSpec {
+                label: outcome.label,
+                value: match outcome.value {
+                    OracleValueInput::Exact(value) => OracleValue::Exact(value),
+                    OracleValueInput::Range(range) => OracleValue::Range {
+                        start: range.start,
+                        end: range.end,
+                    },
+                },
+            })
+            .collect();
+        Ok(NewDecision {
+            name: input.name,
+            oracle_public_key: from_hex(&input.oracle_public_key)?,
+            announced,
+            outcomes,
+        })
+    }
+}
+
+#[derive(SimpleObject)]
+pub struct MarketCreated {
+    pub market: Market,
+    pub decision: Decision,
+}
+
+#[derive(SimpleObject)]
+pub struct MarketUpdated {
+    pub market: Market,
+}
+
+#[derive(SimpleObject)]
+pub struct Traded {
+    /// One trade per outcome, in the order of their index.
+    pub trades: Vec<Trade>,
+    /// Collateral paid for a purchase, or received for a sale.
+    pub cost: f64,
+    /// Price of every outcome after the trade.
+    pub prices: Vec<f64>,
+}
+
+#[derive(SimpleObject)]
+pub struct LiquidityChanged {
+    /// Pool shares minted or burnt.
+    pub shares: f64,
+    /// Outcome tokens sent back to the liquidity provider.
+    pub outcome_tokens: Vec<f64>,
+    /// Collateral sent to the liquidity provider from accrued fees.
+    pub fees: f64,
+}
+
+#[derive(SimpleObject)]
+pub struct DecisionResolved {
+    pub winning_outcome_id: i32,
+    pub market: Market,
+}
+
+#[derive(Union)]
+pub enum CreateMarketResult {
+    MarketCreated(MarketCreated),
+    #[graphql(flatten)]
+    Failure(Failure),
+}
+
+#[derive(Union)]
+pub enum MarketResult {
+    MarketUpdated(MarketUpdated),
+    #[graphql(flatten)]
+    Failure(Failure),
+}
+
+#[derive(Union)]
+pub enum TradeResult {
+    Traded(Traded),
+    #[graphql(flatten)]
+    Failure(Failure),
+}
+
+#[derive(Union)]
+pub enum LiquidityResult {
+    LiquidityChanged(LiquidityChanged),
+    #[graphql(flatten)]
+    Failure(Failure),
+}
+
+#[derive(Union)]
+pub enum ResolveResult {
+    DecisionResolved(DecisionResolved),
+    #[graphql(flatten)]
+    Failure(Failure),
+}
+
+/// Turn the result of a service into the result union of the mutation.
+fn into_result<T, R>(result: Result<T, service::Error>, success: fn(T) -> R) -> Result<R>
+where
+    R: From<Failure>,
+{
+    match result {
+        Ok(value) => Ok(success(value)),
+        Err(err) => Ok(failure(err)?.into()),
+    }
+}
+
+fn traded(executed: service::Executed) -> TradeResult {
+    TradeResult::Traded(Traded {
+        trades: executed.trades.into_iter().map(Trade).collect(),
+        cost: executed.cost,
+        prices: executed.prices,
+    })
+}
+
+fn liquidity_changed(change: amm::cfmm::fpmm::LiquidityChange) -> LiquidityResult {
+    LiquidityResult::LiquidityChanged(LiquidityChanged {
+        shares: change.shares,
+        outcome_tokens: change.outcome_tokens,
+        fees: change.fees,
+    })
+}
+
+/// Lets only the operator run the mutation.
+struct OperatorGuard;
+
+#[async_trait::async_trait]
+impl Guard for OperatorGuard {
+    async fn check(&self, ctx: &Context<'_>) -> Result<()> {
+        Ok(Caller::operator(ctx.data_opt::<Caller>())?)
+    }
+}
+
+pub struct Mutation;
+
+#[Object]
+impl Mutation {
+    /// Create a market in `DRAFT` status, with its decision.
+    #[graphql(guard = "OperatorGuard")]
+    async fn create_market(
+        &self,
+        ctx: &Context<'_>,
+        input: CreateMarketInput,
+    ) -> Result<CreateMarketResult> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let events = ctx.data::<Events>()?;
+        let result = match NewDecision::try_from(input.decision) {
+            Ok(decision) => {
+                service::create_market(
+                    db,
+                    events,
+                    input.name,
+                    input.kind.into(),
+                    input.liquidity,
+                    input.fee,
+                    decision,
+                )
+                .await
+            }
+            Err(err) => Err(err),
+        };
+        into_result(result, |(market, decision)| {
+            CreateMarketResult::MarketCreated(MarketCreated {
+                market: Market(market),
+                decision: Decision(decision),
+            })
+        })
+    }
+
+    /// Move the market to another status, e.g. to open or halt trading.
+    #[graphql(guard = "OperatorGuard")]
+    async fn transition_market(
+        &self,
+        ctx: &Context<'_>,
+        market_id: i32,
+        status: MarketStatus,
+        reason: Option<String>,
+    ) -> Result<MarketResult> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let events = ctx.data::<Events>()?;
+        let result = service::transition(db, events, market_id, status.into(), reason).await;
+        into_result(result, |market| {
+            MarketResult::MarketUpdated(MarketUpdated {
+                market: Market(market),
+            })
+        })
+    }
+
+    /// Buy securities for the trader of the request.
+    async fn purchase(
+        &self,
+        ctx: &Context<'_>,
+        market_id: i32,
+        input: Purchase,
+    ) -> Result<TradeResult> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let events = ctx.data::<Events>()?;
+        let trader = Caller::trader(ctx.data_opt::<Caller>())?;
+        let result = service::trade(
+            db,
+            events,
+            market_id,
+            trader,
+            Side::Buy,
+            input.purchase_vector,
+        )
+        .await;
+        into_result(result, traded)
+    }
+
+    /// Sell securities held by the trader of the request.
+    async fn sell(&self, ctx: &Context<'_>, market_id: i32, input: Sell) -> Result<TradeResult> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let events = ctx.data::<Events>()?;
+        let trader = Caller::trader(ctx.data_opt::<Caller>())?;
+        let result =
+            service::trade(db, events, market_id, trader, Side::Sell, input.sell_vector).await;
+        into_result(result, traded)
+    }
+
+    /// Fund the pool of an FPMM market with `amount` collateral.
+    #[graphql(guard = "OperatorGuard")]
+    async fn add_liquidity(
+        &self,
+        ctx: &Context<'_>,
+        market_id: i32,
+        provider: String,
+        amount: f64,
+    ) -> Result<LiquidityResult> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let events = ctx.data::<Events>()?;
+        let result = service::add_liquidity(db, events, market_id, provider, amount).await;
+        into_result(result, liquidity_changed)
+    }
+
+    /// Burn pool shares of an FPMM market.
+    #[graphql(guard = "OperatorGuard")]
+    async fn remove_liquidity(
+        &self,
+        ctx: &Context<'_>,
+        market_id: i32,
+        provider: String,
+        shares: f64,
+    ) -> Result<LiquidityResult> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let events = ctx.data::<Events>()?;
+        let result = service::remove_liquidity(db, events, market_id, provider, shares).await;
+        into_result(result, liquidity_changed)
+    }
+
+    /// Resolve the decision with the attestation of its oracle, which decides
+    /// the winning outcome. The market must be `RESOLVING`, and becomes
+    /// `RESOLVED`.
+    async fn resolve_decision(
+        &self,
+        ctx: &Context<'_>,
+        decision_id: i32,
+        #[graphql(
+            desc = "Hex encoded attestation of the oracle, see `amm::outcomes::Attestation`."
+        )]
+        attestation: String,
+    ) -> Result<ResolveResult> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        let events = ctx.data::<Events>()?;
+        let attestation =
+            from_hex(&attestation).and_then(|bytes| Ok(Attestation::from_bytes(&bytes)?));
+        let result = match attestation {
+            Ok(attestation) => service::resolve(db, events, decision_id, attestation).await,
+            Err(err) => Err(err),
+        };
+        into_result(result, |(resolution, market)| {
+            ResolveResult::DecisionResolved(DecisionResolved {
+                winning_outcome_id: resolution.outcome_id,
+                market: Market(market),
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amm::outcomes::Attestation;
+    use async_graphql::Request;
+    use bitcoin::{
+        hashes::{hex::ToHex, sha256},
+        secp256k1::{Message, Secp256k1, XOnlyPublicKey},
+    };
+    use migration::memory_db;
+    use serde_json::{json, Value};
+
+    use crate::{
+        auth::Caller,
+        events::Events,
+        graphql::{
+            build_schema,
+            fixtures::{create_market, execute, execute_as, open, oracle},
+        },
+    };
+
+    fn alice() -> Caller {
+        Caller::Trader("alice".to_owned())
+    }
+
+    /// Hex encoded attestation of the outcome by the oracle.
+    fn attest(outcome: &str) -> String {
+        let message = Message::from_hashed_data::<sha256::Hash>(outcome.as_bytes());
+        Attestation {
+            oracle_public_key: XOnlyPublicKey::from_keypair(&oracle()).0,
+            signatures: vec![Secp256k1::new().sign_schnorr_no_aux_rand(&message, &oracle())],
+            outcomes: vec![outcome.to_owned()],
+        }
+        .to_bytes()
+        .unwrap()
+        .to_hex()
+    }
+
+    fn trade(market_id: &Value, mutation: &str, vector: &str) -> String {
+        format!(
+            r#"mutation {{
+                {}(marketId: {}, input: {}) {{
+                    __typename
+                    ... on Traded {{ cost prices trades {{ amount }} }}
+                    ... on PurchaseFailure {{ code }}
+                    ... on MarketFailure {{ code }}
+                }}
+            }}"#,
+            mutation, market_id, vector
+        )
+    }
+
+    #[tokio::test]
+    async fn mutations_must_return_typed_failures() {
+        let db = memory_db().await.unwrap();
+        let schema = build_schema(db, Events::default());
+
+        let created = create_market(&schema, "kind: LMSR, liquidity: 10").await;
+        let market = &created["market"];
+        assert_eq!(market["status"], "DRAFT");
+        let (market_id, decision_id) = (market["id"].clone(), created["decision"]["id"].clone());
+
+        let trade = |mutation: &str, vector: &str| trade(&market_id, mutation, vector);
+        let draft = execute_as(
+            &schema,
+            alice(),
+            &trade("purchase", "{ purchaseVector: [1, 0] }"),
+        )
+        .await;
+
+        // Only the operator manages markets, and only traders trade.
+        let anonymous = schema.execute(open(&market_id)).await;
+        assert_eq!(
+            anonymous.errors[0].message,
+            "Caller is not authorized for the operation"
+        );
+        let as_trader = schema
+            .execute(Request::new(open(&market_id)).data(alice()))
+            .await;
+        assert_eq!(as_trader.errors.len(), 1);
+        let as_operator = schema
+            .execute(
+                Request::new(trade("purchase", "{ purchaseVector: [1, 0] }"))
+                    .data(Caller::Operator),
+            )
+            .await;
+        assert_eq!(as_operator.errors.len(), 1);
+
+        assert_eq!(
+            draft["purchase"],
+            json!({ "__typename": "MarketFailure", "code": "NOT_OPEN" })
+        );
+
+        assert_eq!(
+            execute(&schema, &open(&market_id)).await["transitionMarket"]["__typename"],
+            "MarketUpdated"
+        );
+        let wrong = execute_as(
+            &schema,
+            alice(),
+            &trade("purchase", "{ purchaseVector: [1, 0, 0] }"),
+        )
+        .await;
+        assert_eq!(wrong["purchase"]["code"], "WRONG_PURCHASE_LENGTH");
+        let bought = execute_as(
+            &schema,
+            alice(),
+            &trade("purchase", "{ purchaseVector: [2, 0] }"),
+        )
+        .await;
+        assert_eq!(bought["purchase"]["__typename"], "Traded");
+        assert_eq!(bought["purchase"]["trades"], json!([{ "amount": 2.0 }]));
+        let oversold = execute_as(&schema, alice(), &trade("sell", "{ sellVector: [3, 0] }")).await;
+        assert_eq!(oversold["sell"]["code"], "INSUFFICIENT_POSITION");
+        let sold = execute_as(&schema, alice(), &trade("sell", "{ sellVector: [2, 0] }")).await;
+        let (paid, received) = (
+            bought["purchase"]["cost"].as_f64().unwrap(),
+            sold["sell"]["cost"].as_f64().unwrap(),
+        );
+        assert!((paid - received).abs() < 1e-9);
+
+        let liquidity = format!(
+            r#"mutation {{ addLiquidity(marketId: {}, provider: "lp", amount: 1) {{
+                __typename ... on MarketFailure {{ code }}
+            }} }}"#,
+            market_id
+        );
+        assert_eq!(
+            execute(&schema, &liquidity).await["addLiquidity"]["code"],
+            "UNSUPPORTED_MARKET_MAKER"
+        );
+
+        for status in ["CLOSED", "RESOLVING"] {
+            execute(
+                &schema,
+                &format!(
+                    "mutation {{ transitionMarket(marketId: {}, status: {}) {{ __typename }} }}",
+                    market_id, status
+                ),
+            )
+            .await;
+        }
+        let resolve = |value: &str| {
+            format!(
+                r#"mutation {{
+                    resolveDecision(decisionId: {}, attestation: "{}") {{
+                        __typename
+                        ... on DecisionResolved {{ market {{ status }} }}
+                        ... on OutcomeFailure {{ code }}
+                    }}
+                }}"#,
+                decision_id,
+                attest(value)
+            )
+        };
+        let unknown = execute(&schema, &resolve("maybe")).await;
+        assert_eq!(unknown["resolveDecision"]["code"], "NO_MATCHING_OUTCOME");
+        let resolved = execute(&schema, &resolve("yes")).await;
+        assert_eq!(resolved["resolveDecision"]["market"]["status"], "RESOLVED");
+    }
+
+    #[tokio::test]
+    async fn funded_fixed_product_markets_must_trade() {
+        let db = memory_db().await.unwrap();
+        let schema = build_schema(db, Events::default());
+
+        let created = create_market(&schema, "kind: FPMM, fee: 0.02").await;
+        let market_id = created["market"]["id"].clone();
+        let add_liquidity = format!(
+            r#"mutation {{ addLiquidity(marketId: {}, provider: "lp", amount: 100) {{
+                __typename
+                ... on LiquidityChanged {{ shares }}
+                ... on MarketFailure {{ code }}
+            }} }}"#,
+            market_id
+        );
+        let funded = execute(&schema, &add_liquidity).await;
+        assert_eq!(funded["addLiquidity"]["shares"], 100.0);
+        execute(&schema, &open(&market_id)).await;
+        let quoted = execute(
+            &schema,
+            &format!(
+                "{{ market(id: {}) {{ quote(input: {{ purchaseVector: [10, 0] }}) }} }}",
+                market_id
+            ),
+        )
+        .await;
+
+        let bought = execute_as(
+            &schema,
+            alice(),
+            &trade(&market_id, "purchase", "{ purchaseVector: [10, 0] }"),
+        )
+        .await;
+        assert_eq!(bought["purchase"]["trades"], json!([{ "amount": 10.0 }]));
+        assert_eq!(quoted["market"]["quote"], bought["purchase"]["cost"]);
+        let prices = &bought["purchase"]["prices"];
+        assert!(prices[0].as_f64().unwrap() > 0.5);
+        let sold = execute_as(
+            &schema,
+            alice(),
+            &trade(&market_id, "sell", "{ sellVector: [10, 0] }"),
+        )
+        .await;
+        assert_eq!(sold["sell"]["__typename"], "Traded");
+        let (paid, received) = (
+            bought["purchase"]["cost"].as_f64().unwrap(),
+            sold["sell"]["cost"].as_f64().unwrap(),
+        );
+        assert!(0. < received && received < paid);
+
+        execute(
+            &schema,
+            &format!(
+                "mutation {{ transitionMarket(marketId: {}, status: HALTED) {{ __typename }} }}",
+                market_id
+            ),
+        )
+        .await;
+        assert_eq!(
+            execute(&schema, &add_liquidity).await["addLiquidity"]["code"],
+            "WRONG_STATUS"
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use async_graphql::{
+    connection::{query, Connection, Edge},
+    Error, OutputType, Result,
+};
+use sea_orm::{
+    ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, QuerySelect, Select,
+};
+
+/// Number of nodes in a page when neither `first` nor `last` is given.
+pub const DEFAULT_PAGE_SIZE: usize = 20;
+/// Largest page a client can request.
+pub const MAX_PAGE_SIZE: usize = 100;
+
+/// Page through `select` in the order of its integer primary key `id`, which
+/// is also the cursor of each edge.
+///
+/// `first`/`after` page forwards and `last`/`before` page backwards, as in
+/// the Relay cursor connections specification. When both `first` and `last`
+/// are given, `first` wins.
+#[allow(clippy::too_many_arguments)]
+pub async fn paginate<E, N>(
+    db: &DatabaseConnection,
+    select: Select<E>,
+    id: E::Column,
+    id_of: fn(&E::Model) -> i32,
+    node: fn(E::Model) -> N,
+    after: Option<String>,
+    before: Option<String>,
+    first: Option<i32>,
+    last: Option<i32>,
+) -> Result<Connection<i32, N>>
+where
+    E: EntityTrait,
+    N: OutputType,
+{
+    query(
+        after,
+        before,
+        first,
+        last,
+        |after: Option<i32>, before: Option<i32>, first, last| async move {
+            let mut select = select;
+            if let Some(after) = after {
+                select = select.filter(id.gt(after));
+            }
+            if let Some(before) = before {
+                select = select.filter(id.lt(before));
+            }
+            // One more row than requested tells whether there is another page.
+            let (models, has_previous, has_next) = match (first, last) {
+                (None, Some(last)) => {
+                    let limit = last.min(MAX_PAGE_SIZE);
+                    let mut models = select
+                        .order_by_desc(id)
+                        .limit(limit as u64 + 1)
+                        .all(db)
+                        .await?;
+                    let has_previous = models.len() > limit;
+                    models.truncate(limit);
+                    models.reverse();
+                    (models, has_previous, before.is_some())
+                }
+                (first, _) => {
+                    let limit = first.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE);
+                    let mut models = select
+                        .order_by_asc(id)
+                        .limit(limit as u64 + 1)
+                        .all(db)
+                        .await?;
+                    let has_next = models.len() > limit;
+                    models.truncate(limit);
+                    (models, after.is_some(), has_next)
+                }
+            };
+            let mut connection = Connection::new(has_previous, has_next);
+            connection.edges.extend(
+                models
+                    .into_iter()
+                    .map(|model| Edge::new(id_of(&model), node(model))),
+            );
+            Ok::<_, Error>(connection)
+        },
+    )
+    .await
+}
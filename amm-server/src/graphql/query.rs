@@ -0,0 +1,145 @@
+use amm::entity::{decision, market, position, trade};
+use async_graphql::{connection::Connection, dataloader::DataLoader, Context, Object, Result};
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+
+use super::{
+    loader::{DbLoader, MarketById, OutcomeById},
+    pagination::paginate,
+    types::{Decision, Market, MarketStatus, Outcome, Position, Trade},
+};
+
+pub struct Query;
+
+#[Object]
+impl Query {
+    async fn market(&self, ctx: &Context<'_>, id: i32) -> Result<Option<Market>> {
+        let loader = ctx.data::<DataLoader<DbLoader>>()?;
+        Ok(loader.load_one(MarketById(id)).await?.map(Market))
+    }
+
+    async fn markets(
+        &self,
+        ctx: &Context<'_>,
+        status: Option<MarketStatus>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<Connection<i32, Market>> {
+        let mut select = market::Entity::find();
+        if let Some(status) = status {
+            select = select.filter(market::Column::Status.eq(market::Status::from(status)));
+        }
+        paginate(
+            ctx.data::<DatabaseConnection>()?,
+            select,
+            market::Column::Id,
+            |market| market.id,
+            Market,
+            after,
+            before,
+            first,
+            last,
+        )
+        .await
+    }
+
+    async fn decision(&self, ctx: &Context<'_>, id: i32) -> Result<Option<Decision>> {
+        let db = ctx.data::<DatabaseConnection>()?;
+        Ok(decision::Entity::find_by_id(id)
+            .one(db)
+            .await?
+            .map(Decision))
+    }
+
+    async fn decisions(
+        &self,
+        ctx: &Context<'_>,
+        market_id: Option<i32>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<Connection<i32, Decision>> {
+        let mut select = decision::Entity::find();
+        if let Some(market_id) = market_id {
+            select = select.filter(decision::Column::MarketId.eq(market_id));
+        }
+        paginate(
+            ctx.data::<DatabaseConnection>()?,
+            select,
+            decision::Column::Id,
+            |decision| decision.id,
+            Decision,
+            after,
+            before,
+            first,
+            last,
+        )
+        .await
+    }
+
+    async fn outcome(&self, ctx: &Context<'_>, id: i32) -> Result<Option<Outcome>> {
+        let loader = ctx.data::<DataLoader<DbLoader>>()?;
+        Ok(loader.load_one(OutcomeById(id)).await?.map(Outcome))
+    }
+
+    /// Trades of the market, oldest first.
+    #[allow(clippy::too_many_arguments)]
+    async fn trades(
+        &self,
+        ctx: &Context<'_>,
+        market_id: i32,
+        trader: Option<String>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<Connection<i32, Trade>> {
+        let mut select = trade::Entity::find().filter(trade::Column::MarketId.eq(market_id));
+        if let Some(trader) = trader {
+            select = select.filter(trade::Column::Trader.eq(trader));
+        }
+        paginate(
+            ctx.data::<DatabaseConnection>()?,
+            select,
+            trade::Column::Id,
+            |trade| trade.id,
+            Trade,
+            after,
+            before,
+            first,
+            last,
+        )
+        .await
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn positions(
+        &self,
+        ctx: &Context<'_>,
+        trader: String,
+        market_id: Option<i32>,
+        after: Option<String>,
+        before: Option<String>,
+        first: Option<i32>,
+        last: Option<i32>,
+    ) -> Result<Connection<i32, Position>> {
+        let mut select = position::Entity::find().filter(position::Column::Trader.eq(trader));
+        if let Some(market_id) = market_id {
+            select = select.filter(position::Column::MarketId.eq(market_id));
+        }
+        paginate(
+            ctx.data::<DatabaseConnection>()?,
+            select,
+            position::Column::Id,
+            |position| position.id,
+            Position,
+            after,
+            before,
+            first,
+            last,
+        )
+        .await
+    }
+}
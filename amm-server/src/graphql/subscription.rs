@@ -0,0 +1,173 @@
+use async_graphql::{Context, Result, SimpleObject, Subscription};
+use tokio_stream::{Stream, StreamExt};
+
+use super::types::{Market, Trade};
+use crate::events::{Event, Events};
+
+/// Price of every outcome of a market, by outcome index.
+#[derive(SimpleObject, Clone, Debug, PartialEq)]
+pub struct PriceUpdate {
+    pub market_id: i32,
+    pub prices: Vec<f64>,
+}
+
+pub struct Subscription;
+
+/// Every subscription is of all markets unless `marketId` is given. A client
+/// which does not keep up misses the oldest events rather than slowing trades
+/// down.
+#[Subscription]
+impl Subscription {
+    /// Prices after every trade, and every change of the liquidity of an
+    /// FPMM.
+    async fn prices(
+        &self,
+        ctx: &Context<'_>,
+        market_id: Option<i32>,
+    ) -> Result<impl Stream<Item = PriceUpdate>> {
+        let events = ctx.data::<Events>()?;
+        Ok(events.subscribe(market_id).filter_map(|event| match event {
+            Event::Prices { market_id, prices } => Some(PriceUpdate { market_id, prices }),
+            _ => None,
+        }))
+    }
+
+    /// Trades as they are executed, one per outcome traded.
+    async fn trades(
+        &self,
+        ctx: &Context<'_>,
+        market_id: Option<i32>,
+    ) -> Result<impl Stream<Item = Trade>> {
+        let events = ctx.data::<Events>()?;
+        Ok(events.subscribe(market_id).filter_map(|event| match event {
+            Event::Trade(trade) => Some(Trade(trade)),
+            _ => None,
+        }))
+    }
+
+    /// Markets as they are created or change status.
+    async fn markets(
+        &self,
+        ctx: &Context<'_>,
+        market_id: Option<i32>,
+    ) -> Result<impl Stream<Item = Market>> {
+        let events = ctx.data::<Events>()?;
+        Ok(events.subscribe(market_id).filter_map(|event| match event {
+            Event::Market(market) => Some(Market(market)),
+            _ => None,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use migration::memory_db;
+    use serde_json::json;
+    use tokio_stream::StreamExt;
+
+    use crate::{
+        auth::Caller,
+        events::Events,
+        graphql::{
+            build_schema,
+            fixtures::{create_market, execute, execute_as, open},
+        },
+    };
+
+    #[tokio::test]
+    async fn subscribers_must_receive_the_events_of_their_market() {
+        let db = memory_db().await.unwrap();
+        let schema = build_schema(db, Events::default());
+
+        let mut ids = vec![];
+        for _ in 0..2 {
+            let created = create_market(&schema, "kind: LMSR, liquidity: 10").await;
+            ids.push(created["market"]["id"].clone());
+        }
+
+        let mut prices = schema.execute_stream(format!(
+            "subscription {{ prices(marketId: {}) {{ marketId prices }} }}",
+            ids[1]
+        ));
+        let mut trades = schema.execute_stream("subscription { trades { marketId trader } }");
+        let mut markets = schema.execute_stream(format!(
+            "subscription {{ markets(marketId: {}) {{ status }} }}",
+            ids[1]
+        ));
+        // Subscriptions only start listening once polled.
+        for stream in [&mut prices, &mut trades, &mut markets] {
+            assert!(tokio::time::timeout(Duration::ZERO, stream.next())
+                .await
+                .is_err());
+        }
+
+        for id in &ids {
+            execute(&schema, &open(id)).await;
+            execute_as(
+                &schema,
+                Caller::Trader("alice".to_owned()),
+                &format!(
+                    "mutation {{
+                        purchase(marketId: {}, input: {{ purchaseVector: [1, 0] }}) {{ __typename }}
+                    }}",
+                    id
+                ),
+            )
+            .await;
+        }
+
+        let next = |response: Option<async_graphql::Response>| {
+            let response = response.unwrap();
+            assert!(response.errors.is_empty(), "{:?}", response.errors);
+            response.data.into_json().unwrap()
+        };
+        assert_eq!(
+            next(markets.next().await),
+            json!({ "markets": { "status": "OPEN" } })
+        );
+        let update = next(prices.next().await);
+        assert_eq!(update["prices"]["marketId"], ids[1]);
+        assert!(update["prices"]["prices"][0].as_f64().unwrap() > 0.5);
+        for id in &ids {
+            assert_eq!(
+                next(trades.next().await),
+                json!({ "trades": { "marketId": id, "trader": "alice" } })
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn prices_must_be_published_after_liquidity_changes() {
+        let db = memory_db().await.unwrap();
+        let schema = build_schema(db, Events::default());
+        let created = create_market(&schema, "kind: FPMM, fee: 0.02").await;
+        let market_id = created["market"]["id"].clone();
+
+        let mut prices = schema.execute_stream(format!(
+            "subscription {{ prices(marketId: {}) {{ prices }} }}",
+            market_id
+        ));
+        assert!(tokio::time::timeout(Duration::ZERO, prices.next())
+            .await
+            .is_err());
+        execute(
+            &schema,
+            &format!(
+                r#"mutation {{
+                    addLiquidity(marketId: {}, provider: "lp", amount: 100) {{ __typename }}
+                }}"#,
+                market_id
+            ),
+        )
+        .await;
+
+        let response = prices.next().await.unwrap();
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            response.data.into_json().unwrap(),
+            json!({ "prices": { "prices": [0.5, 0.5] } })
+        );
+    }
+}
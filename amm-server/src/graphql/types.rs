@@ -0,0 +1,231 @@
+use amm::{
+    dto::GetPriceForPurchase,
+    entity::{decision, market, outcome, position, trade},
+    repository::{self, MarketMakerState},
+};
+use async_graphql::{dataloader::DataLoader, Context, Enum, Object, Result};
+use bitcoin::hashes::hex::ToHex;
+
+use super::loader::{DbLoader, DecisionsOfMarket, MarketById, OutcomeById, OutcomesOfDecision};
+use crate::service;
+
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+#[graphql(remote = "amm::entity::market::Status")]
+pub enum MarketStatus {
+    Draft,
+    Open,
+    Halted,
+    Closed,
+    Resolving,
+    Resolved,
+    Cancelled,
+}
+
+#[derive(Enum, Copy, Clone, Debug, PartialEq, Eq)]
+#[graphql(remote = "amm::entity::trade::Side")]
+pub enum TradeSide {
+    Buy,
+    Sell,
+}
+
+pub struct Market(pub market::Model);
+
+impl Market {
+    fn state(&self) -> Result<Option<MarketMakerState>> {
+        let state = match &self.0.market_maker {
+            Some(state) => Some(serde_json::from_value(state.clone())?),
+            None => None,
+        };
+        Ok(state)
+    }
+}
+
+#[Object]
+impl Market {
+    async fn id(&self) -> i32 {
+        self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    async fn status(&self) -> MarketStatus {
+        self.0.status.into()
+    }
+
+    /// Kind of market maker, e.g. `lmsr` or `fpmm`.
+    async fn kind(&self) -> Option<&str> {
+        self.0.market_maker.as_ref()?.get("kind")?.as_str()
+    }
+
+    /// Current price of every outcome, by outcome index.
+    async fn prices(&self) -> Result<Vec<f64>> {
+        Ok(self
+            .state()?
+            .map(|state| state.prices())
+            .unwrap_or_default())
+    }
+
+    /// Cost of the purchase at the current prices, without executing it.
+    async fn quote(&self, input: GetPriceForPurchase) -> Result<f64> {
+        let state = self.state()?.ok_or(repository::Error::NoMarketMaker)?;
+        Ok(service::quote(&state, input)?)
+    }
+
+    async fn decisions(&self, ctx: &Context<'_>) -> Result<Vec<Decision>> {
+        let loader = ctx.data::<DataLoader<DbLoader>>()?;
+        let decisions = loader
+            .load_one(DecisionsOfMarket(self.0.id))
+            .await?
+            .unwrap_or_default();
+        Ok(decisions.into_iter().map(Decision).collect())
+    }
+}
+
+pub struct Decision(pub decision::Model);
+
+#[Object]
+impl Decision {
+    async fn id(&self) -> i32 {
+        self.0.id
+    }
+
+    async fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    /// Hex encoded public key of the oracle which attests the decision.
+    async fn oracle_public_key(&self) -> String {
+        self.0.oracle_public_key.to_hex()
+    }
+
+    async fn market(&self, ctx: &Context<'_>) -> Result<Option<Market>> {
+        let loader = ctx.data::<DataLoader<DbLoader>>()?;
+        Ok(loader
+            .load_one(MarketById(self.0.market_id))
+            .await?
+            .map(Market))
+    }
+
+    /// Outcomes in the order of their index.
+    async fn outcomes(&self, ctx: &Context<'_>) -> Result<Vec<Outcome>> {
+        let loader = ctx.data::<DataLoader<DbLoader>>()?;
+        let outcomes = loader
+            .load_one(OutcomesOfDecision(self.0.id))
+            .await?
+            .unwrap_or_default();
+        Ok(outcomes.into_iter().map(Outcome).collect())
+    }
+}
+
+pub struct Outcome(pub outcome::Model);
+
+#[Object]
+impl Outcome {
+    async fn id(&self) -> i32 {
+        self.0.id
+    }
+
+    /// Position of the outcome in the price and purchase vectors.
+    async fn index(&self) -> i32 {
+        self.0.index
+    }
+
+    async fn label(&self) -> &str {
+        &self.0.label
+    }
+
+    /// Exact value signed by the oracle, for enumerated events.
+    async fn oracle_value(&self) -> Option<&str> {
+        self.0.oracle_value.as_deref()
+    }
+
+    /// First value of the range signed by the oracle, for numeric events.
+    async fn range_start(&self) -> Option<i64> {
+        self.0.range_start
+    }
+
+    /// Last value of the range signed by the oracle, for numeric events.
+    async fn range_end(&self) -> Option<i64> {
+        self.0.range_end
+    }
+}
+
+async fn load_outcome(ctx: &Context<'_>, id: i32) -> Result<Option<Outcome>> {
+    let loader = ctx.data::<DataLoader<DbLoader>>()?;
+    Ok(loader.load_one(OutcomeById(id)).await?.map(Outcome))
+}
+
+pub struct Trade(pub trade::Model);
+
+#[Object]
+impl Trade {
+    async fn id(&self) -> i32 {
+        self.0.id
+    }
+
+    async fn market_id(&self) -> i32 {
+        self.0.market_id
+    }
+
+    async fn outcome(&self, ctx: &Context<'_>) -> Result<Option<Outcome>> {
+        load_outcome(ctx, self.0.outcome_id).await
+    }
+
+    async fn trader(&self) -> &str {
+        &self.0.trader
+    }
+
+    async fn side(&self) -> TradeSide {
+        self.0.side.into()
+    }
+
+    async fn amount(&self) -> f64 {
+        self.0.amount
+    }
+
+    /// Collateral paid for a buy, or received for a sell.
+    async fn cost(&self) -> f64 {
+        self.0.cost
+    }
+
+    /// Price of every outcome right after the trade, by outcome index.
+    async fn prices(&self) -> Result<Vec<f64>> {
+        Ok(serde_json::from_value(self.0.prices.clone())?)
+    }
+
+    /// Unix timestamp in seconds.
+    async fn created_at(&self) -> i64 {
+        self.0.created_at
+    }
+}
+
+pub struct Position(pub position::Model);
+
+#[Object]
+impl Position {
+    async fn id(&self) -> i32 {
+        self.0.id
+    }
+
+    async fn market(&self, ctx: &Context<'_>) -> Result<Option<Market>> {
+        let loader = ctx.data::<DataLoader<DbLoader>>()?;
+        Ok(loader
+            .load_one(MarketById(self.0.market_id))
+            .await?
+            .map(Market))
+    }
+
+    async fn outcome(&self, ctx: &Context<'_>) -> Result<Option<Outcome>> {
+        load_outcome(ctx, self.0.outcome_id).await
+    }
+
+    async fn trader(&self) -> &str {
+        &self.0.trader
+    }
+
+    async fn amount(&self) -> f64 {
+        self.0.amount
+    }
+}
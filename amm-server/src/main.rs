@@ -1,5 +1,10 @@
 mod api;
+mod auth;
 mod config;
+mod events;
+mod graphql;
+mod rest;
+mod service;
 
 use std::{
     collections::HashMap,
@@ -32,6 +37,8 @@ pub(crate) type DlcManager = dlc_manager::manager::Manager<
 #[tokio::main]
 async fn main() {
     let config = Opts::parse();
+    let conn = dlc_amm_api::connect_db(&config).await;
+    let tokens = config.tokens();
     let bitcoind_provider = Arc::new(
         bitcoin_rpc_provider::BitcoinCoreProvider::new(
             config.bitcoind_rpc_host,
@@ -65,6 +72,9 @@ async fn main() {
         .unwrap(),
     ));
 
-    let runtime = tokio::runtime::Builder::new_multi_thread();
-    println!("Hello, world!");
+    let app = dlc_amm_api::setup(conn, tokens).await;
+    axum::Server::bind(&config.listen)
+        .serve(app.into_make_service())
+        .await
+        .expect("failed to serve the api");
 }
@@ -0,0 +1,102 @@
+use amm::{lifecycle, outcomes, repository};
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{graphql::error::failure, service};
+
+/// Body of every response with an error status.
+#[derive(Serialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct ErrorBody {
+    /// Kind of the error, e.g. `NOT_OPEN`, the same as the `code` of the
+    /// GraphQL failures. `INTERNAL` for every error of the server.
+    pub code: String,
+    pub message: String,
+}
+
+/// Error of a handler, answered with the status matching the service error.
+#[derive(Debug)]
+pub struct ApiError(pub service::Error);
+
+impl<E: Into<service::Error>> From<E> for ApiError {
+    fn from(err: E) -> Self {
+        ApiError(err.into())
+    }
+}
+
+fn status(err: &service::Error) -> StatusCode {
+    match err {
+        service::Error::Repository(repository::Error::NotFound)
+        | service::Error::Lifecycle(lifecycle::Error::NotFound)
+        | service::Error::Outcomes(outcomes::Error::UnknownDecision) => StatusCode::NOT_FOUND,
+        service::Error::Repository(repository::Error::StaleState)
+        | service::Error::Repository(repository::Error::NotOpen)
+        | service::Error::Repository(repository::Error::WrongStatus)
+        | service::Error::Repository(repository::Error::NoMarketMaker)
+        | service::Error::Lifecycle(lifecycle::Error::StaleState)
+        | service::Error::Lifecycle(lifecycle::Error::IllegalTransition)
+        | service::Error::Outcomes(outcomes::Error::NotResolving)
+        | service::Error::InsufficientPosition => StatusCode::CONFLICT,
+        service::Error::Unauthorized => StatusCode::UNAUTHORIZED,
+        service::Error::Repository(_)
+        | service::Error::Lifecycle(lifecycle::Error::Db(_))
+        | service::Error::Outcomes(outcomes::Error::Db(_))
+        | service::Error::History(_)
+        | service::Error::Db(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        _ => StatusCode::UNPROCESSABLE_ENTITY,
+    }
+}
+
+/// Code of the error, the same as the `code` of the failure which the
+/// GraphQL API returns for it.
+fn code(err: service::Error) -> String {
+    match err {
+        service::Error::Unauthorized => "UNAUTHORIZED".to_owned(),
+        err => match failure(err) {
+            Ok(failure) => failure.code(),
+            Err(_) => "INTERNAL".to_owned(),
+        },
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = status(&self.0);
+        let message = self.0.to_string();
+        let body = ErrorBody {
+            code: code(self.0),
+            message,
+        };
+        (status, Json(body)).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amm::{cfmm::Error as CFMMError, repository, AmountError};
+    use sea_orm::DbErr;
+
+    use super::code;
+    use crate::service::Error;
+
+    #[test]
+    fn codes_must_be_those_of_graphql() {
+        assert_eq!(
+            code(Error::Pool(CFMMError::Amount(AmountError::Overflow))),
+            "INVALID_AMOUNT"
+        );
+        assert_eq!(
+            code(Error::Repository(repository::Error::NotOpen)),
+            "NOT_OPEN"
+        );
+        assert_eq!(code(Error::Unauthorized), "UNAUTHORIZED");
+        assert_eq!(
+            code(Error::Db(DbErr::RecordNotFound("".to_owned()))),
+            "INTERNAL"
+        );
+    }
+}
@@ -0,0 +1,290 @@
+use std::{collections::HashMap, convert::TryFrom};
+
+use amm::{
+    dto::{GetPriceForPurchase, MarketId, Purchase, Sell},
+    entity::{decision, market, outcome, position, trade::Side},
+    repository::{self, MarketMakerState},
+};
+use amplify::Wrapper;
+use axum::{
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    Json,
+};
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect,
+};
+
+use super::{
+    error::ApiError,
+    types::{ExecutedView, ListMarkets, ListPositions, MarketView, PositionView, Prices, Quote},
+    AppState,
+};
+use crate::{auth::Caller, service};
+
+const DEFAULT_PAGE_SIZE: u64 = 20;
+const MAX_PAGE_SIZE: u64 = 100;
+
+/// Id of the market in the database. Ids which do not fit are of no market.
+fn market_id(id: MarketId) -> Result<i32, ApiError> {
+    i32::try_from(id.into_inner()).map_err(|_| repository::Error::NotFound.into())
+}
+
+async fn find_market(db: &DatabaseConnection, id: i32) -> Result<market::Model, ApiError> {
+    Ok(market::Entity::find_by_id(id)
+        .one(db)
+        .await?
+        .ok_or(repository::Error::NotFound)?)
+}
+
+/// Outcomes of the first decision of each market, which are the outcomes
+/// traded by its market maker.
+async fn outcomes_of<C: ConnectionTrait>(
+    db: &C,
+    market_ids: Vec<i32>,
+) -> Result<HashMap<i32, Vec<outcome::Model>>, ApiError> {
+    let decisions = decision::Entity::find()
+        .filter(decision::Column::MarketId.is_in(market_ids))
+        .order_by_asc(decision::Column::Id)
+        .all(db)
+        .await?;
+    let mut first = HashMap::new();
+    for decision in decisions {
+        first.entry(decision.market_id).or_insert(decision.id);
+    }
+    // Market of each of the decisions.
+    let first: HashMap<i32, i32> = first
+        .into_iter()
+        .map(|(market_id, decision_id)| (decision_id, market_id))
+        .collect();
+    let outcomes = outcome::Entity::find()
+        .filter(outcome::Column::DecisionId.is_in(first.keys().copied()))
+        .order_by_asc(outcome::Column::Index)
+        .all(db)
+        .await?;
+    let mut by_market: HashMap<i32, Vec<outcome::Model>> = HashMap::new();
+    for outcome in outcomes {
+        by_market
+            .entry(first[&outcome.decision_id])
+            .or_default()
+            .push(outcome);
+    }
+    Ok(by_market)
+}
+
+async fn market_view(
+    db: &DatabaseConnection,
+    market: market::Model,
+) -> Result<MarketView, ApiError> {
+    let outcomes = outcomes_of(db, vec![market.id])
+        .await?
+        .remove(&market.id)
+        .unwrap_or_default();
+    Ok(MarketView::new(market, outcomes).map_err(repository::Error::from)?)
+}
+
+/// Markets in the order of their creation.
+#[utoipa::path(
+    get,
+    path = "/api/markets",
+    params(ListMarkets),
+    responses((status = 200, body = [MarketView]))
+)]
+pub async fn list_markets(
+    State(state): State<AppState>,
+    Query(query): Query<ListMarkets>,
+) -> Result<Json<Vec<MarketView>>, ApiError> {
+    let mut select = market::Entity::find().order_by_asc(market::Column::Id);
+    if let Some(status) = query.status {
+        select = select.filter(market::Column::Status.eq(status));
+    }
+    if let Some(after) = query.after {
+        select = select.filter(market::Column::Id.gt(after));
+    }
+    let markets = select
+        .limit(query.limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE))
+        .all(&state.db)
+        .await?;
+    let mut outcomes = outcomes_of(&state.db, markets.iter().map(|m| m.id).collect()).await?;
+    let views = markets
+        .into_iter()
+        .map(|market| {
+            let outcomes = outcomes.remove(&market.id).unwrap_or_default();
+            MarketView::new(market, outcomes)
+        })
+        .collect::<Result<_, _>>()
+        .map_err(repository::Error::from)?;
+    Ok(Json(views))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/markets/{id}",
+    params(("id" = MarketId, Path, description = "Id of the market")),
+    responses(
+        (status = 200, body = MarketView),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn get_market(
+    State(state): State<AppState>,
+    Path(id): Path<MarketId>,
+) -> Result<Json<MarketView>, ApiError> {
+    let market = find_market(&state.db, market_id(id)?).await?;
+    Ok(Json(market_view(&state.db, market).await?))
+}
+
+/// Current prices, as `GetPricesAtThePoint`.
+#[utoipa::path(
+    get,
+    path = "/api/markets/{id}/prices",
+    params(("id" = MarketId, Path, description = "Id of the market")),
+    responses(
+        (status = 200, body = Prices),
+        (status = 404, body = ErrorBody),
+    )
+)]
+pub async fn get_prices(
+    State(state): State<AppState>,
+    Path(id): Path<MarketId>,
+) -> Result<Json<Prices>, ApiError> {
+    let market = find_market(&state.db, market_id(id)?).await?;
+    let prices = match market.market_maker {
+        Some(state) => serde_json::from_value::<MarketMakerState>(state)
+            .map_err(repository::Error::from)?
+            .prices(),
+        None => vec![],
+    };
+    Ok(Json(Prices {
+        market_id: market.id,
+        prices,
+    }))
+}
+
+/// Cost of the purchase at the current prices, without executing it.
+#[utoipa::path(
+    post,
+    path = "/api/markets/{id}/quote",
+    params(("id" = MarketId, Path, description = "Id of the market")),
+    request_body = GetPriceForPurchase,
+    responses(
+        (status = 200, body = Quote),
+        (status = 404, body = ErrorBody),
+        (status = 409, body = ErrorBody),
+        (status = 422, body = ErrorBody),
+    )
+)]
+pub async fn quote(
+    State(state): State<AppState>,
+    Path(id): Path<MarketId>,
+    Json(input): Json<GetPriceForPurchase>,
+) -> Result<Json<Quote>, ApiError> {
+    let market = find_market(&state.db, market_id(id)?).await?;
+    let state: MarketMakerState = serde_json::from_value(
+        market
+            .market_maker
+            .ok_or(repository::Error::NoMarketMaker)?,
+    )
+    .map_err(repository::Error::from)?;
+    Ok(Json(Quote {
+        cost: service::quote(&state, input)?,
+    }))
+}
+
+/// Buy the securities of the purchase vector for the trader of the bearer
+/// token. The market must be open.
+#[utoipa::path(
+    post,
+    path = "/api/markets/{id}/purchase",
+    params(("id" = MarketId, Path, description = "Id of the market")),
+    request_body = Purchase,
+    responses(
+        (status = 200, body = ExecutedView),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+        (status = 409, body = ErrorBody),
+        (status = 422, body = ErrorBody),
+    )
+)]
+pub async fn purchase(
+    State(state): State<AppState>,
+    Path(id): Path<MarketId>,
+    headers: HeaderMap,
+    Json(input): Json<Purchase>,
+) -> Result<Json<ExecutedView>, ApiError> {
+    let trader = Caller::trader(state.tokens.caller(&headers).as_ref())?;
+    let executed = service::trade(
+        &state.db,
+        &state.events,
+        market_id(id)?,
+        trader,
+        Side::Buy,
+        input.purchase_vector,
+    )
+    .await?;
+    Ok(Json(executed.into()))
+}
+
+/// Sell securities held by the trader of the bearer token back to the
+/// market maker.
+#[utoipa::path(
+    post,
+    path = "/api/markets/{id}/sell",
+    params(("id" = MarketId, Path, description = "Id of the market")),
+    request_body = Sell,
+    responses(
+        (status = 200, body = ExecutedView),
+        (status = 401, body = ErrorBody),
+        (status = 404, body = ErrorBody),
+        (status = 409, body = ErrorBody),
+        (status = 422, body = ErrorBody),
+    )
+)]
+pub async fn sell(
+    State(state): State<AppState>,
+    Path(id): Path<MarketId>,
+    headers: HeaderMap,
+    Json(input): Json<Sell>,
+) -> Result<Json<ExecutedView>, ApiError> {
+    let trader = Caller::trader(state.tokens.caller(&headers).as_ref())?;
+    let executed = service::trade(
+        &state.db,
+        &state.events,
+        market_id(id)?,
+        trader,
+        Side::Sell,
+        input.sell_vector,
+    )
+    .await?;
+    Ok(Json(executed.into()))
+}
+
+/// Securities held by the trader.
+#[utoipa::path(
+    get,
+    path = "/api/positions",
+    params(ListPositions),
+    responses((status = 200, body = [PositionView]))
+)]
+pub async fn list_positions(
+    State(state): State<AppState>,
+    Query(query): Query<ListPositions>,
+) -> Result<Json<Vec<PositionView>>, ApiError> {
+    let mut select = position::Entity::find()
+        .filter(position::Column::Trader.eq(query.trader))
+        .order_by_asc(position::Column::Id);
+    if let Some(market_id) = query.market_id {
+        select = select.filter(position::Column::MarketId.eq(market_id));
+    }
+    if let Some(after) = query.after {
+        select = select.filter(position::Column::Id.gt(after));
+    }
+    let positions = select
+        .limit(query.limit.unwrap_or(DEFAULT_PAGE_SIZE).min(MAX_PAGE_SIZE))
+        .all(&state.db)
+        .await?;
+    Ok(Json(
+        positions.into_iter().map(PositionView::from).collect(),
+    ))
+}
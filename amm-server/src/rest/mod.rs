@@ -0,0 +1,252 @@
+//! REST API, for clients which cannot use GraphQL.
+//!
+//! Requests mirror the `amm::dto` types. The OpenAPI 3 document of the API is
+//! generated from the Rust types and served at `OPENAPI_PATH`, to generate
+//! client SDKs from.
+
+mod error;
+mod handlers;
+mod types;
+
+use amm::{
+    dto::{GetPriceForPurchase, MarketId, Purchase, Sell},
+    entity::{market::Status, trade::Side},
+};
+use axum::{
+    routing::{get, post},
+    Json, Router,
+};
+use sea_orm::DatabaseConnection;
+use utoipa::OpenApi;
+
+use self::{
+    error::ErrorBody,
+    types::{ExecutedView, MarketView, OutcomeView, PositionView, Prices, Quote, TradeView},
+};
+use crate::{auth::Tokens, events::Events};
+
+pub const OPENAPI_PATH: &str = "/api-docs/openapi.json";
+
+#[derive(Clone)]
+pub struct AppState {
+    pub db: DatabaseConnection,
+    pub events: Events,
+    pub tokens: Tokens,
+}
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "DLC AMM"),
+    paths(
+        handlers::list_markets,
+        handlers::get_market,
+        handlers::get_prices,
+        handlers::quote,
+        handlers::purchase,
+        handlers::sell,
+        handlers::list_positions,
+    ),
+    components(schemas(
+        MarketId,
+        GetPriceForPurchase,
+        Purchase,
+        Sell,
+        Status,
+        Side,
+        ErrorBody,
+        MarketView,
+        OutcomeView,
+        Prices,
+        Quote,
+        TradeView,
+        ExecutedView,
+        PositionView,
+    ))
+)]
+pub struct ApiDoc;
+
+pub fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/api/markets", get(handlers::list_markets))
+        .route("/api/markets/:id", get(handlers::get_market))
+        .route("/api/markets/:id/prices", get(handlers::get_prices))
+        .route("/api/markets/:id/quote", post(handlers::quote))
+        .route("/api/markets/:id/purchase", post(handlers::purchase))
+        .route("/api/markets/:id/sell", post(handlers::sell))
+        .route("/api/positions", get(handlers::list_positions))
+        .route(OPENAPI_PATH, get(|| async { Json(ApiDoc::openapi()) }))
+        .with_state(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use amm::entity::market::{self, Status};
+    use axum::{
+        body::Body,
+        http::{Method, Request, StatusCode},
+        Router,
+    };
+    use migration::memory_db;
+    use sea_orm::{prelude::Json, sea_query::Expr, EntityTrait};
+    use serde_json::{json, Value};
+    use tower::ServiceExt;
+
+    use super::{router, AppState, OPENAPI_PATH};
+    use crate::{
+        auth::Tokens,
+        events::Events,
+        service::{self, MarketMakerKind, NewDecision},
+    };
+
+    /// Token of the trader "alice".
+    const TOKEN: &str = "alice-token";
+
+    async fn call_as(
+        app: &Router,
+        token: &str,
+        method: Method,
+        uri: &str,
+        body: Value,
+    ) -> (StatusCode, Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .header("authorization", format!("Bearer {}", token))
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    async fn call(app: &Router, method: Method, uri: &str, body: Value) -> (StatusCode, Value) {
+        call_as(app, TOKEN, method, uri, body).await
+    }
+
+    #[tokio::test]
+    async fn markets_must_be_quoted_and_traded_over_rest() {
+        let db = memory_db().await.unwrap();
+        let events = Events::default();
+        let (market, _) = service::create_market(
+            &db,
+            &events,
+            "m".to_owned(),
+            MarketMakerKind::Lmsr,
+            Some(10.),
+            None,
+            NewDecision::yes_no(),
+        )
+        .await
+        .unwrap();
+        let app = router(AppState {
+            db: db.clone(),
+            events: events.clone(),
+            tokens: Tokens::new(None, vec![("alice".to_owned(), TOKEN.to_owned())]),
+        });
+
+        let (status, markets) = call(&app, Method::GET, "/api/markets", Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(markets[0]["status"], "Draft");
+        assert_eq!(markets[0]["kind"], "lmsr");
+        assert_eq!(markets[0]["outcomes"][1]["label"], "no");
+
+        let uri = |path: &str| format!("/api/markets/{}/{}", market.id, path);
+        let (status, quote) = call(
+            &app,
+            Method::POST,
+            &uri("quote"),
+            json!({ "purchaseVector": [1, 1] }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!((quote["cost"].as_f64().unwrap() - 1.).abs() < 1e-9);
+        let (status, error) = call(
+            &app,
+            Method::POST,
+            &uri("quote"),
+            json!({ "purchaseVector": [1] }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(error["code"], "WRONG_PURCHASE_LENGTH");
+
+        let purchase = json!({ "purchaseVector": [2, 0] });
+        let (status, error) = call(&app, Method::POST, &uri("purchase"), purchase.clone()).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(error["code"], "NOT_OPEN");
+        service::transition(&db, &events, market.id, Status::Open, None)
+            .await
+            .unwrap();
+        let (status, error) = call_as(
+            &app,
+            "bob-token",
+            Method::POST,
+            &uri("purchase"),
+            purchase.clone(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(error["code"], "UNAUTHORIZED");
+        let (status, executed) = call(&app, Method::POST, &uri("purchase"), purchase).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(executed["trades"][0]["side"], "Buy");
+        let (_, prices) = call(&app, Method::GET, &uri("prices"), Value::Null).await;
+        assert_eq!(prices["prices"], executed["prices"]);
+
+        let (status, positions) = call(
+            &app,
+            Method::GET,
+            "/api/positions?trader=alice",
+            Value::Null,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(positions[0]["amount"], 2.);
+        let (_, page) = call(
+            &app,
+            Method::GET,
+            &format!("/api/positions?trader=alice&after={}", positions[0]["id"]),
+            Value::Null,
+        )
+        .await;
+        assert_eq!(page, json!([]));
+        let (status, error) = call(
+            &app,
+            Method::POST,
+            &uri("sell"),
+            json!({ "sellVector": [3, 0] }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(error["code"], "INSUFFICIENT_POSITION");
+        let (status, error) = call(&app, Method::GET, "/api/markets/999", Value::Null).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(error["code"], "NOT_FOUND");
+
+        market::Entity::update_many()
+            .col_expr(
+                market::Column::MarketMaker,
+                Expr::value(Option::<Json>::None),
+            )
+            .exec(&db)
+            .await
+            .unwrap();
+        let (status, error) = call(
+            &app,
+            Method::POST,
+            &uri("quote"),
+            json!({ "purchaseVector": [1, 1] }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(error["code"], "NO_MARKET_MAKER");
+
+        let (status, doc) = call(&app, Method::GET, OPENAPI_PATH, Value::Null).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(doc["openapi"].as_str().unwrap().starts_with("3."));
+        assert!(doc["paths"]["/api/markets/{id}/purchase"]["post"].is_object());
+        assert!(doc["components"]["schemas"]["GetPriceForPurchase"].is_object());
+    }
+}
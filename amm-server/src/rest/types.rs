@@ -0,0 +1,185 @@
+use amm::{
+    entity::{market, market::Status, outcome, position, trade, trade::Side},
+    repository::MarketMakerState,
+};
+use sea_orm::entity::prelude::Json;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::service::Executed;
+
+/// Filter and page of `GET /api/markets`.
+#[derive(Deserialize, IntoParams, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct ListMarkets {
+    pub status: Option<Status>,
+    /// Id of the last market of the previous page.
+    pub after: Option<i32>,
+    /// Number of markets in the page, at most 100.
+    pub limit: Option<u64>,
+}
+
+/// Filter of `GET /api/positions`.
+#[derive(Deserialize, IntoParams, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct ListPositions {
+    pub trader: String,
+    pub market_id: Option<i32>,
+    /// Id of the last position of the previous page.
+    pub after: Option<i32>,
+    /// Number of positions in the page, at most 100.
+    pub limit: Option<u64>,
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OutcomeView {
+    pub id: i32,
+    /// Position of the outcome in the price and purchase vectors.
+    pub index: i32,
+    pub label: String,
+}
+
+impl From<outcome::Model> for OutcomeView {
+    fn from(outcome: outcome::Model) -> Self {
+        OutcomeView {
+            id: outcome.id,
+            index: outcome.index,
+            label: outcome.label,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct MarketView {
+    pub id: i32,
+    pub name: String,
+    pub status: Status,
+    /// Kind of market maker, e.g. `lmsr` or `fpmm`.
+    pub kind: Option<String>,
+    /// Current price of every outcome, by outcome index.
+    pub prices: Vec<f64>,
+    /// Outcomes traded by the purchase vectors, by index.
+    pub outcomes: Vec<OutcomeView>,
+}
+
+impl MarketView {
+    pub fn new(
+        market: market::Model,
+        outcomes: Vec<outcome::Model>,
+    ) -> Result<Self, serde_json::Error> {
+        let state = match &market.market_maker {
+            Some(state) => Some(serde_json::from_value::<MarketMakerState>(state.clone())?),
+            None => None,
+        };
+        Ok(MarketView {
+            id: market.id,
+            kind: market
+                .market_maker
+                .as_ref()
+                .and_then(|state| state.get("kind")?.as_str())
+                .map(str::to_owned),
+            name: market.name,
+            status: market.status,
+            prices: state.map(|state| state.prices()).unwrap_or_default(),
+            outcomes: outcomes.into_iter().map(OutcomeView::from).collect(),
+        })
+    }
+}
+
+/// Response of `GET /api/markets/{id}/prices`, the REST counterpart of
+/// `amm::dto::GetPricesAtThePoint`.
+#[derive(Serialize, ToSchema, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Prices {
+    pub market_id: i32,
+    /// Price of every outcome, by outcome index.
+    pub prices: Vec<f64>,
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Quote {
+    /// Collateral the purchase would cost at the current prices.
+    pub cost: f64,
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TradeView {
+    pub id: i32,
+    pub market_id: i32,
+    pub outcome_id: i32,
+    pub trader: String,
+    pub side: Side,
+    pub amount: f64,
+    /// Collateral paid for a buy, or received for a sell.
+    pub cost: f64,
+    /// Price of every outcome right after the trade, by outcome index.
+    #[schema(value_type = Vec<f64>)]
+    pub prices: Json,
+    /// Unix timestamp in seconds.
+    pub created_at: i64,
+}
+
+impl From<trade::Model> for TradeView {
+    fn from(trade: trade::Model) -> Self {
+        TradeView {
+            id: trade.id,
+            market_id: trade.market_id,
+            outcome_id: trade.outcome_id,
+            trader: trade.trader,
+            side: trade.side,
+            amount: trade.amount,
+            cost: trade.cost,
+            prices: trade.prices,
+            created_at: trade.created_at,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ExecutedView {
+    /// One trade per outcome traded.
+    pub trades: Vec<TradeView>,
+    /// Collateral paid for a purchase, or received for a sale.
+    pub cost: f64,
+    /// Price of every outcome after the trade.
+    pub prices: Vec<f64>,
+}
+
+impl From<Executed> for ExecutedView {
+    fn from(executed: Executed) -> Self {
+        ExecutedView {
+            trades: executed.trades.into_iter().map(TradeView::from).collect(),
+            cost: executed.cost,
+            prices: executed.prices,
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PositionView {
+    pub id: i32,
+    pub market_id: i32,
+    pub outcome_id: i32,
+    pub trader: String,
+    pub amount: f64,
+}
+
+impl From<position::Model> for PositionView {
+    fn from(position: position::Model) -> Self {
+        PositionView {
+            id: position.id,
+            market_id: position.market_id,
+            outcome_id: position.outcome_id,
+            trader: position.trader,
+            amount: position.amount,
+        }
+    }
+}
@@ -0,0 +1,491 @@
+//! Operations which change the state of markets, shared by every API.
+//!
+//! Each operation runs in a single database transaction, so a trade is never
+//! saved in the market maker without its history and positions. Changes are
+//! published to `Events` once committed.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use amm::{
+    cfmm::{
+        fpmm::{FixedProductMarketMaker, LiquidityChange},
+        Error as CFMMError,
+    },
+    cost_function::{
+        lmsr::LMScoringRule, ls_lmsr::LSLMScoringRule, lsmr_logsumexp, AMMError, PurchaseError,
+    },
+    dto::{GetPriceForPurchase, Purchase, Sell},
+    entity::{
+        decision, liquidity_event,
+        market::{self, Status},
+        outcome, position, resolution,
+        trade::{self, Side},
+    },
+    history::{self, NewTrade},
+    lifecycle,
+    outcomes::{self, AnnouncedOutcomes, Attestation, OutcomeSpec},
+    repository::{self, MarketMakerState},
+};
+use amplify::{Display, Error, From};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DbErr,
+    EntityTrait, QueryFilter, QueryOrder, Set, TransactionTrait,
+};
+
+use crate::events::{Event, Events};
+
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum Error {
+    /// {0}
+    #[from]
+    Amm(AMMError),
+    /// {0}
+    #[from]
+    Purchase(PurchaseError),
+    /// {0}
+    #[from]
+    Pool(CFMMError),
+    /// {0}
+    #[from]
+    Repository(repository::Error),
+    /// {0}
+    #[from]
+    Lifecycle(lifecycle::Error),
+    /// {0}
+    #[from]
+    Outcomes(outcomes::Error),
+    /// {0}
+    #[from]
+    History(history::Error),
+    /// Market maker of the market does not support the operation
+    UnsupportedMarketMaker,
+    /// Trader does not hold the securities to sell
+    InsufficientPosition,
+    /// Value must be hex encoded
+    InvalidHex,
+    /// Caller is not authorized for the operation
+    Unauthorized,
+    /// Database error: {0}
+    #[from]
+    Db(DbErr),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MarketMakerKind {
+    Lmsr,
+    LogSumExpLmsr,
+    LsLmsr,
+    Fpmm,
+}
+
+impl MarketMakerKind {
+    /// Market maker for `outcomes` outcomes. `liquidity` is `b` for an LMSR
+    /// and `alpha` for an LS-LMSR, and is required for both. An FPMM has no
+    /// liquidity until it is funded, and no fee unless one is given.
+    pub fn create(
+        self,
+        outcomes: usize,
+        liquidity: Option<f64>,
+        fee: Option<f64>,
+    ) -> Result<MarketMakerState, Error> {
+        let liquidity = || liquidity.ok_or(AMMError::BogusLiquidityParam);
+        let state = match self {
+            MarketMakerKind::Lmsr => {
+                MarketMakerState::Lmsr(LMScoringRule::try_create(outcomes, liquidity()?)?)
+            }
+            MarketMakerKind::LogSumExpLmsr => MarketMakerState::LogSumExpLmsr(
+                lsmr_logsumexp::LMScoringRule::try_create(outcomes, liquidity()?)?,
+            ),
+            MarketMakerKind::LsLmsr => {
+                MarketMakerState::LsLmsr(LSLMScoringRule::try_create(outcomes, liquidity()?)?)
+            }
+            MarketMakerKind::Fpmm => MarketMakerState::Fpmm(FixedProductMarketMaker::try_create(
+                outcomes,
+                fee.unwrap_or(0.),
+            )?),
+        };
+        Ok(state)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NewDecision {
+    pub name: String,
+    pub oracle_public_key: Vec<u8>,
+    pub announced: AnnouncedOutcomes,
+    pub outcomes: Vec<OutcomeSpec>,
+}
+
+impl NewDecision {
+    /// Decision between "yes" and "no" of oracle `02`, for tests.
+    #[cfg(test)]
+    pub fn yes_no() -> Self {
+        let outcome = |label: &str| OutcomeSpec {
+            label: label.to_owned(),
+            value: outcomes::OracleValue::Exact(label.to_owned()),
+        };
+        NewDecision {
+            name: "d".to_owned(),
+            oracle_public_key: vec![2],
+            announced: AnnouncedOutcomes::Enumerated(vec!["yes".to_owned(), "no".to_owned()]),
+            outcomes: vec![outcome("yes"), outcome("no")],
+        }
+    }
+}
+
+/// Trade executed by `buy` or `sell`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Executed {
+    /// One trade per outcome, in the order of their index.
+    pub trades: Vec<trade::Model>,
+    /// Collateral paid for a buy, or received for a sell.
+    pub cost: f64,
+    /// Price of every outcome after the trade.
+    pub prices: Vec<f64>,
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
+
+/// Create a draft market with its decision. The number of outcomes of the
+/// decision is the number of outcomes of the market maker.
+pub async fn create_market(
+    db: &DatabaseConnection,
+    events: &Events,
+    name: String,
+    kind: MarketMakerKind,
+    liquidity: Option<f64>,
+    fee: Option<f64>,
+    decision: NewDecision,
+) -> Result<(market::Model, decision::Model), Error> {
+    let state = kind.create(decision.outcomes.len(), liquidity, fee)?;
+    let txn = db.begin().await?;
+    let created = repository::create(&txn, name, state).await?;
+    let (decision, _) = outcomes::create_decision(
+        &txn,
+        created.market_id,
+        decision.name,
+        decision.oracle_public_key,
+        &decision.announced,
+        &decision.outcomes,
+    )
+    .await?;
+    let market = market::Entity::find_by_id(created.market_id)
+        .one(&txn)
+        .await?
+        .ok_or(repository::Error::NotFound)?;
+    txn.commit().await?;
+    events.publish(Event::Market(market.clone()));
+    Ok((market, decision))
+}
+
+pub async fn transition(
+    db: &DatabaseConnection,
+    events: &Events,
+    market_id: i32,
+    to: Status,
+    reason: Option<String>,
+) -> Result<market::Model, Error> {
+    let market = lifecycle::transition(db, market_id, to, reason, now()).await?;
+    events.publish(Event::Market(market.clone()));
+    Ok(market)
+}
+
+/// Outcomes of the first decision of the market, by index.
+async fn market_outcomes<C: ConnectionTrait>(
+    db: &C,
+    market_id: i32,
+) -> Result<Vec<outcome::Model>, Error> {
+    let decision = decision::Entity::find()
+        .filter(decision::Column::MarketId.eq(market_id))
+        .order_by_asc(decision::Column::Id)
+        .one(db)
+        .await?
+        .ok_or(repository::Error::NotFound)?;
+    Ok(outcome::Entity::find()
+        .filter(outcome::Column::DecisionId.eq(decision.id))
+        .order_by_asc(outcome::Column::Index)
+        .all(db)
+        .await?)
+}
+
+async fn add_to_position<C: ConnectionTrait>(
+    db: &C,
+    market_id: i32,
+    outcome_id: i32,
+    trader: &str,
+    amount: f64,
+) -> Result<(), Error> {
+    let existing = position::Entity::find()
+        .filter(position::Column::MarketId.eq(market_id))
+        .filter(position::Column::OutcomeId.eq(outcome_id))
+        .filter(position::Column::Trader.eq(trader))
+        .one(db)
+        .await?;
+    match existing {
+        Some(existing) => {
+            let held = existing.amount;
+            let mut existing: position::ActiveModel = existing.into();
+            existing.amount = Set(held + amount);
+            existing.update(db).await?;
+        }
+        None => {
+            position::ActiveModel {
+                market_id: Set(market_id),
+                outcome_id: Set(outcome_id),
+                trader: Set(trader.to_owned()),
+                amount: Set(amount),
+                ..Default::default()
+            }
+            .insert(db)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Take `amount` from the position of the trader, unless it holds less. The
+/// check and the update are a single statement, so concurrent sales can not
+/// both spend the same securities.
+async fn take_from_position<C: ConnectionTrait>(
+    db: &C,
+    market_id: i32,
+    outcome_id: i32,
+    trader: &str,
+    amount: f64,
+) -> Result<(), Error> {
+    let result = position::Entity::update_many()
+        .col_expr(
+            position::Column::Amount,
+            Expr::col(position::Column::Amount).sub(amount),
+        )
+        .filter(position::Column::MarketId.eq(market_id))
+        .filter(position::Column::OutcomeId.eq(outcome_id))
+        .filter(position::Column::Trader.eq(trader))
+        .filter(position::Column::Amount.gte(amount))
+        .exec(db)
+        .await?;
+    if result.rows_affected == 0 {
+        return Err(Error::InsufficientPosition);
+    }
+    Ok(())
+}
+
+/// Cost of the purchase at the current prices, without executing it. A
+/// fixed product market maker is priced by executing the purchase against a
+/// copy of its pool.
+pub fn quote(state: &MarketMakerState, input: GetPriceForPurchase) -> Result<f64, Error> {
+    match state {
+        MarketMakerState::Fpmm(mm) => Ok(Purchase {
+            purchase_vector: input.purchase_vector,
+        }
+        .execute_fixed_product(&mut mm.clone())?
+        .iter()
+        .map(|fill| fill.cost)
+        .sum()),
+        state => Ok(input.price(
+            state
+                .as_cost_function()
+                .ok_or(Error::UnsupportedMarketMaker)?,
+        )?),
+    }
+}
+
+/// Buy (or sell) the securities of `vector` from (or to) the market maker of
+/// an open market. A sale fails, and nothing is saved, unless the trader
+/// holds the securities.
+pub async fn trade(
+    db: &DatabaseConnection,
+    events: &Events,
+    market_id: i32,
+    trader: String,
+    side: Side,
+    vector: Vec<f64>,
+) -> Result<Executed, Error> {
+    let txn = db.begin().await?;
+    let outcomes = market_outcomes(&txn, market_id).await?;
+    if outcomes.len() != vector.len() {
+        return Err(PurchaseError::WrongPurchaseLength.into());
+    }
+    let (fills, prices) = repository::trade(&txn, market_id, |state| {
+        let purchase = Purchase {
+            purchase_vector: vector.clone(),
+        };
+        let sell = Sell {
+            sell_vector: vector.clone(),
+        };
+        let fills = match (&mut *state, side) {
+            (MarketMakerState::Fpmm(mm), Side::Buy) => purchase.execute_fixed_product(mm)?,
+            (MarketMakerState::Fpmm(mm), Side::Sell) => sell.execute_fixed_product(mm)?,
+            (state, side) => {
+                let mm = state
+                    .as_cost_function_mut()
+                    .ok_or(Error::UnsupportedMarketMaker)?;
+                match side {
+                    Side::Buy => purchase.execute(mm)?,
+                    Side::Sell => sell.execute(mm)?,
+                }
+            }
+        };
+        Ok::<_, Error>((fills, state.prices()))
+    })
+    .await?;
+    let created_at = now();
+    let mut trades = Vec::with_capacity(fills.len());
+    for fill in &fills {
+        let outcome_id = outcomes[fill.index].id;
+        match side {
+            Side::Buy => add_to_position(&txn, market_id, outcome_id, &trader, fill.amount).await?,
+            Side::Sell => {
+                take_from_position(&txn, market_id, outcome_id, &trader, fill.amount).await?
+            }
+        }
+        let trade = history::record_trade(
+            &txn,
+            NewTrade {
+                market_id,
+                outcome_id,
+                trader: trader.clone(),
+                side,
+                amount: fill.amount,
+                cost: fill.cost,
+                prices: prices.clone(),
+                created_at,
+            },
+        )
+        .await?;
+        trades.push(trade);
+    }
+    txn.commit().await?;
+    for trade in &trades {
+        events.publish(Event::Trade(trade.clone()));
+    }
+    events.publish(Event::Prices {
+        market_id,
+        prices: prices.clone(),
+    });
+    Ok(Executed {
+        trades,
+        cost: fills.iter().map(|fill| fill.cost).sum(),
+        prices,
+    })
+}
+
+/// Change the pool of an FPMM market, if its status is one of `allowed`.
+async fn change_liquidity<F>(
+    db: &DatabaseConnection,
+    events: &Events,
+    market_id: i32,
+    provider: String,
+    kind: liquidity_event::Kind,
+    allowed: &[Status],
+    mut f: F,
+) -> Result<LiquidityChange, Error>
+where
+    F: FnMut(&mut FixedProductMarketMaker) -> Result<(f64, LiquidityChange), AMMError>,
+{
+    let txn = db.begin().await?;
+    let (amount, change, prices) =
+        repository::update_in(&txn, market_id, allowed, |state| match state {
+            MarketMakerState::Fpmm(mm) => {
+                let (amount, change) = f(mm)?;
+                Ok((amount, change, mm.prices()))
+            }
+            _ => Err(Error::UnsupportedMarketMaker),
+        })
+        .await?;
+    liquidity_event::ActiveModel {
+        market_id: Set(market_id),
+        provider: Set(provider),
+        kind: Set(kind),
+        amount: Set(amount),
+        shares: Set(change.shares),
+        created_at: Set(now()),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await?;
+    txn.commit().await?;
+    events.publish(Event::Prices { market_id, prices });
+    Ok(change)
+}
+
+/// Fund the pool of the market with `amount` collateral, while it is
+/// `Status::Draft` or `Status::Open`.
+pub async fn add_liquidity(
+    db: &DatabaseConnection,
+    events: &Events,
+    market_id: i32,
+    provider: String,
+    amount: f64,
+) -> Result<LiquidityChange, Error> {
+    let id = provider.clone();
+    change_liquidity(
+        db,
+        events,
+        market_id,
+        provider,
+        liquidity_event::Kind::Add,
+        &[Status::Draft, Status::Open],
+        |mm| Ok((amount, mm.add_liquidity(id.clone(), amount)?)),
+    )
+    .await
+}
+
+/// Burn `shares` of the provider, the collateral moved is the accrued fees.
+/// Not while the market is `Status::Halted` or `Status::Resolving`.
+pub async fn remove_liquidity(
+    db: &DatabaseConnection,
+    events: &Events,
+    market_id: i32,
+    provider: String,
+    shares: f64,
+) -> Result<LiquidityChange, Error> {
+    let id = provider.clone();
+    change_liquidity(
+        db,
+        events,
+        market_id,
+        provider,
+        liquidity_event::Kind::Remove,
+        &[
+            Status::Draft,
+            Status::Open,
+            Status::Closed,
+            Status::Resolved,
+            Status::Cancelled,
+        ],
+        |mm| {
+            let change = mm.remove_liquidity(&id, shares)?;
+            Ok((change.fees, change))
+        },
+    )
+    .await
+}
+
+/// Record the outcome attested by the oracle, and move the market from
+/// `Status::Resolving` to `Status::Resolved`.
+pub async fn resolve(
+    db: &DatabaseConnection,
+    events: &Events,
+    decision_id: i32,
+    attestation: Attestation,
+) -> Result<(resolution::Model, market::Model), Error> {
+    let txn = db.begin().await?;
+    let decision = decision::Entity::find_by_id(decision_id)
+        .one(&txn)
+        .await?
+        .ok_or(outcomes::Error::UnknownDecision)?;
+    let now = now();
+    let resolution = outcomes::resolve(&txn, decision_id, &attestation, now).await?;
+    let market =
+        lifecycle::transition(&txn, decision.market_id, Status::Resolved, None, now).await?;
+    txn.commit().await?;
+    events.publish(Event::Market(market.clone()));
+    Ok((resolution, market))
+}
@@ -0,0 +1,139 @@
+//! Amount of an asset, in its smallest unit.
+//!
+//! Amounts are tagged with their asset, so that arithmetic between two
+//! different assets (e.g. adding satoshis to units of an outcome token) is
+//! rejected instead of silently producing garbage. Pricing maths is done in
+//! floating point, so conversions to and from `f64` are explicit and only
+//! happen at that boundary.
+//!
+//! Outcome tokens of `cfmm::fpmm`, and the venues of `matching` and
+//! `arbitrage` which trade them, are fractional like those of
+//! `cost_function`, so they stay in floating point.
+
+use std::{cmp::Ordering, convert::TryFrom, fmt};
+
+use amplify::{Display, Error};
+
+use crate::{utils::FinitePositiveFloat, AssetId};
+
+#[derive(Clone, Debug, PartialEq, Eq, Display, Error)]
+#[display(doc_comments)]
+pub enum AmountError {
+    /// Amounts of different assets can not be combined
+    AssetMismatch,
+    /// Amount does not fit in 64 bits
+    Overflow,
+    /// Amount would be negative
+    Underflow,
+    /// Floating point amount is NaN, infinite or negative
+    InvalidFloat,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, serde::Deserialize, serde::Serialize)]
+pub struct Amount {
+    asset: AssetId,
+    units: u64,
+}
+
+impl Amount {
+    pub fn new(asset: AssetId, units: u64) -> Self {
+        Self { asset, units }
+    }
+
+    pub fn zero(asset: AssetId) -> Self {
+        Self::new(asset, 0)
+    }
+
+    /// Round a floating point amount to the nearest unit.
+    pub fn from_f64(asset: AssetId, value: f64) -> Result<Self, AmountError> {
+        let value = FinitePositiveFloat::try_from(value).map_err(|_| AmountError::InvalidFloat)?;
+        let rounded = value.inner().round();
+        if rounded >= u64::MAX as f64 {
+            return Err(AmountError::Overflow);
+        }
+        Ok(Self::new(asset, rounded as u64))
+    }
+
+    pub fn asset(&self) -> &AssetId {
+        &self.asset
+    }
+
+    pub fn units(&self) -> u64 {
+        self.units
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.units == 0
+    }
+
+    /// Number of units as `f64`, for pricing.
+    pub fn to_f64(&self) -> f64 {
+        self.units as f64
+    }
+
+    fn check_asset(&self, other: &Amount) -> Result<(), AmountError> {
+        if self.asset != other.asset {
+            return Err(AmountError::AssetMismatch);
+        }
+        Ok(())
+    }
+
+    pub fn checked_add(&self, other: &Amount) -> Result<Amount, AmountError> {
+        self.check_asset(other)?;
+        let units = self
+            .units
+            .checked_add(other.units)
+            .ok_or(AmountError::Overflow)?;
+        Ok(Self::new(self.asset.clone(), units))
+    }
+
+    pub fn checked_sub(&self, other: &Amount) -> Result<Amount, AmountError> {
+        self.check_asset(other)?;
+        let units = self
+            .units
+            .checked_sub(other.units)
+            .ok_or(AmountError::Underflow)?;
+        Ok(Self::new(self.asset.clone(), units))
+    }
+}
+
+/// Amounts of different assets are not comparable.
+impl PartialOrd for Amount {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        if self.asset != other.asset {
+            return None;
+        }
+        self.units.partial_cmp(&other.units)
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} of {}", self.units, self.asset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amplify::Wrapper;
+
+    use super::{Amount, AmountError};
+    use crate::AssetId;
+
+    #[test]
+    fn amounts_of_different_assets_must_not_be_combined() {
+        let btc = Amount::new(AssetId::from_inner([0; 32]), 100);
+        let other = Amount::new(AssetId::from_inner([1; 32]), 100);
+        assert_eq!(btc.checked_add(&other), Err(AmountError::AssetMismatch));
+        assert_eq!(btc.partial_cmp(&other), None);
+        assert_eq!(btc.checked_add(&btc).unwrap().units(), 200);
+        assert_eq!(
+            btc.checked_sub(&btc.checked_add(&btc).unwrap()),
+            Err(AmountError::Underflow)
+        );
+        assert_eq!(
+            Amount::from_f64(btc.asset().clone(), -1.),
+            Err(AmountError::InvalidFloat)
+        );
+    }
+}
@@ -0,0 +1,436 @@
+//! Arbitrage between market makers trading the same assets.
+//!
+//! When e.g. an LMSR market and a CPMM trade the same outcome token, their
+//! prices drift apart. `Arbitrageur` looks for cycles of trades which start
+//! and end in the house's numeraire asset and return more than they cost
+//! after fees, and can execute them to bring the prices back together.
+//!
+//! Every cycle is sized by maximizing its profit, and the numeraire spent
+//! by one call to `rebalance` never exceeds the capital limit.
+//! Every decision is logged with the `log` crate.
+
+use amplify::{Display, Error, From};
+use log::{debug, info, warn};
+
+use crate::{
+    cfmm::OrderType,
+    matching::{Error as VenueError, Venue},
+    utils::FinitePositiveFloat,
+    AssetId,
+};
+
+/// Number of steps for searching the most profitable size of a cycle.
+const SEARCH_STEPS: usize = 100;
+
+/// Maximum number of cycles executed by one `rebalance`.
+const MAX_ROUNDS: usize = 16;
+
+#[derive(Clone, Debug, PartialEq, Eq, Display, Error, From)]
+#[display(doc_comments)]
+pub enum Error {
+    /// Capital limit must be a positive finite number
+    InvalidCapitalLimit,
+    /// Fee must be a number in `[0, 1)`
+    InvalidFee,
+    /// Error from a market maker
+    #[from]
+    Venue(VenueError),
+}
+
+/// What a market maker trades, see `matching::Venue`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VenueInfo {
+    /// Name used in the logs.
+    pub name: String,
+    pub traded: AssetId,
+    pub counter: AssetId,
+    /// Fee charged on the input of every trade, which is not included in
+    /// the quotes of the venue.
+    pub fee: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Leg {
+    pub venue: usize,
+    pub order_type: OrderType,
+    pub amount_in: f64,
+    pub amount_out: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Opportunity {
+    pub legs: Vec<Leg>,
+    /// Numeraire spent.
+    pub amount_in: f64,
+    /// Numeraire received at the end of the cycle.
+    pub amount_out: f64,
+}
+
+impl Opportunity {
+    pub fn profit(&self) -> f64 {
+        self.amount_out - self.amount_in
+    }
+}
+
+pub struct Arbitrageur<V> {
+    venues: Vec<(V, VenueInfo)>,
+    numeraire: AssetId,
+    capital_limit: f64,
+    max_hops: usize,
+    min_profit: f64,
+}
+
+impl<V: Venue> Arbitrageur<V> {
+    pub fn try_create(numeraire: AssetId, capital_limit: f64) -> Result<Self, Error> {
+        FinitePositiveFloat::try_non_zero(capital_limit).map_err(|_| Error::InvalidCapitalLimit)?;
+        Ok(Self {
+            venues: vec![],
+            numeraire,
+            capital_limit,
+            max_hops: 3,
+            min_profit: 0.,
+        })
+    }
+
+    pub fn max_hops(mut self, max_hops: usize) -> Self {
+        self.max_hops = max_hops;
+        self
+    }
+
+    /// Opportunities which earn less than this are ignored.
+    pub fn min_profit(mut self, min_profit: f64) -> Self {
+        self.min_profit = min_profit;
+        self
+    }
+
+    pub fn add_venue(&mut self, venue: V, info: VenueInfo) -> Result<usize, Error> {
+        if !(info.fee >= 0. && info.fee < 1.) {
+            return Err(Error::InvalidFee);
+        }
+        self.venues.push((venue, info));
+        Ok(self.venues.len() - 1)
+    }
+
+    pub fn venue(&self, index: usize) -> Option<&V> {
+        self.venues.get(index).map(|(v, _)| v)
+    }
+
+    /// Asset received from the leg, and how much of it per unit of input at
+    /// the marginal price.
+    fn marginal_leg(&self, venue: usize, order_type: OrderType) -> (&AssetId, f64) {
+        let (v, info) = &self.venues[venue];
+        let price = v.marginal_price();
+        match order_type {
+            OrderType::Buy => (&info.traded, (1. - info.fee) / price),
+            OrderType::Sell => (&info.counter, price * (1. - info.fee)),
+        }
+    }
+
+    fn input_of(&self, venue: usize, order_type: OrderType) -> &AssetId {
+        let info = &self.venues[venue].1;
+        match order_type {
+            OrderType::Buy => &info.counter,
+            OrderType::Sell => &info.traded,
+        }
+    }
+
+    /// Cycles from the numeraire which are profitable at marginal prices.
+    /// A venue is used at most once in a cycle, so its quotes stay valid.
+    fn find_cycles(&self) -> Vec<Vec<(usize, OrderType)>> {
+        let mut result = vec![];
+        let mut path = vec![];
+        self.find_cycles_from(&self.numeraire, 1., &mut path, &mut result);
+        result
+    }
+
+    fn find_cycles_from(
+        &self,
+        asset: &AssetId,
+        rate: f64,
+        path: &mut Vec<(usize, OrderType)>,
+        result: &mut Vec<Vec<(usize, OrderType)>>,
+    ) {
+        if path.len() >= self.max_hops {
+            return;
+        }
+        for venue in 0..self.venues.len() {
+            if path.iter().any(|(v, _)| *v == venue) {
+                continue;
+            }
+            for order_type in [OrderType::Buy, OrderType::Sell] {
+                if self.input_of(venue, order_type) != asset {
+                    continue;
+                }
+                let (next, leg_rate) = self.marginal_leg(venue, order_type);
+                if !leg_rate.is_finite() || leg_rate <= 0. {
+                    continue;
+                }
+                path.push((venue, order_type));
+                if *next == self.numeraire {
+                    if rate * leg_rate > 1. {
+                        result.push(path.clone());
+                    }
+                } else {
+                    self.find_cycles_from(next, rate * leg_rate, path, result);
+                }
+                path.pop();
+            }
+        }
+    }
+
+    /// Output of the leg for `amount_in` of its input.
+    fn simulate_leg(
+        &self,
+        venue: usize,
+        order_type: OrderType,
+        amount_in: f64,
+    ) -> Result<f64, VenueError> {
+        let (v, info) = &self.venues[venue];
+        match order_type {
+            OrderType::Sell => Ok(v.quote(OrderType::Sell, amount_in)? * (1. - info.fee)),
+            OrderType::Buy => {
+                // Find how much can be bought with the budget.
+                let budget = amount_in * (1. - info.fee);
+                let affordable =
+                    |a: f64| matches!(v.quote(OrderType::Buy, a), Ok(c) if c <= budget);
+                let mut lo = 0.;
+                let mut hi = budget / v.marginal_price();
+                for _ in 0..SEARCH_STEPS {
+                    if !affordable(hi) {
+                        break;
+                    }
+                    lo = hi;
+                    hi *= 2.;
+                }
+                for _ in 0..SEARCH_STEPS {
+                    let mid = 0.5 * (lo + hi);
+                    if affordable(mid) {
+                        lo = mid;
+                    } else {
+                        hi = mid;
+                    }
+                }
+                Ok(lo)
+            }
+        }
+    }
+
+    fn simulate(&self, cycle: &[(usize, OrderType)], amount_in: f64) -> Option<Opportunity> {
+        let mut amount = amount_in;
+        let mut legs = Vec::with_capacity(cycle.len());
+        for (venue, order_type) in cycle {
+            let out = self.simulate_leg(*venue, *order_type, amount).ok()?;
+            legs.push(Leg {
+                venue: *venue,
+                order_type: *order_type,
+                amount_in: amount,
+                amount_out: out,
+            });
+            amount = out;
+        }
+        Some(Opportunity {
+            legs,
+            amount_in,
+            amount_out: amount,
+        })
+    }
+
+    /// Most profitable size of the cycle up to `capital`, by golden section
+    /// search on the profit, which is concave for convex curves.
+    fn size_cycle(&self, cycle: &[(usize, OrderType)], capital: f64) -> Option<Opportunity> {
+        let profit = |x: f64| self.simulate(cycle, x).map_or(f64::MIN, |o| o.profit());
+        let ratio = (5f64.sqrt() - 1.) / 2.;
+        let (mut lo, mut hi) = (0., capital);
+        for _ in 0..SEARCH_STEPS {
+            let a = hi - ratio * (hi - lo);
+            let b = lo + ratio * (hi - lo);
+            if profit(a) < profit(b) {
+                lo = a;
+            } else {
+                hi = b;
+            }
+        }
+        self.simulate(cycle, 0.5 * (lo + hi))
+    }
+
+    fn describe(&self, legs: &[(usize, OrderType)]) -> String {
+        legs.iter()
+            .map(|(v, t)| format!("{:?} on {}", t, self.venues[*v].1.name))
+            .collect::<Vec<_>>()
+            .join(" -> ")
+    }
+
+    /// Profitable opportunities within `capital`, the most profitable first.
+    pub fn find_opportunities(&self, capital: f64) -> Vec<Opportunity> {
+        let mut result = vec![];
+        for cycle in self.find_cycles() {
+            let description = self.describe(&cycle);
+            match self.size_cycle(&cycle, capital) {
+                Some(o) if o.profit() > self.min_profit => {
+                    info!(
+                        "arbitrage: found {} spending {} for profit {}",
+                        description,
+                        o.amount_in,
+                        o.profit()
+                    );
+                    result.push(o);
+                }
+                Some(o) => debug!(
+                    "arbitrage: skipped {}, profit {} is below {} after fees",
+                    description,
+                    o.profit(),
+                    self.min_profit
+                ),
+                None => debug!("arbitrage: skipped {}, venues can not fill it", description),
+            }
+        }
+        result.sort_by(|a, b| b.profit().total_cmp(&a.profit()));
+        result
+    }
+
+    fn execute(&mut self, opportunity: &Opportunity) -> Result<f64, Error> {
+        let mut amount = opportunity.amount_in;
+        for (i, leg) in opportunity.legs.iter().enumerate() {
+            let (venue, info) = &mut self.venues[leg.venue];
+            let result = match leg.order_type {
+                OrderType::Sell => venue
+                    .execute(OrderType::Sell, amount)
+                    .map(|(_, out)| out * (1. - info.fee)),
+                OrderType::Buy => venue
+                    .execute(OrderType::Buy, leg.amount_out)
+                    .map(|(bought, _)| bought),
+            };
+            amount = match result {
+                Ok(out) => out,
+                Err(e) => {
+                    warn!(
+                        "arbitrage: aborted at leg {} on {}, holding {} of the input: {}",
+                        i, info.name, amount, e
+                    );
+                    return Err(e.into());
+                }
+            };
+        }
+        Ok(amount)
+    }
+
+    /// Execute the most profitable cycles until none is left, or the capital
+    /// limit is reached. Returns the executed opportunities.
+    pub fn rebalance(&mut self) -> Result<Vec<Opportunity>, Error> {
+        let mut spent = 0.;
+        let mut executed = vec![];
+        for _ in 0..MAX_ROUNDS {
+            let capital = self.capital_limit - spent;
+            if capital <= 0. {
+                info!("arbitrage: capital limit {} reached", self.capital_limit);
+                break;
+            }
+            let best = match self.find_opportunities(capital).into_iter().next() {
+                Some(o) => o,
+                None => {
+                    info!("arbitrage: no profitable opportunity left");
+                    break;
+                }
+            };
+            let received = self.execute(&best)?;
+            info!(
+                "arbitrage: executed {} legs, spent {} and received {}",
+                best.legs.len(),
+                best.amount_in,
+                received
+            );
+            spent += best.amount_in;
+            executed.push(best);
+        }
+        Ok(executed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amplify::Wrapper;
+
+    use super::{Arbitrageur, VenueInfo};
+    use crate::{
+        cfmm::{cpmm::ConstantProductMarketMaker, fpmm::FixedProductMarketMaker},
+        cost_function::lmsr::LMScoringRule,
+        matching::{FixedProductVenue, OutcomeVenue, PoolVenue, Venue},
+        AssetId, AssetInfo,
+    };
+
+    fn pool(base: u64, quote: u64) -> PoolVenue<ConstantProductMarketMaker> {
+        PoolVenue(ConstantProductMarketMaker::new(
+            AssetInfo::new(AssetId::from_inner([0; 32]), base, "A".to_owned()),
+            AssetInfo::new(AssetId::from_inner([1; 32]), quote, "B".to_owned()),
+        ))
+    }
+
+    fn info(name: &str, fee: f64) -> VenueInfo {
+        VenueInfo {
+            name: name.to_owned(),
+            traded: AssetId::from_inner([1; 32]),
+            counter: AssetId::from_inner([0; 32]),
+            fee,
+        }
+    }
+
+    #[test]
+    fn rebalance_must_close_the_price_gap_within_capital() {
+        let mut arb = Arbitrageur::try_create(AssetId::from_inner([0; 32]), 50.).unwrap();
+        arb.add_venue(pool(4000, 1000), info("cheap", 0.001))
+            .unwrap();
+        arb.add_venue(pool(5000, 1000), info("expensive", 0.001))
+            .unwrap();
+        let executed = arb.rebalance().unwrap();
+        let spent: f64 = executed.iter().map(|o| o.amount_in).sum();
+        assert!(!executed.is_empty());
+        assert!(spent <= 50. + 1e-9);
+        assert!(executed.iter().all(|o| o.profit() > 0.));
+        let gap = arb.venue(1).unwrap().marginal_price() - arb.venue(0).unwrap().marginal_price();
+        assert!(gap > 0. && gap < 1.);
+    }
+
+    #[test]
+    fn fees_must_be_considered() {
+        let mut arb = Arbitrageur::try_create(AssetId::from_inner([0; 32]), 50.).unwrap();
+        arb.add_venue(pool(4000, 1000), info("cheap", 0.2)).unwrap();
+        arb.add_venue(pool(4400, 1000), info("expensive", 0.2))
+            .unwrap();
+        assert!(arb.find_opportunities(50.).is_empty());
+    }
+
+    #[test]
+    fn prices_must_converge_across_market_maker_families() {
+        // The outcome token is the traded asset of every venue.
+        let lmsr =
+            OutcomeVenue::try_create(LMScoringRule::try_create(2, 100.).unwrap(), 0).unwrap();
+        let mut fpmm = FixedProductMarketMaker::try_create(2, 0.).unwrap();
+        fpmm.add_liquidity("lp".to_owned(), 1000.).unwrap();
+        fpmm.buy(300., 0, 0.).unwrap();
+        let fpmm = FixedProductVenue::try_create(fpmm, 0).unwrap();
+        let mut arb: Arbitrageur<Box<dyn Venue>> =
+            Arbitrageur::try_create(AssetId::from_inner([0; 32]), 100.).unwrap();
+        arb.add_venue(Box::new(lmsr), info("lmsr", 0.)).unwrap();
+        arb.add_venue(Box::new(pool(4000, 10000)), info("cpmm", 0.))
+            .unwrap();
+        arb.add_venue(Box::new(fpmm), info("fpmm", 0.)).unwrap();
+        let spread = |arb: &Arbitrageur<Box<dyn Venue>>| {
+            let prices = (0..3)
+                .map(|v| arb.venue(v).unwrap().marginal_price())
+                .collect::<Vec<_>>();
+            prices.iter().cloned().fold(f64::MIN, f64::max)
+                - prices.iter().cloned().fold(f64::MAX, f64::min)
+        };
+        let before = spread(&arb);
+        assert!(arb.venue(2).unwrap().marginal_price() > 0.6);
+        let executed = arb.rebalance().unwrap();
+        assert!(executed.iter().all(|o| o.profit() > 0.));
+        // Both cheaper venues have been bought from and sold to the FPMM.
+        for venue in [0, 1] {
+            assert!(executed
+                .iter()
+                .any(|o| o.legs[0].venue == venue && o.legs[1].venue == 2));
+        }
+        assert!(spread(&arb) < before);
+    }
+}
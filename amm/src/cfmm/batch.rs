@@ -0,0 +1,490 @@
+//! Batch auction for CFMM pools.
+//!
+//! When orders are processed one at a time, whoever lands first gets the
+//! better price, so traders race each other. In batch mode, orders are
+//! collected over an interval and all of them are cleared at one uniform
+//! price. Opposite orders are matched against each other first, and only the
+//! net imbalance is sent to the pool.
+//!
+//! The clearing is a pure function of the pool state and the batch contents.
+//! Orders are sorted into a canonical order before any arithmetic, so the
+//! result does not depend on the order of submission.
+//!
+//! The pool only trades whole units, so the imbalance sent to it is rounded
+//! down. The remainder, less than a unit of the quote asset, is not filled:
+//! the orders on the side of the imbalance are filled pro rata to what the
+//! pool takes, at the price it actually gives. Every asset which goes in the
+//! batch goes out of it.
+
+use std::cmp::Ordering;
+
+use super::{
+    twap::Timestamp, AssetIndex, ConstantFunctionMarketMaker, Error as CFMMError, OrderInfo,
+    OrderType,
+};
+
+/// Maximum number of iterations to find the clearing price.
+const MAX_ITERATIONS: usize = 100;
+
+/// Index of the order inside the batch.
+pub type BatchOrderId = u64;
+
+/// Settlement of a single order in the batch.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fill {
+    pub id: BatchOrderId,
+    pub order: OrderInfo,
+    /// Amount of the asset of the order which is filled. Less than the order
+    /// only on the side of the imbalance, the rest stays with the user.
+    pub filled: f64,
+    /// Amount of the counter asset the user receives (for `Sell`) or pays
+    /// (for `Buy`), for the filled amount.
+    pub counter_amount: f64,
+}
+
+/// Result of the batch auction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchClearing {
+    /// Uniform price for every order in the batch, in the same unit as
+    /// `ConstantFunctionMarketMaker::spot_price`. It is the average price of
+    /// `net_order`, or the spot price when nothing is sent to the pool.
+    pub price: f64,
+    /// Volume (in quote asset) matched between traders, without touching
+    /// the pool.
+    pub matched_volume: f64,
+    /// Order sent to the pool for the net imbalance, if any.
+    pub net_order: Option<OrderInfo>,
+    pub fills: Vec<Fill>,
+}
+
+/// Sum of every order in the batch, by the side and the asset.
+#[derive(Clone, Copy, Debug, Default)]
+struct Totals {
+    base_in: f64,
+    base_out: f64,
+    quote_in: f64,
+    quote_out: f64,
+}
+
+impl Totals {
+    /// Net quote asset which must be sold to the pool at `price`.
+    /// Negative value means the pool must provide quote asset.
+    fn net_quote(&self, price: f64) -> f64 {
+        (self.quote_in - self.quote_out) + (self.base_out - self.base_in) / price
+    }
+
+    /// Quote asset brought by the side of the imbalance at `price`, that is
+    /// by the sellers of the quote asset and the buyers of the base asset
+    /// when `net_quote` is positive.
+    fn excess(&self, price: f64, net_quote: f64) -> f64 {
+        if net_quote > 0. {
+            self.quote_in + self.base_out / price
+        } else {
+            self.quote_out + self.base_in / price
+        }
+    }
+
+    fn matched_volume(&self, price: f64) -> f64 {
+        let supply = self.quote_in + self.base_out / price;
+        let demand = self.quote_out + self.base_in / price;
+        supply.min(demand)
+    }
+}
+
+fn canonical_cmp(a: &OrderInfo, b: &OrderInfo) -> Ordering {
+    (a.order_type, a.index)
+        .cmp(&(b.order_type, b.index))
+        .then(a.amount.units().cmp(&b.amount.units()))
+}
+
+fn counter_amount(index: AssetIndex, amount: f64, price: f64) -> f64 {
+    match index {
+        AssetIndex::Zero => amount / price,
+        AssetIndex::One => amount * price,
+    }
+}
+
+/// Whether the order is on the side of the imbalance.
+fn is_excess(order: &OrderInfo, net_quote: f64) -> bool {
+    let brings_quote = matches!(
+        (order.index, order.order_type),
+        (AssetIndex::One, OrderType::Sell) | (AssetIndex::Zero, OrderType::Buy)
+    );
+    brings_quote == (net_quote > 0.)
+}
+
+fn units(order: &Option<OrderInfo>) -> u64 {
+    order.as_ref().map_or(0, |o| o.amount.units())
+}
+
+/// Order to send to the pool for the net imbalance, rounded down to whole
+/// units, and the average price at which the pool moves its reserves for it.
+/// The spot price when the imbalance is less than a unit.
+fn pool_order<P: ConstantFunctionMarketMaker>(
+    pool: &P,
+    net_quote: f64,
+) -> Result<(Option<OrderInfo>, f64), CFMMError> {
+    let units = net_quote.abs().floor();
+    if units < 1. {
+        return Ok((None, pool.spot_price()));
+    }
+    let order_type = if net_quote > 0. {
+        OrderType::Sell
+    } else {
+        OrderType::Buy
+    };
+    let order = OrderInfo::try_from_f64(pool, AssetIndex::One, units, order_type)?;
+    // The pool moves the base reserve by whole units too.
+    let base_amount = pool.price_for_order(&order)?.round();
+    let price = base_amount / order.quantity();
+    Ok((Some(order), price))
+}
+
+/// Compute the uniform clearing price of the batch against the pool.
+/// This does not modify the pool.
+pub fn compute_clearing<P: ConstantFunctionMarketMaker>(
+    pool: &P,
+    orders: &[(BatchOrderId, OrderInfo)],
+) -> Result<BatchClearing, CFMMError> {
+    let mut sorted = orders.iter().collect::<Vec<_>>();
+    sorted.sort_by(|(id_a, a), (id_b, b)| canonical_cmp(a, b).then(id_a.cmp(id_b)));
+
+    let mut totals = Totals::default();
+    for (_, o) in &sorted {
+        if pool.index_of(o.id())? != o.index {
+            return Err(CFMMError::UnknownAssetId);
+        }
+        if o.amount.is_zero() {
+            return Err(CFMMError::InvalidOrderAmount);
+        }
+        let amount = o.quantity();
+        match (o.index, o.order_type) {
+            (AssetIndex::Zero, OrderType::Sell) => totals.base_in += amount,
+            (AssetIndex::Zero, OrderType::Buy) => totals.base_out += amount,
+            (AssetIndex::One, OrderType::Sell) => totals.quote_in += amount,
+            (AssetIndex::One, OrderType::Buy) => totals.quote_out += amount,
+        }
+    }
+
+    // Fixed-point iteration: the net imbalance depends on the price, and
+    // the price is the average price the pool gives for the imbalance. The
+    // imbalance is rounded to units, so the iteration stops once the order
+    // repeats. When it alternates between two orders, the smaller one is
+    // kept, as the imbalance at its price covers it.
+    let (mut net_order, mut price) = (None, pool.spot_price());
+    let mut previous = None;
+    let mut converged = false;
+    for _ in 0..MAX_ITERATIONS {
+        let (order, new_price) = pool_order(pool, totals.net_quote(price))?;
+        if order == net_order {
+            converged = true;
+            break;
+        }
+        if previous.as_ref() == Some(&order) {
+            if units(&order) < units(&net_order) {
+                net_order = order;
+                price = new_price;
+            }
+            converged = true;
+            break;
+        }
+        previous = Some(std::mem::replace(&mut net_order, order));
+        price = new_price;
+    }
+    if !converged || !price.is_finite() || price <= 0. {
+        return Err(CFMMError::ClearingPriceNotFound);
+    }
+
+    // The part of the imbalance the pool does not take, less than a unit
+    // unless the order alternated.
+    let net_quote = totals.net_quote(price);
+    let sent = units(&net_order) as f64;
+    let same_side = match &net_order {
+        Some(order) => (order.order_type == OrderType::Sell) == (net_quote > 0.),
+        None => true,
+    };
+    if !same_side || net_quote.abs() < sent {
+        return Err(CFMMError::ClearingPriceNotFound);
+    }
+    let remainder = net_quote.abs() - sent;
+    let excess = totals.excess(price, net_quote);
+    let ratio = if remainder > 0. {
+        1. - remainder / excess
+    } else {
+        1.
+    };
+
+    let fills = orders
+        .iter()
+        .map(|(id, order)| {
+            let filled = if is_excess(order, net_quote) {
+                order.quantity() * ratio
+            } else {
+                order.quantity()
+            };
+            Fill {
+                id: *id,
+                order: order.clone(),
+                filled,
+                counter_amount: counter_amount(order.index, filled, price),
+            }
+        })
+        .collect();
+    Ok(BatchClearing {
+        price,
+        matched_volume: totals.matched_volume(price),
+        net_order,
+        fills,
+    })
+}
+
+/// Pool which processes orders in batches.
+#[derive(Clone, Debug)]
+pub struct BatchAuction<P> {
+    pool: P,
+    /// Length of each batch in seconds.
+    interval: u64,
+    batch_start: Timestamp,
+    orders: Vec<(BatchOrderId, OrderInfo)>,
+    next_id: BatchOrderId,
+}
+
+impl<P: ConstantFunctionMarketMaker> BatchAuction<P> {
+    pub fn new(pool: P, interval: u64, batch_start: Timestamp) -> Self {
+        Self {
+            pool,
+            interval,
+            batch_start,
+            orders: vec![],
+            next_id: 0,
+        }
+    }
+
+    pub fn pool(&self) -> &P {
+        &self.pool
+    }
+
+    pub fn pending_orders(&self) -> &[(BatchOrderId, OrderInfo)] {
+        &self.orders
+    }
+
+    pub fn is_due(&self, now: Timestamp) -> bool {
+        now >= self.batch_start + self.interval
+    }
+
+    /// Add the order to the current batch. The order is rejected if the
+    /// batch could not be cleared with it, e.g. when the pool does not have
+    /// the reserves for the imbalance.
+    pub fn submit(&mut self, order: OrderInfo) -> Result<BatchOrderId, CFMMError> {
+        let id = self.next_id;
+        let mut orders = self.orders.clone();
+        orders.push((id, order));
+        compute_clearing(&self.pool, &orders)?;
+        self.next_id += 1;
+        self.orders = orders;
+        Ok(id)
+    }
+
+    /// Remove the order from the current batch, and return it.
+    pub fn cancel(&mut self, id: BatchOrderId) -> Result<OrderInfo, CFMMError> {
+        let position = self
+            .orders
+            .iter()
+            .position(|(order_id, _)| *order_id == id)
+            .ok_or(CFMMError::UnknownOrder)?;
+        Ok(self.orders.remove(position).1)
+    }
+
+    /// Clear the current batch, send the net imbalance to the pool, and start
+    /// a new batch. When the batch can not be cleared, e.g. after a
+    /// cancellation left an imbalance the pool can not take, the orders are
+    /// kept so that they can be cancelled.
+    pub fn clear(&mut self, now: Timestamp) -> Result<BatchClearing, CFMMError> {
+        if !self.is_due(now) {
+            return Err(CFMMError::BatchNotReady);
+        }
+        let clearing = compute_clearing(&self.pool, &self.orders)?;
+        if let Some(order) = &clearing.net_order {
+            self.pool.order(order)?;
+        }
+        self.orders.clear();
+        self.batch_start = now;
+        Ok(clearing)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amplify::Wrapper;
+
+    use super::{compute_clearing, BatchAuction, BatchClearing, Fill};
+    use crate::{
+        cfmm::{
+            cpmm::ConstantProductMarketMaker, AssetIndex, ConstantFunctionMarketMaker, Error,
+            OrderInfo, OrderType,
+        },
+        Amount, AssetId, AssetInfo,
+    };
+
+    fn pool() -> ConstantProductMarketMaker {
+        ConstantProductMarketMaker::new(
+            AssetInfo::new(AssetId::from_inner([0; 32]), 1000, "A".to_owned()),
+            AssetInfo::new(AssetId::from_inner([1; 32]), 1000, "B".to_owned()),
+        )
+    }
+
+    fn order(index: AssetIndex, units: u64, order_type: OrderType) -> OrderInfo {
+        let id = AssetId::from_inner([index as u8; 32]);
+        OrderInfo::new(index, Amount::new(id, units), order_type)
+    }
+
+    /// Base and quote asset which go in the batch, less what goes out of it.
+    fn imbalance(clearing: &BatchClearing) -> (f64, f64) {
+        let (mut base, mut quote) = (0., 0.);
+        for Fill {
+            order,
+            filled,
+            counter_amount,
+            ..
+        } in &clearing.fills
+        {
+            let (filled, counter) = match order.order_type() {
+                OrderType::Sell => (*filled, -counter_amount),
+                OrderType::Buy => (-filled, *counter_amount),
+            };
+            match order.index() {
+                AssetIndex::Zero => {
+                    base += filled;
+                    quote += counter
+                }
+                AssetIndex::One => {
+                    quote += filled;
+                    base += counter
+                }
+            }
+        }
+        if let Some(order) = &clearing.net_order {
+            quote -= order.quantity();
+            base += clearing.price * order.quantity();
+        }
+        (base, quote)
+    }
+
+    #[test]
+    fn batch_must_clear_at_the_price_of_the_pool() {
+        let pool = pool();
+        let orders = vec![
+            (0, order(AssetIndex::One, 300, OrderType::Sell)),
+            (1, order(AssetIndex::Zero, 100, OrderType::Sell)),
+        ];
+        let clearing = compute_clearing(&pool, &orders).unwrap();
+
+        // The pool gives 153 base units for the 181 quote units of the
+        // imbalance, every order is cleared at that price.
+        let net_order = clearing.net_order.clone().unwrap();
+        assert_eq!(net_order, order(AssetIndex::One, 181, OrderType::Sell));
+        assert_eq!(pool.price_for_order(&net_order).unwrap().round(), 153.);
+        assert_eq!(clearing.price, 153. / 181.);
+
+        // The base seller is filled, the quote seller is filled for what the
+        // base seller and the pool take.
+        let base_seller = &clearing.fills[1];
+        assert_eq!(base_seller.filled, 100.);
+        assert!((base_seller.counter_amount - 100. / clearing.price).abs() < 1e-9);
+        let quote_seller = &clearing.fills[0];
+        assert!(quote_seller.filled < 300.);
+        assert!((quote_seller.filled - 181. - base_seller.counter_amount).abs() < 1e-9);
+        assert!((quote_seller.counter_amount - 253.).abs() < 1e-9);
+
+        let (base, quote) = imbalance(&clearing);
+        assert!(base.abs() < 1e-9 && quote.abs() < 1e-9);
+    }
+
+    #[test]
+    fn batch_must_balance_when_the_pool_is_not_used() {
+        let pool = ConstantProductMarketMaker::new(
+            AssetInfo::new(AssetId::from_inner([0; 32]), 1000, "A".to_owned()),
+            AssetInfo::new(AssetId::from_inner([1; 32]), 2500, "B".to_owned()),
+        );
+        // Half a unit of the quote asset is left over at the spot price.
+        let orders = vec![
+            (0, order(AssetIndex::One, 3, OrderType::Sell)),
+            (1, order(AssetIndex::Zero, 1, OrderType::Sell)),
+        ];
+        let clearing = compute_clearing(&pool, &orders).unwrap();
+        assert_eq!(clearing.net_order, None);
+        assert_eq!(clearing.price, pool.spot_price());
+        let (base, quote) = imbalance(&clearing);
+        assert!(base.abs() < 1e-9 && quote.abs() < 1e-9);
+        assert_eq!(clearing.fills[0].filled, 2.5);
+        assert_eq!(clearing.fills[1].filled, 1.);
+    }
+
+    #[test]
+    fn clearing_must_not_depend_on_submission_order() {
+        let pool = pool();
+        let orders = vec![
+            order(AssetIndex::One, 300, OrderType::Sell),
+            order(AssetIndex::Zero, 100, OrderType::Sell),
+            order(AssetIndex::Zero, 17, OrderType::Buy),
+            order(AssetIndex::One, 40, OrderType::Buy),
+        ];
+        let mut forward = BatchAuction::new(pool.clone(), 10, 0);
+        let mut backward = BatchAuction::new(pool, 10, 0);
+        for o in &orders {
+            forward.submit(o.clone()).unwrap();
+        }
+        for o in orders.iter().rev() {
+            backward.submit(o.clone()).unwrap();
+        }
+        let expected = compute_clearing(forward.pool(), forward.pending_orders()).unwrap();
+        let forward = forward.clear(10).unwrap();
+        let backward = backward.clear(10).unwrap();
+        assert_eq!(forward, expected);
+        assert_eq!(forward.price, backward.price);
+        assert_eq!(forward.net_order, backward.net_order);
+        for fill in &forward.fills {
+            let other = backward
+                .fills
+                .iter()
+                .find(|other| other.order == fill.order)
+                .unwrap();
+            assert_eq!(fill.filled, other.filled);
+            assert_eq!(fill.counter_amount, other.counter_amount);
+        }
+    }
+
+    #[test]
+    fn batch_must_be_cleared_after_its_interval() {
+        let mut auction = BatchAuction::new(pool(), 10, 0);
+        let id = auction
+            .submit(order(AssetIndex::One, 50, OrderType::Sell))
+            .unwrap();
+        assert_eq!(auction.clear(9), Err(Error::BatchNotReady));
+        assert_eq!(auction.pending_orders().len(), 1);
+
+        let clearing = auction.clear(10).unwrap();
+        assert_eq!(clearing.fills[0].id, id);
+        assert!(auction.pending_orders().is_empty());
+        assert_eq!(auction.clear(15), Err(Error::BatchNotReady));
+    }
+
+    #[test]
+    fn unfillable_orders_must_be_rejected_and_orders_cancelled() {
+        let mut auction = BatchAuction::new(pool(), 10, 0);
+        assert!(auction
+            .submit(order(AssetIndex::Zero, 1000, OrderType::Buy))
+            .is_err());
+        assert!(auction.pending_orders().is_empty());
+
+        let id = auction
+            .submit(order(AssetIndex::Zero, 10, OrderType::Buy))
+            .unwrap();
+        assert_eq!(
+            auction.cancel(id),
+            Ok(order(AssetIndex::Zero, 10, OrderType::Buy))
+        );
+        assert_eq!(auction.cancel(id), Err(Error::UnknownOrder));
+        assert!(auction.pending_orders().is_empty());
+    }
+}
@@ -1,37 +1,33 @@
-use noisy_float::types::R64;
+use std::convert::TryFrom;
 
-use crate::{
-    cfmm::ConstantFunctionMarketMaker, cost_function::PurchaseError, utils::FinitePositiveFloat,
-    AssetId, AssetInfo,
-};
+use crate::{cfmm::ConstantFunctionMarketMaker, utils::FinitePositiveFloat, Amount, AssetInfo};
 
-use super::{Error as CFMMError, OrderInfo};
+use super::{Error as CFMMError, OrderInfo, VirtualTrades};
 
 /// `ConstantProductMarketMaker` was originally used in Uniswap V2.
 /// It has following advantages
 /// 1. users can dynamically crowdfund an asset to trade
 /// 2. Amount of the trade is bounded, so the server can never be out of funds.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct ConstantProductMarketMaker {
     base_asset: AssetInfo,
     quote_asset: AssetInfo,
 }
 
 impl ConstantProductMarketMaker {
-    fn k(&self) -> R64 {
-        self.base_asset.amount * self.quote_asset.amount
+    pub fn new(base_asset: AssetInfo, quote_asset: AssetInfo) -> Self {
+        Self {
+            base_asset,
+            quote_asset,
+        }
     }
 
-    fn is_base_buy(&self, order: &OrderInfo) -> bool {
-        if order.is_buy() {
-            &self.base_asset.id == &order.id
-        } else {
-            &self.base_asset.id != &order.id
-        }
+    fn k(&self) -> f64 {
+        self.base_asset.reserve() * self.quote_asset.reserve()
     }
 
     pub fn price(&self) -> f64 {
-        (self.base_asset.amount / self.quote_asset.amount).into()
+        self.base_asset.reserve() / self.quote_asset.reserve()
     }
 }
 
@@ -51,29 +47,76 @@ impl ConstantFunctionMarketMaker for ConstantProductMarketMaker {
         &mut self.quote_asset
     }
 
-    fn price_for_order(&self, order: &OrderInfo) -> f64 {
-        let k = self.k();
-        let amount_before = self.asset_by_index(order.index).amount;
-        let y = k
-            / (if order.is_buy() {
-                amount_before - order.amount
-            } else {
-                amount_before + order.amount
-            });
-        y.into()
+    fn spot_price(&self) -> f64 {
+        self.price()
     }
 
-    /// Move the internal reserves, and returns the amount that user will get.
-    fn order(&mut self, order: &OrderInfo) -> f64 {
-        let amount_y = self.price_for_order(&order);
-        if self.is_base_buy(&order) {
-            self.base_asset.amount -= order.amount;
-            self.quote_asset.amount += amount_y;
+    fn price_for_order(&self, order: &OrderInfo) -> Result<f64, CFMMError> {
+        if order.amount.is_zero() {
+            return Err(CFMMError::InvalidOrderAmount);
+        }
+        let k = self.k();
+        let amount = order.quantity();
+        let amount_before = self.asset_by_index(order.index).reserve();
+        let other_before = self.asset_by_index(order.index.other()).reserve();
+        if order.is_buy() {
+            if amount >= amount_before {
+                return Err(CFMMError::InsufficientLiquidity);
+            }
+            Ok(k / (amount_before - amount) - other_before)
         } else {
-            self.base_asset.amount += order.amount;
-            self.quote_asset.amount -= amount_y;
+            Ok(other_before - k / (amount_before + amount))
         }
+    }
+
+    /// Move the internal reserves, and returns the amount of the counter asset
+    /// that the user will get (for sell) or pay (for buy).
+    fn order(&mut self, order: &OrderInfo) -> Result<f64, CFMMError> {
+        let amount_y = self.price_for_order(order)?;
+        self.apply_order(order, amount_y)?;
+        Ok(amount_y)
+    }
 
-        amount_y
+    /// Closed-form solution from the TWAMM paper (Paradigm, 2021).
+    fn execute_virtual_orders(
+        &mut self,
+        base_in: f64,
+        quote_in: f64,
+    ) -> Result<VirtualTrades, CFMMError> {
+        if FinitePositiveFloat::try_from(base_in).is_err()
+            || FinitePositiveFloat::try_from(quote_in).is_err()
+        {
+            return Err(CFMMError::InvalidOrderAmount);
+        }
+        let x = self.base_asset.reserve();
+        let y = self.quote_asset.reserve();
+        let k = x * y;
+        let (x_end, y_end) = if base_in == 0. && quote_in == 0. {
+            (x, y)
+        } else if quote_in == 0. {
+            (x + base_in, k / (x + base_in))
+        } else if base_in == 0. {
+            (k / (y + quote_in), y + quote_in)
+        } else {
+            let a = (x * quote_in).sqrt();
+            let b = (y * base_in).sqrt();
+            let c = (a - b) / (a + b);
+            // `(e + c) / (e - c)` where `e = exp(2 * sqrt(base_in * quote_in / k))`,
+            // written so that it does not overflow.
+            let r = c * (-2. * (base_in * quote_in / k).sqrt()).exp();
+            let x_end = (k * base_in / quote_in).sqrt() * (1. + r) / (1. - r);
+            (x_end, k / x_end)
+        };
+        // Both reserves move, or none of them.
+        let x_end = Amount::from_f64(self.base_asset.id().clone(), x_end)?;
+        let y_end = Amount::from_f64(self.quote_asset.id().clone(), y_end)?;
+        let trades = VirtualTrades {
+            base_out: x + base_in - x_end.to_f64(),
+            quote_out: y + quote_in - y_end.to_f64(),
+            ..VirtualTrades::default()
+        };
+        self.base_asset.amount = x_end;
+        self.quote_asset.amount = y_end;
+        Ok(trades)
     }
 }
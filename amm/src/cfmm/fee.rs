@@ -0,0 +1,348 @@
+//! Trading fees for CFMM pools.
+//!
+//! `FeePool` wraps any `ConstantFunctionMarketMaker` and charges the fee
+//! decided by a `FeePolicy`. Collected fees stay in the pool's reserves, so
+//! they go to liquidity providers.
+//!
+//! A fixed fee either loses money to arbitrageurs in volatile periods, or
+//! scares off volume when the market is calm. `DynamicFee` moves the fee
+//! with recently observed volatility and order flow imbalance.
+//!
+//! Fee policies plug into every `ConstantFunctionMarketMaker`. The FPMM of
+//! `fpmm` is not one: it holds a reserve per outcome rather than a base and a
+//! quote asset, so it has no single spot price or order direction for a
+//! `FeePolicy` to observe. It charges its own fixed `fee` instead, which is
+//! accrued to its liquidity providers by share.
+
+use super::{AssetIndex, ConstantFunctionMarketMaker, Error as CFMMError, OrderInfo};
+use crate::{Amount, AssetInfo};
+
+/// Decides the fee of the pool.
+pub trait FeePolicy {
+    /// Fee rate for the next order, in `[0, 1)`.
+    fn current_fee(&self) -> f64;
+
+    /// Called after every executed order with the spot price before and
+    /// after the order.
+    fn on_order(&mut self, order: &OrderInfo, price_before: f64, price_after: f64);
+}
+
+/// Fee which never changes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FixedFee(f64);
+
+impl FixedFee {
+    pub fn try_create(fee: f64) -> Result<Self, CFMMError> {
+        if !fee.is_finite() || fee.is_sign_negative() || fee >= 1. {
+            return Err(CFMMError::InvalidFeeParam);
+        }
+        Ok(Self(fee))
+    }
+}
+
+impl FeePolicy for FixedFee {
+    fn current_fee(&self) -> f64 {
+        self.0
+    }
+
+    fn on_order(&mut self, _order: &OrderInfo, _price_before: f64, _price_after: f64) {}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DynamicFeeConfig {
+    /// Fee when there is no volatility and no imbalance.
+    pub base_fee: f64,
+    pub min_fee: f64,
+    pub max_fee: f64,
+    /// Fee added per unit of volatility (standard deviation of log returns
+    /// per order).
+    pub volatility_factor: f64,
+    /// Fee added per unit of order flow imbalance, which is in `[0, 1]`.
+    pub imbalance_factor: f64,
+    /// Weight of the past observations in exponential moving average, in
+    /// `[0, 1)`. Larger value means slower reaction.
+    pub decay: f64,
+}
+
+impl Default for DynamicFeeConfig {
+    fn default() -> Self {
+        Self {
+            base_fee: 0.003,
+            min_fee: 0.0005,
+            max_fee: 0.03,
+            volatility_factor: 0.5,
+            imbalance_factor: 0.005,
+            decay: 0.9,
+        }
+    }
+}
+
+/// Fee which moves with recent volatility and order flow imbalance.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DynamicFee {
+    config: DynamicFeeConfig,
+    /// Exponential moving average of squared log returns.
+    variance: f64,
+    /// Exponential moving average of order direction. Positive when users
+    /// are mostly selling the base asset.
+    imbalance: f64,
+}
+
+impl DynamicFee {
+    pub fn try_create(config: DynamicFeeConfig) -> Result<Self, CFMMError> {
+        let c = &config;
+        let is_valid = [c.base_fee, c.min_fee, c.max_fee, c.volatility_factor]
+            .iter()
+            .chain(&[c.imbalance_factor, c.decay])
+            .all(|v| v.is_finite() && !v.is_sign_negative())
+            && c.min_fee <= c.base_fee
+            && c.base_fee <= c.max_fee
+            && c.max_fee < 1.
+            && c.decay < 1.;
+        if !is_valid {
+            return Err(CFMMError::InvalidFeeParam);
+        }
+        Ok(Self {
+            config,
+            variance: 0.,
+            imbalance: 0.,
+        })
+    }
+
+    pub fn config(&self) -> &DynamicFeeConfig {
+        &self.config
+    }
+
+    pub fn volatility(&self) -> f64 {
+        self.variance.sqrt()
+    }
+
+    pub fn imbalance(&self) -> f64 {
+        self.imbalance
+    }
+}
+
+impl FeePolicy for DynamicFee {
+    fn current_fee(&self) -> f64 {
+        let c = &self.config;
+        let fee = c.base_fee
+            + c.volatility_factor * self.volatility()
+            + c.imbalance_factor * self.imbalance.abs();
+        fee.max(c.min_fee).min(c.max_fee)
+    }
+
+    fn on_order(&mut self, order: &OrderInfo, price_before: f64, price_after: f64) {
+        let decay = self.config.decay;
+        if price_before > 0. && price_after > 0. {
+            let log_return = (price_after / price_before).ln();
+            if log_return.is_finite() {
+                self.variance = decay * self.variance + (1. - decay) * log_return.powi(2);
+            }
+        }
+        let is_base_in = (order.index == AssetIndex::Zero) != order.is_buy();
+        let direction = if is_base_in { 1. } else { -1. };
+        self.imbalance = decay * self.imbalance + (1. - decay) * direction;
+    }
+}
+
+/// Quote for an order, including the fee.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FeeQuote {
+    /// Amount of the counter asset, same as
+    /// `ConstantFunctionMarketMaker::price_for_order`.
+    pub amount: f64,
+    /// Fee rate applied to this order.
+    pub fee: f64,
+    /// Fee paid in the asset the user sends to the pool.
+    pub fee_amount: f64,
+}
+
+/// Pool which charges fees decided by `F`.
+#[derive(Clone, Debug)]
+pub struct FeePool<P, F> {
+    pool: P,
+    policy: F,
+}
+
+impl<P: ConstantFunctionMarketMaker, F: FeePolicy> FeePool<P, F> {
+    pub fn new(pool: P, policy: F) -> Self {
+        Self { pool, policy }
+    }
+
+    pub fn pool(&self) -> &P {
+        &self.pool
+    }
+
+    pub fn policy(&self) -> &F {
+        &self.policy
+    }
+
+    pub fn current_fee(&self) -> f64 {
+        self.policy.current_fee()
+    }
+
+    /// Order sent to the inner pool, and the fee paid by the user.
+    fn quote_inner(&self, order: &OrderInfo) -> Result<(FeeQuote, OrderInfo), CFMMError> {
+        if order.amount.is_zero() {
+            return Err(CFMMError::InvalidOrderAmount);
+        }
+        let fee = self.policy.current_fee();
+        if order.is_buy() {
+            let cost = self.pool.price_for_order(order)?;
+            let amount = cost / (1. - fee);
+            let quote = FeeQuote {
+                amount,
+                fee,
+                fee_amount: amount - cost,
+            };
+            Ok((quote, order.clone()))
+        } else {
+            let fee_amount = Amount::from_f64(order.id().clone(), order.quantity() * fee)?;
+            let mut inner_order = order.clone();
+            inner_order.amount = order.amount.checked_sub(&fee_amount)?;
+            let amount = self.pool.price_for_order(&inner_order)?;
+            let quote = FeeQuote {
+                amount,
+                fee,
+                fee_amount: fee_amount.to_f64(),
+            };
+            Ok((quote, inner_order))
+        }
+    }
+
+    pub fn quote(&self, order: &OrderInfo) -> Result<FeeQuote, CFMMError> {
+        self.quote_inner(order).map(|(q, _)| q)
+    }
+}
+
+impl<P: ConstantFunctionMarketMaker + Clone, F: FeePolicy> ConstantFunctionMarketMaker
+    for FeePool<P, F>
+{
+    fn base_asset(&self) -> &AssetInfo {
+        self.pool.base_asset()
+    }
+
+    fn quote_asset(&self) -> &AssetInfo {
+        self.pool.quote_asset()
+    }
+
+    fn base_asset_mut(&mut self) -> &mut AssetInfo {
+        self.pool.base_asset_mut()
+    }
+
+    fn quote_asset_mut(&mut self) -> &mut AssetInfo {
+        self.pool.quote_asset_mut()
+    }
+
+    fn spot_price(&self) -> f64 {
+        self.pool.spot_price()
+    }
+
+    fn price_for_order(&self, order: &OrderInfo) -> Result<f64, CFMMError> {
+        self.quote(order).map(|q| q.amount)
+    }
+
+    fn order(&mut self, order: &OrderInfo) -> Result<f64, CFMMError> {
+        let (quote, inner_order) = self.quote_inner(order)?;
+        // Fee stays in the reserve of the asset the user sends.
+        let fee_index = if order.is_buy() {
+            order.index.other()
+        } else {
+            order.index
+        };
+        let fee_id = self.pool.asset_by_index(fee_index).id().clone();
+        let fee_amount = Amount::from_f64(fee_id, quote.fee_amount)?;
+        // Fill the order and add the fee on a copy of the pool, so that an
+        // order is never filled without its fee.
+        let mut pool = self.pool.clone();
+        pool.order(&inner_order)?;
+        let reserve = pool.asset_by_index_mut(fee_index);
+        reserve.amount = reserve.amount.checked_add(&fee_amount)?;
+        let price_before = self.pool.spot_price();
+        self.pool = pool;
+        self.policy
+            .on_order(order, price_before, self.pool.spot_price());
+        Ok(quote.amount)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amplify::Wrapper;
+
+    use super::{DynamicFee, DynamicFeeConfig, FeePolicy, FeePool, FixedFee};
+    use crate::{
+        cfmm::{
+            cpmm::ConstantProductMarketMaker, AssetIndex, ConstantFunctionMarketMaker,
+            Error as CFMMError, OrderInfo, OrderType,
+        },
+        Amount, AmountError, AssetId, AssetInfo,
+    };
+
+    fn sell_base(units: u64) -> OrderInfo {
+        OrderInfo::new(
+            AssetIndex::Zero,
+            Amount::new(AssetId::from_inner([0; 32]), units),
+            OrderType::Sell,
+        )
+    }
+
+    #[test]
+    fn pool_must_be_untouched_when_the_fee_cannot_be_added() {
+        let pool = ConstantProductMarketMaker::new(
+            AssetInfo::new(AssetId::from_inner([0; 32]), 1000, "A".to_owned()),
+            AssetInfo::new(AssetId::from_inner([1; 32]), 1000, "B".to_owned()),
+        );
+        let mut pool = FeePool::new(pool, FixedFee::try_create(0.1).unwrap());
+        pool.order(&sell_base(100)).unwrap();
+        // 90 units are sold to the pool, and the fee of 10 units stays in it.
+        assert_eq!(pool.base_asset().amount().units(), 1100);
+
+        // The 40 units sold fit in the reserve, the 60 units of fee do not.
+        let pool = ConstantProductMarketMaker::new(
+            AssetInfo::new(AssetId::from_inner([0; 32]), u64::MAX - 50, "A".to_owned()),
+            AssetInfo::new(AssetId::from_inner([1; 32]), u64::MAX / 2, "B".to_owned()),
+        );
+        let mut pool = FeePool::new(pool, FixedFee::try_create(0.6).unwrap());
+        assert_eq!(
+            pool.order(&sell_base(100)),
+            Err(CFMMError::Amount(AmountError::Overflow))
+        );
+        assert_eq!(pool.base_asset().amount().units(), u64::MAX - 50);
+        assert_eq!(pool.quote_asset().amount().units(), u64::MAX / 2);
+    }
+
+    #[test]
+    fn dynamic_fee_must_follow_volatility_within_bounds() {
+        let config = DynamicFeeConfig::default();
+        let mut fee = DynamicFee::try_create(config).unwrap();
+        assert_eq!(fee.current_fee(), config.base_fee);
+        let (up, down) = (
+            sell_base(1),
+            OrderInfo::new(
+                AssetIndex::One,
+                Amount::new(AssetId::from_inner([1; 32]), 1),
+                OrderType::Sell,
+            ),
+        );
+        fee.on_order(&up, 1., 1.01);
+        fee.on_order(&down, 1.01, 1.);
+        let calm = fee.current_fee();
+        assert!(calm > config.base_fee);
+        for _ in 0..50 {
+            fee.on_order(&up, 1., 2.);
+            fee.on_order(&down, 2., 1.);
+        }
+        assert!(fee.current_fee() > calm);
+        assert!(fee.current_fee() <= config.max_fee);
+
+        let invalid = DynamicFeeConfig {
+            min_fee: 0.01,
+            ..config
+        };
+        assert_eq!(
+            DynamicFee::try_create(invalid),
+            Err(CFMMError::InvalidFeeParam)
+        );
+    }
+}
@@ -0,0 +1,441 @@
+//! Fixed Product Market Maker (FPMM), as used in Gnosis conditional tokens.
+//!
+//! This is a CFMM for prediction markets. Collateral is always split into
+//! complete sets of outcome tokens (one token of every outcome), and the pool
+//! keeps the product of all of its outcome token balances constant.
+//! Unlike `crate::cost_function`, the market is funded by liquidity providers
+//! who earn the trading fee, rather than by a fixed subsidy.
+
+use std::{collections::HashMap, convert::TryFrom};
+
+use crate::{
+    cost_function::{AMMError, PurchaseError, MINIMAL_PURCHASE},
+    utils::FinitePositiveFloat,
+};
+
+/// Identifier of the liquidity provider.
+pub type ProviderId = String;
+
+/// Number of bisection steps for the collateral of an amount of outcome
+/// tokens.
+const BISECTION_STEPS: usize = 100;
+
+#[derive(Clone, Debug, Default, PartialEq, serde::Deserialize, serde::Serialize)]
+struct Provider {
+    shares: f64,
+    /// `shares * fee_per_share` at the time the fee was last settled.
+    fee_debt: f64,
+}
+
+/// Result of adding or removing liquidity.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LiquidityChange {
+    /// Pool shares minted (for funding) or burned (for defunding).
+    pub shares: f64,
+    /// Outcome tokens sent back to the liquidity provider.
+    pub outcome_tokens: Vec<f64>,
+    /// Collateral sent to the liquidity provider from accrued fees.
+    pub fees: f64,
+}
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct FixedProductMarketMaker {
+    /// Outcome tokens held by the pool.
+    balances: Vec<f64>,
+    /// Fraction of each trade which is taken as the fee.
+    fee: f64,
+    providers: HashMap<ProviderId, Provider>,
+    total_shares: f64,
+    /// Accrued fees per pool share, in collateral.
+    fee_per_share: f64,
+    /// Collateral which is not yet withdrawn as fees.
+    fee_pool: f64,
+}
+
+fn validate_amount(amount: f64) -> Result<(), PurchaseError> {
+    if FinitePositiveFloat::try_from(amount)?.inner() < MINIMAL_PURCHASE {
+        Err(PurchaseError::TooSmall)
+    } else {
+        Ok(())
+    }
+}
+
+impl FixedProductMarketMaker {
+    pub fn try_create(num_outcomes: usize, fee: f64) -> Result<Self, AMMError> {
+        if num_outcomes <= 1 {
+            Err(AMMError::OutcomeLessThanTwo)
+        } else if !fee.is_finite() || fee.is_sign_negative() || fee >= 1. {
+            Err(AMMError::BogusFeeParam)
+        } else {
+            Ok(Self {
+                balances: vec![0.; num_outcomes],
+                fee,
+                providers: HashMap::new(),
+                total_shares: 0.,
+                fee_per_share: 0.,
+                fee_pool: 0.,
+            })
+        }
+    }
+
+    pub fn balances(&self) -> &[f64] {
+        &self.balances
+    }
+
+    pub fn fee(&self) -> f64 {
+        self.fee
+    }
+
+    pub fn total_shares(&self) -> f64 {
+        self.total_shares
+    }
+
+    pub fn shares_of(&self, provider: &ProviderId) -> f64 {
+        self.providers.get(provider).map_or(0., |p| p.shares)
+    }
+
+    /// Collateral collected as fees which is not withdrawn yet.
+    pub fn collected_fees(&self) -> f64 {
+        self.fee_pool
+    }
+
+    /// Product of all outcome balances. This is kept constant by trades.
+    pub fn invariant(&self) -> f64 {
+        self.balances.iter().product()
+    }
+
+    /// Marginal price of each outcome. They sum up to 1.
+    pub fn prices(&self) -> Vec<f64> {
+        let inverse_sum: f64 = self.balances.iter().map(|b| 1. / b).sum();
+        self.balances
+            .iter()
+            .map(|b| (1. / b) / inverse_sum)
+            .collect()
+    }
+
+    fn check_outcome(&self, outcome_index: usize) -> Result<(), PurchaseError> {
+        if outcome_index >= self.balances.len() {
+            Err(PurchaseError::UnknownOutcome)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_liquidity(&self) -> Result<(), AMMError> {
+        if self.total_shares <= 0. {
+            Err(AMMError::NoLiquidity)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Amount of outcome tokens the user gets for `investment` collateral.
+    pub fn calc_buy_amount(&self, investment: f64, outcome_index: usize) -> Result<f64, AMMError> {
+        validate_amount(investment)?;
+        self.check_outcome(outcome_index)?;
+        self.check_liquidity()?;
+        let investment_minus_fee = investment * (1. - self.fee);
+        let mut ending_balance = self.balances[outcome_index];
+        for (i, b) in self.balances.iter().enumerate() {
+            if i != outcome_index {
+                ending_balance = ending_balance * b / (b + investment_minus_fee);
+            }
+        }
+        Ok(self.balances[outcome_index] + investment_minus_fee - ending_balance)
+    }
+
+    /// Amount of outcome tokens the user must sell to get `return_amount`
+    /// collateral.
+    pub fn calc_sell_amount(
+        &self,
+        return_amount: f64,
+        outcome_index: usize,
+    ) -> Result<f64, AMMError> {
+        validate_amount(return_amount)?;
+        self.check_outcome(outcome_index)?;
+        self.check_liquidity()?;
+        let return_amount_plus_fee = return_amount / (1. - self.fee);
+        let mut ending_balance = self.balances[outcome_index];
+        for (i, b) in self.balances.iter().enumerate() {
+            if i != outcome_index {
+                if *b <= return_amount_plus_fee {
+                    return Err(PurchaseError::InsufficientLiquidity.into());
+                }
+                ending_balance = ending_balance * b / (b - return_amount_plus_fee);
+            }
+        }
+        Ok(return_amount_plus_fee + ending_balance - self.balances[outcome_index])
+    }
+
+    /// Collateral for which `tokens` trades `outcome_tokens`, by bisection
+    /// over `[0, hi]` as the outcome tokens increase with the collateral.
+    /// Rounded up for a buy and down for a sale, in favour of the pool.
+    fn solve_collateral<F>(
+        outcome_tokens: f64,
+        hi: f64,
+        buy: bool,
+        tokens: F,
+    ) -> Result<f64, AMMError>
+    where
+        F: Fn(f64) -> Result<f64, AMMError>,
+    {
+        validate_amount(outcome_tokens)?;
+        let (mut lo, mut hi) = (0., hi);
+        for _ in 0..BISECTION_STEPS {
+            let mid = 0.5 * (lo + hi);
+            match tokens(mid) {
+                Ok(t) if t < outcome_tokens => lo = mid,
+                Err(AMMError::PurchaseError(PurchaseError::TooSmall)) => lo = mid,
+                _ => hi = mid,
+            }
+        }
+        let collateral = if buy { hi } else { lo };
+        if (tokens(collateral)? - outcome_tokens).abs() > outcome_tokens * 1e-9 {
+            return Err(PurchaseError::InsufficientLiquidity.into());
+        }
+        Ok(collateral)
+    }
+
+    /// Collateral to invest for `outcome_tokens`, the inverse of
+    /// `calc_buy_amount`.
+    pub fn calc_buy_investment(
+        &self,
+        outcome_tokens: f64,
+        outcome_index: usize,
+    ) -> Result<f64, AMMError> {
+        // At least the investment net of the fee is bought.
+        let hi = outcome_tokens / (1. - self.fee);
+        Self::solve_collateral(outcome_tokens, hi, true, |investment| {
+            self.calc_buy_amount(investment, outcome_index)
+        })
+    }
+
+    /// Collateral returned for selling `outcome_tokens`, the inverse of
+    /// `calc_sell_amount`.
+    pub fn calc_sell_return(
+        &self,
+        outcome_tokens: f64,
+        outcome_index: usize,
+    ) -> Result<f64, AMMError> {
+        // At least the return plus the fee is sold.
+        let hi = outcome_tokens * (1. - self.fee);
+        Self::solve_collateral(outcome_tokens, hi, false, |return_amount| {
+            self.calc_sell_amount(return_amount, outcome_index)
+        })
+    }
+
+    fn accrue_fee(&mut self, fee: f64) {
+        self.fee_pool += fee;
+        self.fee_per_share += fee / self.total_shares;
+    }
+
+    /// Buy outcome tokens with `investment` collateral.
+    /// Fails if the user would get less than `min_outcome_tokens`.
+    /// Returns the amount of outcome tokens bought.
+    pub fn buy(
+        &mut self,
+        investment: f64,
+        outcome_index: usize,
+        min_outcome_tokens: f64,
+    ) -> Result<f64, AMMError> {
+        let outcome_tokens = self.calc_buy_amount(investment, outcome_index)?;
+        if outcome_tokens < min_outcome_tokens {
+            return Err(PurchaseError::SlippageExceeded.into());
+        }
+        let fee = investment * self.fee;
+        self.accrue_fee(fee);
+        for b in self.balances.iter_mut() {
+            *b += investment - fee;
+        }
+        self.balances[outcome_index] -= outcome_tokens;
+        Ok(outcome_tokens)
+    }
+
+    /// Sell outcome tokens to get `return_amount` collateral.
+    /// Fails if it requires more than `max_outcome_tokens`.
+    /// Returns the amount of outcome tokens sold.
+    pub fn sell(
+        &mut self,
+        return_amount: f64,
+        outcome_index: usize,
+        max_outcome_tokens: f64,
+    ) -> Result<f64, AMMError> {
+        let outcome_tokens = self.calc_sell_amount(return_amount, outcome_index)?;
+        if outcome_tokens > max_outcome_tokens {
+            return Err(PurchaseError::SlippageExceeded.into());
+        }
+        let return_amount_plus_fee = return_amount / (1. - self.fee);
+        self.accrue_fee(return_amount_plus_fee - return_amount);
+        self.balances[outcome_index] += outcome_tokens;
+        for b in self.balances.iter_mut() {
+            *b -= return_amount_plus_fee;
+        }
+        Ok(outcome_tokens)
+    }
+
+    /// Settle the accrued fee of the provider.
+    fn withdraw_fees_inner(&mut self, provider: &ProviderId) -> f64 {
+        let fee_per_share = self.fee_per_share;
+        match self.providers.get_mut(provider) {
+            Some(p) => {
+                let fees = p.shares * fee_per_share - p.fee_debt;
+                p.fee_debt = p.shares * fee_per_share;
+                self.fee_pool -= fees;
+                fees
+            }
+            None => 0.,
+        }
+    }
+
+    /// Withdraw collateral collected as fees for `provider`.
+    pub fn withdraw_fees(&mut self, provider: &ProviderId) -> f64 {
+        self.withdraw_fees_inner(provider)
+    }
+
+    /// Fund the pool with `amount` collateral.
+    /// The first funding splits the collateral equally into all outcomes,
+    /// and later fundings keep the current prices, so some outcome tokens are
+    /// sent back to the provider.
+    pub fn add_liquidity(
+        &mut self,
+        provider: ProviderId,
+        amount: f64,
+    ) -> Result<LiquidityChange, AMMError> {
+        validate_amount(amount)?;
+        let fees = self.withdraw_fees_inner(&provider);
+        let (shares, outcome_tokens) = if self.total_shares <= 0. {
+            for b in self.balances.iter_mut() {
+                *b += amount;
+            }
+            (amount, vec![0.; self.balances.len()])
+        } else {
+            let pool_weight = self.balances.iter().cloned().fold(0., f64::max);
+            let outcome_tokens = self
+                .balances
+                .iter()
+                .map(|b| amount - amount * b / pool_weight)
+                .collect::<Vec<_>>();
+            for (b, sent_back) in self.balances.iter_mut().zip(&outcome_tokens) {
+                *b += amount - sent_back;
+            }
+            (amount * self.total_shares / pool_weight, outcome_tokens)
+        };
+        self.total_shares += shares;
+        let fee_per_share = self.fee_per_share;
+        let p = self.providers.entry(provider).or_default();
+        p.shares += shares;
+        p.fee_debt = p.shares * fee_per_share;
+        Ok(LiquidityChange {
+            shares,
+            outcome_tokens,
+            fees,
+        })
+    }
+
+    /// Burn `shares` of `provider` and send back the proportional amount of
+    /// outcome tokens, along with the accrued fees.
+    pub fn remove_liquidity(
+        &mut self,
+        provider: &ProviderId,
+        shares: f64,
+    ) -> Result<LiquidityChange, AMMError> {
+        validate_amount(shares)?;
+        if self.shares_of(provider) < shares {
+            return Err(AMMError::InsufficientShares);
+        }
+        let fees = self.withdraw_fees_inner(provider);
+        let outcome_tokens = self
+            .balances
+            .iter()
+            .map(|b| b * shares / self.total_shares)
+            .collect::<Vec<_>>();
+        for (b, sent) in self.balances.iter_mut().zip(&outcome_tokens) {
+            *b -= sent;
+        }
+        self.total_shares -= shares;
+        let fee_per_share = self.fee_per_share;
+        if let Some(p) = self.providers.get_mut(provider) {
+            p.shares -= shares;
+            p.fee_debt = p.shares * fee_per_share;
+        }
+        Ok(LiquidityChange {
+            shares,
+            outcome_tokens,
+            fees,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FixedProductMarketMaker;
+    use crate::cost_function::{AMMError, PurchaseError};
+
+    fn funded(fee: f64) -> FixedProductMarketMaker {
+        let mut fpmm = FixedProductMarketMaker::try_create(3, fee).unwrap();
+        fpmm.add_liquidity("lp".to_owned(), 100.).unwrap();
+        fpmm
+    }
+
+    #[test]
+    fn buy_must_keep_invariant_and_move_price() {
+        let mut fpmm = funded(0.);
+        let k = fpmm.invariant();
+        let tokens = fpmm.buy(10., 1, 0.).unwrap();
+        assert!(tokens > 10.);
+        assert!((fpmm.invariant() - k).abs() / k < 1e-12);
+        let prices = fpmm.prices();
+        assert!((prices.iter().sum::<f64>() - 1.).abs() < 1e-12);
+        assert!(prices[1] > prices[0]);
+        assert_eq!(
+            fpmm.buy(10., 3, 0.),
+            Err(AMMError::PurchaseError(PurchaseError::UnknownOutcome))
+        );
+    }
+
+    #[test]
+    fn sell_must_be_inverse_of_buy() {
+        let mut fpmm = funded(0.);
+        let tokens = fpmm.buy(10., 0, 0.).unwrap();
+        let sold = fpmm.sell(10., 0, tokens + 1e-9).unwrap();
+        assert!((sold - tokens).abs() < 1e-9);
+        for b in fpmm.balances() {
+            assert!((b - 100.).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn exact_amounts_of_tokens_must_be_priced() {
+        let mut fpmm = funded(0.02);
+        let investment = fpmm.calc_buy_investment(10., 1).unwrap();
+        let tokens = fpmm.buy(investment, 1, 0.).unwrap();
+        assert!((tokens - 10.).abs() < 1e-9);
+        let returned = fpmm.calc_sell_return(10., 1).unwrap();
+        assert!(returned < investment);
+        let sold = fpmm.sell(returned, 1, 10.).unwrap();
+        assert!((sold - 10.).abs() < 1e-9);
+        assert_eq!(
+            fpmm.calc_buy_investment(0., 1),
+            Err(AMMError::PurchaseError(PurchaseError::TooSmall))
+        );
+    }
+
+    #[test]
+    fn fees_must_go_to_providers_by_share() {
+        let mut fpmm = funded(0.02);
+        fpmm.add_liquidity("lp2".to_owned(), 100.).unwrap();
+        fpmm.buy(50., 2, 0.).unwrap();
+        let f1 = fpmm.withdraw_fees(&"lp".to_owned());
+        let f2 = fpmm.withdraw_fees(&"lp2".to_owned());
+        assert!((f1 + f2 - 1.).abs() < 1e-12);
+        assert!((f1 - f2).abs() < 1e-12);
+        assert!(fpmm.collected_fees().abs() < 1e-12);
+
+        let change = fpmm.remove_liquidity(&"lp2".to_owned(), 100.).unwrap();
+        assert_eq!(change.outcome_tokens.len(), 3);
+        assert_eq!(
+            fpmm.remove_liquidity(&"lp2".to_owned(), 1.),
+            Err(AMMError::InsufficientShares)
+        );
+    }
+}
@@ -0,0 +1,359 @@
+//! CFMM built from an arbitrary trading function `φ(x, y)`.
+//!
+//! `x` is the base reserve and `y` is the quote reserve. A trade is accepted
+//! when it keeps `φ` at its current value, so swap amounts are found by
+//! solving `φ(x', y') = φ(x, y)` numerically with a bracketing root solver.
+//! This lets us prototype a new curve without writing its pricing maths.
+//!
+//! `φ` must be strictly increasing in both reserves and its level sets must
+//! be convex, otherwise the pool could be drained or quote ambiguous prices.
+//! Both properties are checked numerically around the initial reserves.
+
+use super::{AssetIndex, ConstantFunctionMarketMaker, Error as CFMMError, OrderInfo};
+use crate::AssetInfo;
+
+/// Maximum iterations for the root solver.
+const MAX_ITERATIONS: usize = 200;
+
+/// The bracket of the root is expanded up to `2^MAX_BRACKET_EXPANSIONS`
+/// times the current reserve.
+const MAX_BRACKET_EXPANSIONS: usize = 64;
+
+/// Relative tolerance for the root solver.
+const ROOT_TOLERANCE: f64 = 1e-14;
+
+/// Relative tolerance for the checks of the trading function.
+const CHECK_TOLERANCE: f64 = 1e-9;
+
+/// Reserves are checked on `[r / 2^CHECK_RANGE, r * 2^CHECK_RANGE]`.
+const CHECK_RANGE: i32 = 3;
+
+/// Number of sample points per doubling of the reserve.
+const CHECK_SAMPLES: i32 = 4;
+
+pub trait TradingFunction {
+    fn value(&self, base: f64, quote: f64) -> f64;
+}
+
+impl<F: Fn(f64, f64) -> f64> TradingFunction for F {
+    fn value(&self, base: f64, quote: f64) -> f64 {
+        self(base, quote)
+    }
+}
+
+impl TradingFunction for Box<dyn TradingFunction> {
+    fn value(&self, base: f64, quote: f64) -> f64 {
+        (**self).value(base, quote)
+    }
+}
+
+/// Find `x` in `[lo, hi]` such that `g(x) = 0`, where `g(lo)` and `g(hi)`
+/// have opposite signs.
+/// This is the Illinois variant of regula falsi, with a bisection step
+/// every third iteration so that the bracket always shrinks.
+pub fn find_root<G: Fn(f64) -> f64>(g: G, lo: f64, hi: f64) -> Result<f64, CFMMError> {
+    let (mut lo, mut hi) = (lo, hi);
+    let (mut f_lo, mut f_hi) = (g(lo), g(hi));
+    if f_lo == 0. {
+        return Ok(lo);
+    }
+    if f_hi == 0. {
+        return Ok(hi);
+    }
+    if !f_lo.is_finite() || !f_hi.is_finite() || f_lo.signum() == f_hi.signum() {
+        return Err(CFMMError::RootNotFound);
+    }
+    // Which end was moved in the last iteration, for the Illinois step.
+    let mut last_moved = 0;
+    for i in 0..MAX_ITERATIONS {
+        let mut mid = (lo * f_hi - hi * f_lo) / (f_hi - f_lo);
+        if i % 3 == 2 || !(mid > lo && mid < hi) {
+            mid = 0.5 * (lo + hi);
+        }
+        let f_mid = g(mid);
+        if !f_mid.is_finite() {
+            return Err(CFMMError::RootNotFound);
+        }
+        if f_mid == 0. || (hi - lo) <= ROOT_TOLERANCE * mid.abs().max(1.) {
+            return Ok(mid);
+        }
+        if f_mid.signum() == f_lo.signum() {
+            lo = mid;
+            f_lo = f_mid;
+            if last_moved == -1 {
+                f_hi /= 2.;
+            }
+            last_moved = -1;
+        } else {
+            hi = mid;
+            f_hi = f_mid;
+            if last_moved == 1 {
+                f_lo /= 2.;
+            }
+            last_moved = 1;
+        }
+    }
+    Err(CFMMError::RootNotFound)
+}
+
+/// Find the root of the increasing function `g`, by expanding the bracket
+/// geometrically from `hint` until `g` changes its sign.
+fn solve_increasing<G: Fn(f64) -> f64>(g: G, hint: f64) -> Result<f64, CFMMError> {
+    let (mut lo, mut hi) = (hint, hint);
+    for _ in 0..MAX_BRACKET_EXPANSIONS {
+        if g(lo) <= 0. {
+            break;
+        }
+        lo /= 2.;
+    }
+    for _ in 0..MAX_BRACKET_EXPANSIONS {
+        if g(hi) >= 0. {
+            break;
+        }
+        hi *= 2.;
+    }
+    find_root(g, lo, hi).map_err(|_| CFMMError::InsufficientLiquidity)
+}
+
+#[derive(Debug, Clone)]
+pub struct GenericMarketMaker<F> {
+    base_asset: AssetInfo,
+    quote_asset: AssetInfo,
+    phi: F,
+}
+
+impl<F: TradingFunction> GenericMarketMaker<F> {
+    pub fn try_create(
+        base_asset: AssetInfo,
+        quote_asset: AssetInfo,
+        phi: F,
+    ) -> Result<Self, CFMMError> {
+        let pool = Self {
+            base_asset,
+            quote_asset,
+            phi,
+        };
+        pool.check()?;
+        Ok(pool)
+    }
+
+    fn reserves(&self) -> (f64, f64) {
+        (self.base_asset.reserve(), self.quote_asset.reserve())
+    }
+
+    pub fn invariant(&self) -> f64 {
+        let (x, y) = self.reserves();
+        self.phi.value(x, y)
+    }
+
+    /// Quote reserve `y` such that `φ(x, y) = k`.
+    fn solve_quote(&self, x: f64, k: f64, y_hint: f64) -> Result<f64, CFMMError> {
+        solve_increasing(|y| self.phi.value(x, y) - k, y_hint)
+    }
+
+    /// Base reserve `x` such that `φ(x, y) = k`.
+    fn solve_base(&self, y: f64, k: f64, x_hint: f64) -> Result<f64, CFMMError> {
+        solve_increasing(|x| self.phi.value(x, y) - k, x_hint)
+    }
+
+    /// Check that `φ` is increasing in both reserves, and that the level set
+    /// through the current reserves is a decreasing convex curve.
+    /// Points which the level set does not reach are skipped.
+    pub fn check(&self) -> Result<(), CFMMError> {
+        let (x0, y0) = self.reserves();
+        let k = self.invariant();
+        if !(x0 > 0. && y0 > 0. && k.is_finite()) {
+            return Err(CFMMError::InvalidTradingFunction);
+        }
+        let ratio = 2f64.powf(1. / CHECK_SAMPLES as f64);
+        let mut curve = vec![];
+        for i in -CHECK_RANGE * CHECK_SAMPLES..=CHECK_RANGE * CHECK_SAMPLES {
+            let x = x0 * ratio.powi(i);
+            let eps = x * 1e-6;
+            // The level set may not reach this `x` (e.g. constant sum).
+            let y_level = match self.solve_quote(x, k, y0) {
+                Ok(y) => y,
+                Err(_) => continue,
+            };
+            let dx = self.phi.value(x + eps, y_level) - self.phi.value(x, y_level);
+            let dy = self.phi.value(x, y_level + y_level * 1e-6) - self.phi.value(x, y_level);
+            if dx <= 0. || dy <= 0. {
+                return Err(CFMMError::InvalidTradingFunction);
+            }
+            curve.push((x, y_level));
+        }
+        let slopes = curve
+            .windows(2)
+            .map(|w| (w[1].1 - w[0].1) / (w[1].0 - w[0].0))
+            .collect::<Vec<_>>();
+        if slopes.len() < 2 {
+            return Err(CFMMError::InvalidTradingFunction);
+        }
+        let is_decreasing = slopes.iter().all(|s| *s < 0.);
+        let is_convex = slopes
+            .windows(2)
+            .all(|s| s[1] >= s[0] - CHECK_TOLERANCE * s[0].abs());
+        if !is_decreasing || !is_convex {
+            return Err(CFMMError::InvalidTradingFunction);
+        }
+        Ok(())
+    }
+
+    /// Returns the amount of the counter asset, and the reserves after the
+    /// order.
+    fn compute(&self, order: &OrderInfo) -> Result<(f64, (f64, f64)), CFMMError> {
+        if order.amount.is_zero() {
+            return Err(CFMMError::InvalidOrderAmount);
+        }
+        let amount = order.quantity();
+        let (x, y) = self.reserves();
+        let k = self.invariant();
+        let sign = if order.is_buy() { -1. } else { 1. };
+        let (amount_y, reserves) = match order.index {
+            AssetIndex::Zero => {
+                let x_after = x + sign * amount;
+                if x_after <= 0. {
+                    return Err(CFMMError::InsufficientLiquidity);
+                }
+                let y_after = self.solve_quote(x_after, k, y)?;
+                ((y_after - y).abs(), (x_after, y_after))
+            }
+            AssetIndex::One => {
+                let y_after = y + sign * amount;
+                if y_after <= 0. {
+                    return Err(CFMMError::InsufficientLiquidity);
+                }
+                let x_after = self.solve_base(y_after, k, x)?;
+                ((x_after - x).abs(), (x_after, y_after))
+            }
+        };
+        Ok((amount_y, reserves))
+    }
+}
+
+impl<F: TradingFunction> ConstantFunctionMarketMaker for GenericMarketMaker<F> {
+    fn base_asset(&self) -> &AssetInfo {
+        &self.base_asset
+    }
+
+    fn quote_asset(&self) -> &AssetInfo {
+        &self.quote_asset
+    }
+
+    fn base_asset_mut(&mut self) -> &mut AssetInfo {
+        &mut self.base_asset
+    }
+
+    fn quote_asset_mut(&mut self) -> &mut AssetInfo {
+        &mut self.quote_asset
+    }
+
+    /// `(∂φ/∂y) / (∂φ/∂x)` by central differences.
+    fn spot_price(&self) -> f64 {
+        let (x, y) = self.reserves();
+        let (hx, hy) = (x * 1e-6, y * 1e-6);
+        let dx = (self.phi.value(x + hx, y) - self.phi.value(x - hx, y)) / (2. * hx);
+        let dy = (self.phi.value(x, y + hy) - self.phi.value(x, y - hy)) / (2. * hy);
+        dy / dx
+    }
+
+    fn price_for_order(&self, order: &OrderInfo) -> Result<f64, CFMMError> {
+        self.compute(order).map(|(amount, _)| amount)
+    }
+
+    fn order(&mut self, order: &OrderInfo) -> Result<f64, CFMMError> {
+        let (amount_y, _) = self.compute(order)?;
+        self.apply_order(order, amount_y)?;
+        Ok(amount_y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amplify::Wrapper;
+
+    use super::{find_root, GenericMarketMaker};
+    use crate::{
+        cfmm::{
+            cpmm::ConstantProductMarketMaker, AssetIndex, ConstantFunctionMarketMaker, Error,
+            OrderInfo, OrderType,
+        },
+        Amount, AssetId, AssetInfo,
+    };
+
+    fn assets() -> (AssetInfo, AssetInfo) {
+        (
+            AssetInfo::new(AssetId::from_inner([0; 32]), 4_000_000, "A".to_owned()),
+            AssetInfo::new(AssetId::from_inner([1; 32]), 1_000_000, "B".to_owned()),
+        )
+    }
+
+    #[test]
+    fn root_must_be_found_inside_the_bracket() {
+        let root = find_root(|x| x * x * x - 2., 0., 2.).unwrap();
+        assert!((root - 2f64.cbrt()).abs() < 1e-12);
+        assert_eq!(find_root(|x| x - 1., 0., 1.), Ok(1.));
+        assert_eq!(find_root(|x| x * x + 1., -1., 1.), Err(Error::RootNotFound));
+    }
+
+    #[test]
+    fn trading_function_must_be_increasing_with_convex_level_sets() {
+        let (base, quote) = assets();
+        assert!(
+            GenericMarketMaker::try_create(base.clone(), quote.clone(), |x: f64, y: f64| { x * y })
+                .is_ok()
+        );
+        // Decreasing in the quote reserve.
+        let decreasing = |x: f64, y: f64| x - y;
+        assert_eq!(
+            GenericMarketMaker::try_create(base.clone(), quote.clone(), decreasing).err(),
+            Some(Error::InvalidTradingFunction)
+        );
+        // Level sets of a circle are concave.
+        let circle = |x: f64, y: f64| x * x + y * y;
+        assert_eq!(
+            GenericMarketMaker::try_create(base, quote, circle).err(),
+            Some(Error::InvalidTradingFunction)
+        );
+    }
+
+    #[test]
+    fn swaps_must_match_constant_product() {
+        let (base, quote) = assets();
+        let mut generic =
+            GenericMarketMaker::try_create(base.clone(), quote.clone(), |x: f64, y: f64| x * y)
+                .unwrap();
+        let cpmm = ConstantProductMarketMaker::new(base, quote);
+        for (index, order_type) in [
+            (AssetIndex::Zero, OrderType::Sell),
+            (AssetIndex::Zero, OrderType::Buy),
+            (AssetIndex::One, OrderType::Sell),
+            (AssetIndex::One, OrderType::Buy),
+        ] {
+            let id = generic.asset_by_index(index).id().clone();
+            let order = OrderInfo::new(index, Amount::new(id, 10_000), order_type);
+            let expected = cpmm.price_for_order(&order).unwrap();
+            let amount = generic.price_for_order(&order).unwrap();
+            assert!((amount - expected).abs() < 1e-6 * expected);
+        }
+
+        let order = OrderInfo::new(
+            AssetIndex::Zero,
+            Amount::new(AssetId::from_inner([0; 32]), 10_000),
+            OrderType::Sell,
+        );
+        let amount = generic.order(&order).unwrap();
+        // Both reserves move together, each by a whole number of units.
+        assert_eq!(generic.base_asset().amount().units(), 4_010_000);
+        assert_eq!(
+            generic.quote_asset().amount().units(),
+            1_000_000 - amount.round() as u64
+        );
+        let order = OrderInfo::new(
+            AssetIndex::One,
+            Amount::new(AssetId::from_inner([1; 32]), 2_000_000),
+            OrderType::Buy,
+        );
+        assert_eq!(generic.order(&order), Err(Error::InsufficientLiquidity));
+    }
+}
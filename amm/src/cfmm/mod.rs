@@ -2,16 +2,38 @@
 //! Also known as trading-function based market maker.
 //! This includes
 //! 1. UniswapV2-style Constant Product Market Maker (CPMM)
+//! 2. Gnosis-style Fixed Product Market Maker (FPMM) for prediction markets
+//! 3. DODO-style Proactive Market Maker (PMM) pegged to a reference price
+//! 4. Replicating Market Maker which holds a given payoff (e.g. options)
+//! 5. Generic Market Maker built from any trading function
 //!
+//! It also includes a `router` which finds the best path across several
+//! pools, `twap` which records time-weighted average prices of a pool,
+//! `batch` which clears orders in batches at an uniform price, `fee` which
+//! charges fees on any pool, and `twamm` which executes long-term orders
+//! over time.
 //!
 
-use amplify::{Display, Error, From};
-use noisy_float::types::R64;
+use std::convert::TryFrom;
 
-use crate::{AssetId, AssetInfo};
+use crate::{utils::FinitePositiveFloat, Amount, AmountError, AssetId, AssetInfo};
+use amplify::{Display, Error, From};
+pub mod batch;
 pub mod cpmm;
+pub mod fee;
+pub mod fpmm;
+pub mod generic;
+pub mod pmm;
+pub mod replicating;
+pub mod router;
+pub mod twamm;
+pub mod twap;
 pub mod uniswapv3;
 
+/// Number of steps for `ConstantFunctionMarketMaker::execute_virtual_orders`
+/// when there is no closed-form solution.
+pub const VIRTUAL_ORDER_STEPS: usize = 64;
+
 /// Error when user tries to fund the AMM>
 #[derive(Clone, Debug, PartialEq, Eq, Display, Error, From)]
 #[display(doc_comments)]
@@ -21,9 +43,73 @@ pub enum Error {
 
     /// Unknown Asset Id
     UnknownAssetId,
+
+    /// Order amount is either NaN, infinite, zero or negative.
+    InvalidOrderAmount,
+
+    /// Pool does not have enough reserves to fill the order.
+    InsufficientLiquidity,
+
+    /// There is no route between the assets.
+    NoRouteFound,
+
+    /// The output of the trade is less than the minimum requested.
+    SlippageExceeded,
+
+    /// Timestamp is older than the latest observation.
+    InvalidTimestamp,
+
+    /// Requested time is older than any observation kept in the pool.
+    ObservationTooOld,
+
+    /// Batch can not be cleared before the end of its interval.
+    BatchNotReady,
+
+    /// Could not find an uniform clearing price for the batch.
+    ClearingPriceNotFound,
+
+    /// Fee parameters must satisfy `0 <= min_fee <= base_fee <= max_fee < 1`.
+    InvalidFeeParam,
+
+    /// Parameter of the trading curve is out of range.
+    InvalidCurveParam,
+
+    /// Payoff must be nonnegative, nondecreasing and concave in price.
+    PayoffNotReplicable,
+
+    /// Trading function must be increasing in both reserves and have convex
+    /// level sets.
+    InvalidTradingFunction,
+
+    /// Numeric solver could not find the root.
+    RootNotFound,
+
+    /// Unknown order.
+    UnknownOrder,
+
+    /// Invalid amount arithmetic.
+    #[from]
+    Amount(AmountError),
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+/// Result of `ConstantFunctionMarketMaker::execute_virtual_orders`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct VirtualTrades {
+    /// Base asset which goes to the sellers of the quote asset.
+    pub base_out: f64,
+    /// Quote asset which goes to the sellers of the base asset.
+    pub quote_out: f64,
+    /// Part of the base asset which could not be sold, and goes back to its
+    /// sellers.
+    pub base_unsold: f64,
+    /// Part of the quote asset which could not be sold, and goes back to its
+    /// sellers.
+    pub quote_unsold: f64,
+}
+
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Deserialize, serde::Serialize,
+)]
 pub enum OrderType {
     Buy,
     Sell,
@@ -37,11 +123,19 @@ pub enum AssetIndex {
     One,
 }
 
+impl AssetIndex {
+    pub fn other(self) -> Self {
+        match self {
+            AssetIndex::Zero => AssetIndex::One,
+            AssetIndex::One => AssetIndex::Zero,
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, PartialOrd)]
 pub struct OrderInfo {
     index: AssetIndex,
-    id: AssetId,
-    amount: R64,
+    amount: Amount,
     order_type: OrderType,
 }
 
@@ -49,17 +143,49 @@ impl OrderInfo {
     pub fn is_buy(&self) -> bool {
         self.order_type == OrderType::Buy
     }
+
+    pub fn index(&self) -> AssetIndex {
+        self.index
+    }
+
+    pub fn id(&self) -> &AssetId {
+        self.amount.asset()
+    }
+
+    pub fn amount(&self) -> &Amount {
+        &self.amount
+    }
+
+    pub fn order_type(&self) -> OrderType {
+        self.order_type
+    }
 }
 
 impl OrderInfo {
-    pub fn new(index: AssetIndex, id: AssetId, amount: R64, order_type: OrderType) -> Self {
+    pub fn new(index: AssetIndex, amount: Amount, order_type: OrderType) -> Self {
         Self {
             index,
-            id,
             amount,
             order_type,
         }
     }
+
+    /// Order of `amount` of the asset at `index`, rounded to the nearest
+    /// unit.
+    pub fn try_from_f64<P: ConstantFunctionMarketMaker + ?Sized>(
+        pool: &P,
+        index: AssetIndex,
+        amount: f64,
+        order_type: OrderType,
+    ) -> Result<Self, Error> {
+        let id = pool.asset_by_index(index).id().clone();
+        Ok(Self::new(index, Amount::from_f64(id, amount)?, order_type))
+    }
+
+    /// Amount as `f64`, for pricing.
+    pub fn quantity(&self) -> f64 {
+        self.amount.to_f64()
+    }
 }
 
 pub trait ConstantFunctionMarketMaker {
@@ -80,6 +206,16 @@ pub trait ConstantFunctionMarketMaker {
         }
     }
 
+    fn index_of(&self, id: &AssetId) -> Result<AssetIndex, Error> {
+        if &self.base_asset().id == id {
+            Ok(AssetIndex::Zero)
+        } else if &self.quote_asset().id == id {
+            Ok(AssetIndex::One)
+        } else {
+            Err(Error::UnknownAssetId)
+        }
+    }
+
     fn asset_by_index(&self, index: AssetIndex) -> &AssetInfo {
         match index {
             AssetIndex::Zero => &self.base_asset(),
@@ -106,14 +242,115 @@ pub trait ConstantFunctionMarketMaker {
 
     fn fund(&mut self, asset_info: &AssetInfo) -> Result<(), Error> {
         let asset = self.asset_by_id_mut(&asset_info.id)?;
-        asset.amount += asset_info.amount;
+        asset.amount = asset.amount.checked_add(&asset_info.amount)?;
         Ok(())
     }
 
-    /// Returns an amount of token which this market maker can offer according
-    /// to the order.
+    /// Move the reserves for the order filled with `amount_y` of the counter
+    /// asset. Both reserves are updated, or none of them.
+    fn apply_order(&mut self, order: &OrderInfo, amount_y: f64) -> Result<(), Error> {
+        let this = self.asset_by_index(order.index).amount.clone();
+        let other = self.asset_by_index(order.index.other()).amount.clone();
+        let counter = Amount::from_f64(other.asset().clone(), amount_y)?;
+        let (this, other) = if order.is_buy() {
+            (
+                this.checked_sub(&order.amount)?,
+                other.checked_add(&counter)?,
+            )
+        } else {
+            (
+                this.checked_add(&order.amount)?,
+                other.checked_sub(&counter)?,
+            )
+        };
+        self.asset_by_index_mut(order.index).amount = this;
+        self.asset_by_index_mut(order.index.other()).amount = other;
+        Ok(())
+    }
+
+    /// Current marginal price of the quote asset in terms of the base asset.
+    fn spot_price(&self) -> f64;
+
+    /// Returns an amount of the counter asset for the order. That is, what
+    /// the user receives for `Sell`, and what the user pays for `Buy`.
     /// Returns error for unknown asset id.
-    fn price_for_order(&self, order: &OrderInfo) -> f64;
+    fn price_for_order(&self, order: &OrderInfo) -> Result<f64, Error>;
+
+    /// Move the internal reserves, and returns the same amount as
+    /// `price_for_order`.
+    fn order(&mut self, order: &OrderInfo) -> Result<f64, Error>;
 
-    fn order(&mut self, order: &OrderInfo) -> f64;
+    /// Sell `base_in` and `quote_in` to the pool continuously, as infinitely
+    /// many infinitely small orders during the same period. This is used by
+    /// `twamm`.
+    /// The outputs are what the reserves of the pool actually moved, plus
+    /// what both sides exchanged with each other.
+    ///
+    /// The default implementation splits the period into
+    /// `VIRTUAL_ORDER_STEPS` steps. In each step, both sides are matched at
+    /// the spot price and only the imbalance is sent to the pool, in whole
+    /// units. The part of the imbalance smaller than a unit is carried into
+    /// the next step, and is returned unsold after the last one.
+    fn execute_virtual_orders(
+        &mut self,
+        base_in: f64,
+        quote_in: f64,
+    ) -> Result<VirtualTrades, Error> {
+        if FinitePositiveFloat::try_from(base_in).is_err()
+            || FinitePositiveFloat::try_from(quote_in).is_err()
+        {
+            return Err(Error::InvalidOrderAmount);
+        }
+        let (base_step, quote_step) = (
+            base_in / VIRTUAL_ORDER_STEPS as f64,
+            quote_in / VIRTUAL_ORDER_STEPS as f64,
+        );
+        let mut trades = VirtualTrades::default();
+        let (mut base_carry, mut quote_carry) = (0., 0.);
+        for _ in 0..VIRTUAL_ORDER_STEPS {
+            let (base_sold, quote_sold) = (base_step + base_carry, quote_step + quote_carry);
+            base_carry = 0.;
+            quote_carry = 0.;
+            // base per quote
+            let price = self.spot_price();
+            let quote_sold_in_base = quote_sold * price;
+            if base_sold > quote_sold_in_base {
+                let net = base_sold - quote_sold_in_base;
+                let units = net.floor();
+                base_carry = net - units;
+                let out = if units > 0. {
+                    let before = self.quote_asset().reserve();
+                    let order =
+                        OrderInfo::try_from_f64(self, AssetIndex::Zero, units, OrderType::Sell)?;
+                    self.order(&order)?;
+                    before - self.quote_asset().reserve()
+                } else {
+                    0.
+                };
+                trades.quote_out += quote_sold + out;
+                trades.base_out += quote_sold_in_base;
+            } else if quote_sold_in_base > base_sold {
+                let net = quote_sold - base_sold / price;
+                let units = net.floor();
+                quote_carry = net - units;
+                let out = if units > 0. {
+                    let before = self.base_asset().reserve();
+                    let order =
+                        OrderInfo::try_from_f64(self, AssetIndex::One, units, OrderType::Sell)?;
+                    self.order(&order)?;
+                    before - self.base_asset().reserve()
+                } else {
+                    0.
+                };
+                trades.base_out += base_sold + out;
+                trades.quote_out += base_sold / price;
+            } else {
+                trades.base_out += quote_sold_in_base;
+                trades.quote_out += quote_sold;
+            }
+        }
+        trades.base_unsold = base_carry;
+        trades.quote_unsold = quote_carry;
+        Ok(trades)
+    }
 }
@@ -0,0 +1,442 @@
+//! DODO-style Proactive Market Maker (PMM).
+//!
+//! Pure CPMM pools make liquidity providers pay the full cost of arbitrage,
+//! because the pool only learns the market price by being arbitraged.
+//! PMM instead asks an external `PriceSource` for the reference price `i`
+//! (quote asset per base asset), and concentrates liquidity around it.
+//!
+//! The pool keeps a target amount for each asset (`B0`, `Q0`). When one of
+//! them is short, the marginal price moves away from `i`:
+//!
+//! * base short (`B < B0`): `P = i * (1 - k + k * (B0 / B)^2)`
+//! * quote short (`Q < Q0`): `P = i / (1 - k + k * (Q0 / Q)^2)`
+//!
+//! where `k` in `[0, 1]` is the slippage factor. `k = 0` is a constant price
+//! market maker at `i`, and `k = 1` behaves like a CPMM.
+//!
+//! When the reference price is unavailable or too old, the pool falls back
+//! to constant product pricing on its reserves. Such a trade moves both
+//! reserves without a price to derive the targets from, so the targets are
+//! reset to the reserves after it: the pool is balanced at the next fresh
+//! reference price.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{
+    twap::Timestamp, AssetIndex, ConstantFunctionMarketMaker, Error as CFMMError, OrderInfo,
+};
+use crate::AssetInfo;
+
+/// Price reported by the `PriceSource`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReferencePrice {
+    /// Amount of quote asset per unit of base asset.
+    pub price: f64,
+    pub timestamp: Timestamp,
+}
+
+/// External source of the reference price, e.g. an oracle or another venue.
+pub trait PriceSource {
+    /// Latest known price, if any.
+    fn latest_price(&self) -> Option<ReferencePrice>;
+
+    /// Current time to decide if the price is stale.
+    fn now(&self) -> Timestamp {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+/// Reserve and target amount of one asset.
+#[derive(Clone, Copy, Debug)]
+struct Side {
+    reserve: f64,
+    target: f64,
+}
+
+/// `∫ P(v) dv` between `v1` and `v2` divided by the reference price, when
+/// this side is short against the target `v0`.
+fn integrate(k: f64, v0: f64, v1: f64, v2: f64) -> f64 {
+    (1. - k) * (v1 - v2).abs() + k * v0 * v0 * (1. / v2 - 1. / v1).abs()
+}
+
+/// Find `v2` such that `integrate(k, v0, v1, v2) == fair`.
+/// `v2 < v1` when `decreasing`, `v2 > v1` otherwise.
+fn solve_trade(k: f64, v0: f64, v1: f64, fair: f64, decreasing: bool) -> Result<f64, CFMMError> {
+    // `(1 - k) * v2^2 + b * v2 - k * v0^2 = 0`
+    let b = if decreasing {
+        fair + k * v0 * v0 / v1 - (1. - k) * v1
+    } else {
+        k * v0 * v0 / v1 - (1. - k) * v1 - fair
+    };
+    let c = 4. * (1. - k) * k * v0 * v0;
+    let v2 = if b > 0. {
+        // Avoid the cancellation of `-b + sqrt(b^2 + c)`.
+        2. * k * v0 * v0 / (b + (b * b + c).sqrt())
+    } else {
+        (-b + (b * b + c).sqrt()) / (2. * (1. - k))
+    };
+    if !v2.is_finite() || v2 <= 0. {
+        return Err(CFMMError::InsufficientLiquidity);
+    }
+    Ok(v2)
+}
+
+/// Target of the side which is short, such that selling `fair` (in units of
+/// this side, at the reference price) brings the reserve back to the target.
+fn solve_target(k: f64, reserve: f64, fair: f64) -> f64 {
+    if fair <= 0. {
+        return reserve;
+    }
+    if k == 0. {
+        return reserve + fair;
+    }
+    reserve * (1. + ((1. + 4. * k * fair / reserve).sqrt() - 1.) / (2. * k))
+}
+
+/// User sells `amount` of `x` to get `y`. `p` is the reference price of `x`
+/// in `y`. Returns the amount of `y` the user gets.
+fn sell(k: f64, x: Side, y: Side, amount: f64, p: f64) -> Result<f64, CFMMError> {
+    if x.reserve < x.target {
+        let back_to_one = x.target - x.reserve;
+        if amount <= back_to_one {
+            return Ok(p * integrate(k, x.target, x.reserve, x.reserve + amount));
+        }
+        let y2 = solve_trade(k, y.target, y.target, p * (amount - back_to_one), true)?;
+        Ok(y.reserve - y2)
+    } else {
+        let y2 = solve_trade(k, y.target, y.reserve, p * amount, true)?;
+        Ok(y.reserve - y2)
+    }
+}
+
+/// User buys `amount` of `x` by paying `y`. `p` is the reference price of
+/// `x` in `y`. Returns the amount of `y` the user pays.
+fn buy(k: f64, x: Side, y: Side, amount: f64, p: f64) -> Result<f64, CFMMError> {
+    if x.reserve <= x.target {
+        if amount >= x.reserve {
+            return Err(CFMMError::InsufficientLiquidity);
+        }
+        return Ok(p * integrate(k, x.target, x.reserve, x.reserve - amount));
+    }
+    let back_to_one = x.reserve - x.target;
+    if amount <= back_to_one {
+        let y2 = solve_trade(k, y.target, y.reserve, p * amount, false)?;
+        return Ok(y2 - y.reserve);
+    }
+    let rest = amount - back_to_one;
+    if rest >= x.target {
+        return Err(CFMMError::InsufficientLiquidity);
+    }
+    Ok(y.target - y.reserve + p * integrate(k, x.target, x.target, x.target - rest))
+}
+
+#[derive(Debug, Clone)]
+pub struct ProactiveMarketMaker<S> {
+    base_asset: AssetInfo,
+    quote_asset: AssetInfo,
+    base_target: f64,
+    quote_target: f64,
+    /// Slippage factor in `[0, 1]`.
+    k: f64,
+    /// Reference price older than this (in seconds) is considered stale.
+    max_price_age: u64,
+    price_source: S,
+}
+
+impl<S: PriceSource> ProactiveMarketMaker<S> {
+    pub fn try_create(
+        base_asset: AssetInfo,
+        quote_asset: AssetInfo,
+        k: f64,
+        max_price_age: u64,
+        price_source: S,
+    ) -> Result<Self, CFMMError> {
+        if !(0. ..=1.).contains(&k) {
+            return Err(CFMMError::InvalidCurveParam);
+        }
+        let base_target = base_asset.reserve();
+        let quote_target = quote_asset.reserve();
+        Ok(Self {
+            base_asset,
+            quote_asset,
+            base_target,
+            quote_target,
+            k,
+            max_price_age,
+            price_source,
+        })
+    }
+
+    pub fn price_source(&self) -> &S {
+        &self.price_source
+    }
+
+    pub fn price_source_mut(&mut self) -> &mut S {
+        &mut self.price_source
+    }
+
+    /// Reference price if it is fresh enough, `None` means the pool behaves
+    /// as a CPMM.
+    pub fn reference_price(&self) -> Option<f64> {
+        let r = self.price_source.latest_price()?;
+        let now = self.price_source.now();
+        let is_fresh = r.timestamp + self.max_price_age >= now;
+        if is_fresh && r.price.is_finite() && r.price > 0. {
+            Some(r.price)
+        } else {
+            None
+        }
+    }
+
+    /// Target amounts of base and quote asset under the reference price `i`.
+    pub fn targets(&self, i: f64) -> (f64, f64) {
+        let b = self.base_asset.reserve();
+        let q = self.quote_asset.reserve();
+        if b < self.base_target {
+            let b0 = solve_target(self.k, b, (q - self.quote_target) / i);
+            (b0, self.quote_target)
+        } else if q < self.quote_target {
+            let q0 = solve_target(self.k, q, (b - self.base_target) * i);
+            (self.base_target, q0)
+        } else {
+            (b, q)
+        }
+    }
+
+    /// Returns the amount of the counter asset, and the new targets. No
+    /// targets for the constant product fallback.
+    fn compute(&self, order: &OrderInfo) -> Result<(f64, Option<(f64, f64)>), CFMMError> {
+        if order.amount.is_zero() {
+            return Err(CFMMError::InvalidOrderAmount);
+        }
+        let amount = order.quantity();
+        let x_reserve = self.asset_by_index(order.index).reserve();
+        let y_reserve = self.asset_by_index(order.index.other()).reserve();
+        let i = match self.reference_price() {
+            Some(i) => i,
+            None => {
+                // Constant product fallback.
+                let k = x_reserve * y_reserve;
+                if order.is_buy() {
+                    if amount >= x_reserve {
+                        return Err(CFMMError::InsufficientLiquidity);
+                    }
+                    return Ok((k / (x_reserve - amount) - y_reserve, None));
+                }
+                return Ok((y_reserve - k / (x_reserve + amount), None));
+            }
+        };
+        let (b0, q0) = self.targets(i);
+        let (x_target, y_target, p) = match order.index {
+            AssetIndex::Zero => (b0, q0, i),
+            AssetIndex::One => (q0, b0, 1. / i),
+        };
+        let x = Side {
+            reserve: x_reserve,
+            target: x_target,
+        };
+        let y = Side {
+            reserve: y_reserve,
+            target: y_target,
+        };
+        let amount_y = if order.is_buy() {
+            buy(self.k, x, y, amount, p)?
+        } else {
+            sell(self.k, x, y, amount, p)?
+        };
+        if !amount_y.is_finite() || amount_y < 0. || (!order.is_buy() && amount_y >= y_reserve) {
+            return Err(CFMMError::InsufficientLiquidity);
+        }
+        Ok((amount_y, Some((b0, q0))))
+    }
+}
+
+impl<S: PriceSource> ConstantFunctionMarketMaker for ProactiveMarketMaker<S> {
+    fn base_asset(&self) -> &AssetInfo {
+        &self.base_asset
+    }
+
+    fn quote_asset(&self) -> &AssetInfo {
+        &self.quote_asset
+    }
+
+    fn base_asset_mut(&mut self) -> &mut AssetInfo {
+        &mut self.base_asset
+    }
+
+    fn quote_asset_mut(&mut self) -> &mut AssetInfo {
+        &mut self.quote_asset
+    }
+
+    /// Funding raises the target by the same amount, so it does not move the
+    /// price.
+    fn fund(&mut self, asset_info: &AssetInfo) -> Result<(), CFMMError> {
+        let index = self.index_of(&asset_info.id)?;
+        let asset = self.asset_by_id_mut(&asset_info.id)?;
+        asset.amount = asset.amount.checked_add(&asset_info.amount)?;
+        let amount = asset_info.reserve();
+        match index {
+            AssetIndex::Zero => self.base_target += amount,
+            AssetIndex::One => self.quote_target += amount,
+        }
+        Ok(())
+    }
+
+    fn spot_price(&self) -> f64 {
+        let b = self.base_asset.reserve();
+        let q = self.quote_asset.reserve();
+        let i = match self.reference_price() {
+            Some(i) => i,
+            None => return b / q,
+        };
+        let (b0, q0) = self.targets(i);
+        let k = self.k;
+        let quote_per_base = if b < b0 {
+            i * (1. - k + k * (b0 / b).powi(2))
+        } else if q < q0 {
+            i / (1. - k + k * (q0 / q).powi(2))
+        } else {
+            i
+        };
+        1. / quote_per_base
+    }
+
+    fn price_for_order(&self, order: &OrderInfo) -> Result<f64, CFMMError> {
+        self.compute(order).map(|(amount, _)| amount)
+    }
+
+    fn order(&mut self, order: &OrderInfo) -> Result<f64, CFMMError> {
+        let (amount_y, targets) = self.compute(order)?;
+        self.apply_order(order, amount_y)?;
+        let (b0, q0) =
+            targets.unwrap_or_else(|| (self.base_asset.reserve(), self.quote_asset.reserve()));
+        self.base_target = b0;
+        self.quote_target = q0;
+        Ok(amount_y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use amplify::Wrapper;
+
+    use super::{PriceSource, ProactiveMarketMaker, ReferencePrice};
+    use crate::{
+        cfmm::{
+            cpmm::ConstantProductMarketMaker, AssetIndex, ConstantFunctionMarketMaker, OrderInfo,
+            OrderType,
+        },
+        Amount, AssetId, AssetInfo,
+    };
+
+    /// Smallest units per coin.
+    const UNIT: u64 = 100_000_000;
+
+    /// Local stand-in for an oracle.
+    struct FixedPrice {
+        price: f64,
+        timestamp: u64,
+        now: Cell<u64>,
+    }
+
+    impl PriceSource for FixedPrice {
+        fn latest_price(&self) -> Option<ReferencePrice> {
+            Some(ReferencePrice {
+                price: self.price,
+                timestamp: self.timestamp,
+            })
+        }
+
+        fn now(&self) -> u64 {
+            self.now.get()
+        }
+    }
+
+    fn assets() -> (AssetInfo, AssetInfo) {
+        (
+            AssetInfo::new(AssetId::from_inner([0; 32]), 100 * UNIT, "BTC".to_owned()),
+            AssetInfo::new(AssetId::from_inner([1; 32]), 2000 * UNIT, "USD".to_owned()),
+        )
+    }
+
+    fn sell_base(amount: u64) -> OrderInfo {
+        OrderInfo::new(
+            AssetIndex::Zero,
+            Amount::new(AssetId::from_inner([0; 32]), amount * UNIT),
+            OrderType::Sell,
+        )
+    }
+
+    #[test]
+    fn must_trade_around_reference_price() {
+        let (base, quote) = assets();
+        let source = FixedPrice {
+            price: 20.,
+            timestamp: 100,
+            now: Cell::new(100),
+        };
+        let mut pmm = ProactiveMarketMaker::try_create(base, quote, 0.1, 60, source).unwrap();
+        assert!((1. / pmm.spot_price() - 20.).abs() < 1e-12);
+
+        let out = pmm.order(&sell_base(1)).unwrap() / UNIT as f64;
+        assert!(out < 20. && out > 19.5);
+        // Selling the quote back returns to the targets.
+        let back =
+            OrderInfo::try_from_f64(&pmm, AssetIndex::One, out * UNIT as f64, OrderType::Sell)
+                .unwrap();
+        let base_back = pmm.order(&back).unwrap() / UNIT as f64;
+        assert!((base_back - 1.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn must_fallback_to_cpmm_when_stale() {
+        let (base, quote) = assets();
+        let source = FixedPrice {
+            price: 30.,
+            timestamp: 100,
+            now: Cell::new(161),
+        };
+        let pmm =
+            ProactiveMarketMaker::try_create(base.clone(), quote.clone(), 0.1, 60, source).unwrap();
+        let cpmm = ConstantProductMarketMaker::new(base, quote);
+        assert_eq!(
+            pmm.price_for_order(&sell_base(5)).unwrap(),
+            cpmm.price_for_order(&sell_base(5)).unwrap()
+        );
+        pmm.price_source().now.set(160);
+        assert!(pmm.price_for_order(&sell_base(5)).unwrap() > 140. * UNIT as f64);
+    }
+
+    #[test]
+    fn targets_must_be_reset_after_stale_trade() {
+        let (base, quote) = assets();
+        let source = FixedPrice {
+            price: 20.,
+            timestamp: 100,
+            now: Cell::new(161),
+        };
+        let mut pmm = ProactiveMarketMaker::try_create(base, quote, 0.1, 60, source).unwrap();
+        // Both reserves move along the constant product.
+        let out = pmm.order(&sell_base(10)).unwrap();
+        assert!(out > 0.);
+        let reserves = (pmm.base_asset().reserve(), pmm.quote_asset().reserve());
+        assert!(reserves.1 < 2000. * UNIT as f64);
+
+        // With a fresh price, the pool is balanced at the reference price.
+        pmm.price_source().now.set(100);
+        assert_eq!(pmm.targets(20.), reserves);
+        assert!((1. / pmm.spot_price() - 20.).abs() < 1e-12);
+        let out = pmm.order(&sell_base(1)).unwrap() / UNIT as f64;
+        assert!(out < 20. && out > 19.5);
+        // The quote asset is short against the targets of the reset.
+        let (b0, q0) = pmm.targets(20.);
+        assert_eq!(b0, reserves.0);
+        assert!(q0 > pmm.quote_asset().reserve());
+        assert!((q0 / reserves.1 - 1.).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,385 @@
+//! CFMM which replicates a given payoff, following
+//! "Replicating Market Makers" (Angeris, Evans, Chitra 2021).
+//!
+//! Let `V(p)` be the value (in the quote asset) of the pool's reserves when
+//! the base asset is priced at `p`. Any `V` which is nonnegative,
+//! nondecreasing and concave can be held by a CFMM, and the reserves at
+//! price `p` are
+//!
+//! * base: `V'(p)`
+//! * quote: `V(p) - p * V'(p)`
+//!
+//! We sample `V` on a price grid and interpolate linearly, so the pool is
+//! equivalent to a ladder of limit orders at the grid prices. The set of
+//! reachable reserves is a piecewise-linear convex curve.
+//!
+//! This lets us hold exactly the payoff we sell as a numeric DLC, e.g. a
+//! covered call.
+
+use super::{AssetIndex, ConstantFunctionMarketMaker, Error as CFMMError, OrderInfo};
+use crate::{AssetId, AssetInfo};
+
+/// Tolerance for numerical checks of the payoff.
+const TOLERANCE: f64 = 1e-9;
+
+/// Payoff `V(p)` sampled on a grid of prices.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PayoffCurve {
+    prices: Vec<f64>,
+    values: Vec<f64>,
+}
+
+/// Part of the curve where the pool trades at a single price.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Segment {
+    /// Price of the base asset in the quote asset.
+    price: f64,
+    /// Range of the base reserve while the pool is at this price.
+    base_low: f64,
+    base_high: f64,
+}
+
+impl PayoffCurve {
+    /// Sample `payoff` on `steps + 1` geometrically spaced prices in
+    /// `[min_price, max_price]`, plus any price in `knots` (e.g. strikes).
+    pub fn from_fn<F: Fn(f64) -> f64>(
+        payoff: F,
+        min_price: f64,
+        max_price: f64,
+        steps: usize,
+        knots: &[f64],
+    ) -> Result<Self, CFMMError> {
+        let is_valid_range = min_price.is_finite()
+            && max_price.is_finite()
+            && min_price > 0.
+            && min_price < max_price
+            && steps > 0;
+        if !is_valid_range {
+            return Err(CFMMError::InvalidCurveParam);
+        }
+        let ratio = (max_price / min_price).powf(1. / steps as f64);
+        let mut prices = (0..=steps)
+            .map(|i| min_price * ratio.powi(i as i32))
+            .chain(
+                knots
+                    .iter()
+                    .cloned()
+                    .filter(|k| *k > min_price && *k < max_price),
+            )
+            .collect::<Vec<_>>();
+        prices.sort_by(|a, b| a.total_cmp(b));
+        prices.dedup_by(|a, b| (*a - *b).abs() <= TOLERANCE * *b);
+        let values = prices.iter().map(|p| payoff(*p)).collect();
+        let curve = Self { prices, values };
+        curve.verify()?;
+        Ok(curve)
+    }
+
+    /// Long `units` of the base asset and short a call at `strike`.
+    /// `V(p) = units * min(p, strike)`.
+    pub fn covered_call(
+        strike: f64,
+        units: f64,
+        min_price: f64,
+        max_price: f64,
+        steps: usize,
+    ) -> Result<Self, CFMMError> {
+        Self::from_fn(
+            |p| units * p.min(strike),
+            min_price,
+            max_price,
+            steps,
+            &[strike],
+        )
+    }
+
+    /// Short a put at `strike`, secured by `cash` of the quote asset.
+    /// `V(p) = cash - (cash / strike) * max(strike - p, 0)`.
+    pub fn cash_secured_put(
+        strike: f64,
+        cash: f64,
+        min_price: f64,
+        max_price: f64,
+        steps: usize,
+    ) -> Result<Self, CFMMError> {
+        let units = cash / strike;
+        Self::from_fn(
+            |p| cash - units * (strike - p).max(0.),
+            min_price,
+            max_price,
+            steps,
+            &[strike],
+        )
+    }
+
+    pub fn prices(&self) -> &[f64] {
+        &self.prices
+    }
+
+    pub fn values(&self) -> &[f64] {
+        &self.values
+    }
+
+    /// `V'` on each interval of the grid.
+    fn slopes(&self) -> Vec<f64> {
+        self.prices
+            .windows(2)
+            .zip(self.values.windows(2))
+            .map(|(p, v)| (v[1] - v[0]) / (p[1] - p[0]))
+            .collect()
+    }
+
+    /// Check that the payoff is replicable, i.e. it is nonnegative,
+    /// nondecreasing and concave, so that the reserve curve is convex and
+    /// both reserves are nonnegative everywhere.
+    pub fn verify(&self) -> Result<(), CFMMError> {
+        if self
+            .values
+            .iter()
+            .any(|v| !v.is_finite() || *v < -TOLERANCE)
+        {
+            return Err(CFMMError::PayoffNotReplicable);
+        }
+        let slopes = self.slopes();
+        if slopes.iter().any(|s| *s < -TOLERANCE) {
+            return Err(CFMMError::PayoffNotReplicable);
+        }
+        if slopes.windows(2).any(|s| s[1] > s[0] + TOLERANCE) {
+            return Err(CFMMError::PayoffNotReplicable);
+        }
+        // Quote reserve at the lowest price must be nonnegative.
+        if self.values[0] - self.prices[0] * slopes[0] < -TOLERANCE {
+            return Err(CFMMError::PayoffNotReplicable);
+        }
+        Ok(())
+    }
+
+    /// Base reserve when the price is below the grid (everything is held in
+    /// the base asset), followed by `V'` on each interval, followed by zero
+    /// (everything is held in the quote asset).
+    fn base_reserve_levels(&self) -> Vec<f64> {
+        let mut levels = vec![self.values[0] / self.prices[0]];
+        levels.extend(self.slopes().into_iter().map(|s| s.max(0.)));
+        levels.push(0.);
+        levels
+    }
+
+    fn segments(&self) -> Vec<Segment> {
+        let levels = self.base_reserve_levels();
+        self.prices
+            .iter()
+            .enumerate()
+            .map(|(j, p)| Segment {
+                price: *p,
+                base_low: levels[j + 1],
+                base_high: levels[j],
+            })
+            .collect()
+    }
+
+    /// Reserves `(base, quote)` which replicate the payoff at `price`.
+    pub fn reserves_at(&self, price: f64) -> (f64, f64) {
+        let levels = self.base_reserve_levels();
+        // number of grid prices which are not higher than `price`.
+        let j = self.prices.iter().take_while(|p| **p <= price).count();
+        if j == 0 {
+            return (levels[0], 0.);
+        }
+        let base = levels[j];
+        let quote = self.values[j - 1] - self.prices[j - 1] * base;
+        (base, quote.max(0.))
+    }
+}
+
+/// CFMM whose portfolio value follows a `PayoffCurve`.
+/// The base asset is the underlying, and the quote asset is the numeraire.
+#[derive(Debug, Clone)]
+pub struct ReplicatingMarketMaker {
+    base_asset: AssetInfo,
+    quote_asset: AssetInfo,
+    /// Sorted by price in ascending order.
+    segments: Vec<Segment>,
+}
+
+impl ReplicatingMarketMaker {
+    /// Create the pool with the reserves which replicate `curve` at
+    /// `initial_price`.
+    pub fn try_create(
+        curve: &PayoffCurve,
+        base: (AssetId, String),
+        quote: (AssetId, String),
+        initial_price: f64,
+    ) -> Result<Self, CFMMError> {
+        curve.verify()?;
+        if !initial_price.is_finite() || initial_price <= 0. {
+            return Err(CFMMError::InvalidCurveParam);
+        }
+        let (base_amount, quote_amount) = curve.reserves_at(initial_price);
+        let mut base_asset = AssetInfo::new(base.0, 0, base.1);
+        let mut quote_asset = AssetInfo::new(quote.0, 0, quote.1);
+        base_asset.set_reserve(base_amount)?;
+        quote_asset.set_reserve(quote_amount)?;
+        Ok(Self {
+            base_asset,
+            quote_asset,
+            segments: curve.segments(),
+        })
+    }
+
+    fn base_reserve(&self) -> f64 {
+        self.base_asset.reserve()
+    }
+
+    /// Walk along the curve. `base_in` is true when the pool receives the
+    /// base asset. `amount` is in the base asset if `in_base`, otherwise in
+    /// the quote asset.
+    /// Returns the amount of the other asset.
+    fn walk(&self, base_in: bool, amount: f64, in_base: bool) -> Result<f64, CFMMError> {
+        let mut base = self.base_reserve();
+        let mut rest = amount;
+        let mut other = 0.;
+        // Receiving base asset moves the price down, and vice versa.
+        let segments: Box<dyn Iterator<Item = &Segment>> = if base_in {
+            Box::new(self.segments.iter().rev())
+        } else {
+            Box::new(self.segments.iter())
+        };
+        for s in segments {
+            if rest <= 0. {
+                break;
+            }
+            let room = if base_in {
+                s.base_high - base
+            } else {
+                base - s.base_low
+            };
+            if room <= TOLERANCE {
+                continue;
+            }
+            let step_base = if in_base {
+                rest.min(room)
+            } else {
+                (rest / s.price).min(room)
+            };
+            let (step_in, step_out) = if in_base {
+                (step_base, step_base * s.price)
+            } else {
+                (step_base * s.price, step_base)
+            };
+            rest -= step_in;
+            other += step_out;
+            base += if base_in { step_base } else { -step_base };
+        }
+        if rest > TOLERANCE * amount.max(1.) {
+            return Err(CFMMError::InsufficientLiquidity);
+        }
+        Ok(other)
+    }
+
+    /// Returns the amount of the counter asset.
+    fn compute(&self, order: &OrderInfo) -> Result<f64, CFMMError> {
+        if order.amount.is_zero() {
+            return Err(CFMMError::InvalidOrderAmount);
+        }
+        let amount = order.quantity();
+        let in_base = order.index == AssetIndex::Zero;
+        // Pool receives base asset when user sells base or buys quote.
+        let base_in = in_base != order.is_buy();
+        self.walk(base_in, amount, in_base)
+    }
+}
+
+impl ConstantFunctionMarketMaker for ReplicatingMarketMaker {
+    fn base_asset(&self) -> &AssetInfo {
+        &self.base_asset
+    }
+
+    fn quote_asset(&self) -> &AssetInfo {
+        &self.quote_asset
+    }
+
+    fn base_asset_mut(&mut self) -> &mut AssetInfo {
+        &mut self.base_asset
+    }
+
+    fn quote_asset_mut(&mut self) -> &mut AssetInfo {
+        &mut self.quote_asset
+    }
+
+    fn spot_price(&self) -> f64 {
+        let base = self.base_reserve();
+        let price = self
+            .segments
+            .iter()
+            .find(|s| base >= s.base_low - TOLERANCE && base <= s.base_high + TOLERANCE)
+            .or_else(|| self.segments.last())
+            .map_or(1., |s| s.price);
+        1. / price
+    }
+
+    fn price_for_order(&self, order: &OrderInfo) -> Result<f64, CFMMError> {
+        self.compute(order)
+    }
+
+    fn order(&mut self, order: &OrderInfo) -> Result<f64, CFMMError> {
+        let amount_y = self.compute(order)?;
+        self.apply_order(order, amount_y)?;
+        Ok(amount_y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amplify::Wrapper;
+
+    use super::{PayoffCurve, ReplicatingMarketMaker};
+    use crate::{
+        cfmm::{AssetIndex, ConstantFunctionMarketMaker, Error, OrderInfo, OrderType},
+        Amount, AssetId,
+    };
+
+    /// Smallest units per coin.
+    const UNIT: f64 = 1e8;
+
+    #[test]
+    fn presets_must_be_replicable() {
+        let call = PayoffCurve::covered_call(100., 2., 10., 1000., 64).unwrap();
+        assert_eq!(call.reserves_at(50.), (2., 0.));
+        assert_eq!(call.reserves_at(200.), (0., 200.));
+        let put = PayoffCurve::cash_secured_put(100., 100., 10., 1000., 64).unwrap();
+        assert_eq!(put.reserves_at(50.), (1., 0.));
+        assert_eq!(put.reserves_at(200.), (0., 100.));
+
+        // Long call is convex, so it can not be held by a CFMM.
+        let long_call = PayoffCurve::from_fn(|p| (p - 100.).max(0.), 10., 1000., 64, &[100.]);
+        assert_eq!(long_call, Err(Error::PayoffNotReplicable));
+    }
+
+    #[test]
+    fn reserves_must_follow_payoff() {
+        let curve = PayoffCurve::from_fn(|p| UNIT * p.sqrt(), 1., 10000., 256, &[]).unwrap();
+        let mut rmm = ReplicatingMarketMaker::try_create(
+            &curve,
+            (AssetId::from_inner([0; 32]), "BTC".to_owned()),
+            (AssetId::from_inner([1; 32]), "USD".to_owned()),
+            100.,
+        )
+        .unwrap();
+        let price_before = 1. / rmm.spot_price();
+        let order = OrderInfo::new(
+            AssetIndex::One,
+            Amount::new(AssetId::from_inner([1; 32]), UNIT as u64),
+            OrderType::Sell,
+        );
+        rmm.order(&order).unwrap();
+        let price = 1. / rmm.spot_price();
+        assert!(price > price_before);
+        let (base, quote) = curve.reserves_at(price);
+        let base_now = rmm.base_asset().reserve();
+        let quote_now = rmm.quote_asset().reserve();
+        // Value of the reserves equals the payoff at the new price, up to
+        // the rounding to units.
+        let value = base * price + quote;
+        assert!((base_now * price + quote_now - value).abs() < 1e-6 * value);
+    }
+}
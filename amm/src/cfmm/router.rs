@@ -0,0 +1,412 @@
+//! Router which finds the best path to swap one asset for another across
+//! several CFMM pools.
+//!
+//! Pools form a graph where assets are nodes and pools are edges.
+//! The router enumerates paths up to `max_hops`, and can split an order
+//! across parallel paths when that gives a better output.
+//!
+//! Orders and quotes are `Amount`s, as every pool trades whole units.
+
+use crate::{Amount, AmountError, AssetId};
+
+use super::{ConstantFunctionMarketMaker, Error as CFMMError, OrderInfo, OrderType};
+
+/// Paths longer than this are not considered by default.
+pub const DEFAULT_MAX_HOPS: usize = 3;
+
+/// Number of chunks an order is divided into when splitting across paths.
+pub const DEFAULT_SPLIT_STEPS: usize = 10;
+
+/// Index of the pool in the `Router`.
+pub type PoolId = usize;
+
+/// Single swap inside a route.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HopQuote {
+    pub pool: PoolId,
+    pub amount_in: Amount,
+    pub amount_out: Amount,
+}
+
+/// Swaps executed one after another, from the input asset to the output asset.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RouteQuote {
+    pub hops: Vec<HopQuote>,
+    pub amount_in: Amount,
+    pub amount_out: Amount,
+}
+
+/// An order, possibly split across several routes.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Quote {
+    pub routes: Vec<RouteQuote>,
+    pub amount_in: Amount,
+    pub amount_out: Amount,
+}
+
+#[derive(Debug, Clone)]
+pub struct Router<P> {
+    pools: Vec<P>,
+    max_hops: usize,
+    split_steps: usize,
+}
+
+impl<P> Default for Router<P> {
+    fn default() -> Self {
+        Self {
+            pools: vec![],
+            max_hops: DEFAULT_MAX_HOPS,
+            split_steps: DEFAULT_SPLIT_STEPS,
+        }
+    }
+}
+
+/// Sell `amount_in` to the pool. Returns the amount of the other asset,
+/// which is what the reserves of the pool actually moved.
+fn swap<P: ConstantFunctionMarketMaker>(
+    pool: &mut P,
+    amount_in: &Amount,
+) -> Result<Amount, CFMMError> {
+    if amount_in.is_zero() {
+        return Err(CFMMError::InvalidOrderAmount);
+    }
+    let index = pool.index_of(amount_in.asset())?;
+    let order = OrderInfo::new(index, amount_in.clone(), OrderType::Sell);
+    let before = pool.asset_by_index(index.other()).amount().clone();
+    pool.order(&order)?;
+    let amount_out = before.checked_sub(pool.asset_by_index(index.other()).amount())?;
+    if amount_out.is_zero() {
+        return Err(CFMMError::InsufficientLiquidity);
+    }
+    Ok(amount_out)
+}
+
+/// Sum of the amounts, which are all of `asset`.
+fn sum<'a>(
+    asset: &AssetId,
+    mut amounts: impl Iterator<Item = &'a Amount>,
+) -> Result<Amount, CFMMError> {
+    amounts.try_fold(Amount::zero(asset.clone()), |total, amount| {
+        Ok(total.checked_add(amount)?)
+    })
+}
+
+impl<P: ConstantFunctionMarketMaker + Clone> Router<P> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_hops(mut self, max_hops: usize) -> Self {
+        self.max_hops = max_hops;
+        self
+    }
+
+    pub fn with_split_steps(mut self, split_steps: usize) -> Self {
+        self.split_steps = split_steps.max(1);
+        self
+    }
+
+    pub fn add_pool(&mut self, pool: P) -> PoolId {
+        self.pools.push(pool);
+        self.pools.len() - 1
+    }
+
+    pub fn pool(&self, id: PoolId) -> Option<&P> {
+        self.pools.get(id)
+    }
+
+    pub fn pools(&self) -> &[P] {
+        &self.pools
+    }
+
+    /// Pools which trade `asset`, along with the asset on the other side.
+    fn neighbours<'a>(
+        &'a self,
+        asset: &'a AssetId,
+    ) -> impl Iterator<Item = (PoolId, AssetId)> + 'a {
+        self.pools.iter().enumerate().filter_map(move |(i, p)| {
+            let other = match p.index_of(asset).ok()? {
+                super::AssetIndex::Zero => p.quote_asset(),
+                super::AssetIndex::One => p.base_asset(),
+            };
+            Some((i, other.id.clone()))
+        })
+    }
+
+    /// All paths (as a sequence of pools) from `from` to `to` which visit
+    /// each asset at most once.
+    pub fn find_paths(&self, from: &AssetId, to: &AssetId) -> Vec<Vec<PoolId>> {
+        let mut paths = vec![];
+        let mut visited = vec![from.clone()];
+        let mut current = vec![];
+        self.find_paths_inner(from, to, &mut visited, &mut current, &mut paths);
+        paths
+    }
+
+    fn find_paths_inner(
+        &self,
+        at: &AssetId,
+        to: &AssetId,
+        visited: &mut Vec<AssetId>,
+        current: &mut Vec<PoolId>,
+        paths: &mut Vec<Vec<PoolId>>,
+    ) {
+        if current.len() >= self.max_hops {
+            return;
+        }
+        for (pool, next) in self.neighbours(at) {
+            if visited.contains(&next) {
+                continue;
+            }
+            current.push(pool);
+            if &next == to {
+                paths.push(current.clone());
+            } else {
+                visited.push(next.clone());
+                self.find_paths_inner(&next, to, visited, current, paths);
+                visited.pop();
+            }
+            current.pop();
+        }
+    }
+
+    fn simulate_path(
+        pools: &mut [P],
+        path: &[PoolId],
+        amount_in: &Amount,
+    ) -> Result<RouteQuote, CFMMError> {
+        let mut hops = Vec::with_capacity(path.len());
+        let mut amount = amount_in.clone();
+        for &id in path {
+            let amount_out = swap(&mut pools[id], &amount)?;
+            hops.push(HopQuote {
+                pool: id,
+                amount_in: amount,
+                amount_out: amount_out.clone(),
+            });
+            amount = amount_out;
+        }
+        Ok(RouteQuote {
+            hops,
+            amount_in: amount_in.clone(),
+            amount_out: amount,
+        })
+    }
+
+    /// Runs each path with its allocated amount one after another, against
+    /// a copy of the pools.
+    fn simulate_allocation(
+        &self,
+        paths: &[Vec<PoolId>],
+        allocation: &[u64],
+        from: &AssetId,
+        to: &AssetId,
+    ) -> Result<Quote, CFMMError> {
+        let mut pools = self.pools.clone();
+        let mut routes = vec![];
+        for (path, &units) in paths.iter().zip(allocation) {
+            if units == 0 {
+                continue;
+            }
+            let amount = Amount::new(from.clone(), units);
+            routes.push(Self::simulate_path(&mut pools, path, &amount)?);
+        }
+        Ok(Quote {
+            amount_in: sum(from, routes.iter().map(|r| &r.amount_in))?,
+            amount_out: sum(to, routes.iter().map(|r| &r.amount_out))?,
+            routes,
+        })
+    }
+
+    /// Quote for the single path which gives the best output.
+    pub fn quote_best_route(
+        &self,
+        amount_in: &Amount,
+        to: &AssetId,
+    ) -> Result<RouteQuote, CFMMError> {
+        if amount_in.is_zero() {
+            return Err(CFMMError::InvalidOrderAmount);
+        }
+        self.find_paths(amount_in.asset(), to)
+            .iter()
+            .filter_map(|path| {
+                let mut pools = self.pools.clone();
+                Self::simulate_path(&mut pools, path, amount_in).ok()
+            })
+            .max_by_key(|route| route.amount_out.units())
+            .ok_or(CFMMError::NoRouteFound)
+    }
+
+    /// Quote which may split the order across several paths.
+    /// The order is divided into `split_steps` chunks, and each chunk is
+    /// greedily given to the path which maximizes the total output. The last
+    /// chunk also takes the units left over by the division.
+    pub fn quote(&self, amount_in: &Amount, to: &AssetId) -> Result<Quote, CFMMError> {
+        if amount_in.is_zero() {
+            return Err(CFMMError::InvalidOrderAmount);
+        }
+        let from = amount_in.asset();
+        let paths = self.find_paths(from, to);
+        if paths.is_empty() {
+            return Err(CFMMError::NoRouteFound);
+        }
+        let steps = self.split_steps as u64;
+        let mut allocation = vec![0; paths.len()];
+        let mut best = None;
+        for step in 0..steps {
+            let chunk = if step + 1 == steps {
+                amount_in.units() - amount_in.units() / steps * step
+            } else {
+                amount_in.units() / steps
+            };
+            let mut step_best: Option<(usize, Quote)> = None;
+            for i in 0..paths.len() {
+                allocation[i] += chunk;
+                if let Ok(q) = self.simulate_allocation(&paths, &allocation, from, to) {
+                    let is_better = match &step_best {
+                        Some((_, b)) => q.amount_out.units() > b.amount_out.units(),
+                        None => true,
+                    };
+                    if is_better {
+                        step_best = Some((i, q));
+                    }
+                }
+                allocation[i] -= chunk;
+            }
+            let (i, q) = step_best.ok_or(CFMMError::InsufficientLiquidity)?;
+            allocation[i] += chunk;
+            best = Some(q);
+        }
+        best.ok_or(CFMMError::NoRouteFound)
+    }
+
+    /// Execute every hop in the quote. If any hop fails, or the total output
+    /// is less than `min_amount_out`, all pools are rolled back to the state
+    /// before the execution.
+    /// Returns the total amount of the output asset.
+    pub fn execute(&mut self, quote: &Quote, min_amount_out: &Amount) -> Result<Amount, CFMMError> {
+        let snapshot = self.pools.clone();
+        match self.execute_inner(quote) {
+            Ok(amount_out) if amount_out >= *min_amount_out => Ok(amount_out),
+            Ok(amount_out) if amount_out.asset() != min_amount_out.asset() => {
+                self.pools = snapshot;
+                Err(AmountError::AssetMismatch.into())
+            }
+            Ok(_) => {
+                self.pools = snapshot;
+                Err(CFMMError::SlippageExceeded)
+            }
+            Err(e) => {
+                self.pools = snapshot;
+                Err(e)
+            }
+        }
+    }
+
+    fn execute_inner(&mut self, quote: &Quote) -> Result<Amount, CFMMError> {
+        let mut total = Amount::zero(quote.amount_out.asset().clone());
+        for route in &quote.routes {
+            let mut amount = route.amount_in.clone();
+            for hop in &route.hops {
+                let pool = self
+                    .pools
+                    .get_mut(hop.pool)
+                    .ok_or(CFMMError::NoRouteFound)?;
+                pool.index_of(hop.amount_out.asset())?;
+                amount = swap(pool, &amount)?;
+            }
+            total = total.checked_add(&amount)?;
+        }
+        Ok(total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amplify::Wrapper;
+
+    use super::Router;
+    use crate::{
+        cfmm::{cpmm::ConstantProductMarketMaker, ConstantFunctionMarketMaker, Error},
+        Amount, AmountError, AssetId, AssetInfo,
+    };
+
+    fn asset(n: u8) -> AssetId {
+        AssetId::from_inner([n; 32])
+    }
+
+    fn amount(n: u8, units: u64) -> Amount {
+        Amount::new(asset(n), units)
+    }
+
+    fn pool(a: u8, b: u8, amount_a: u64, amount_b: u64) -> ConstantProductMarketMaker {
+        ConstantProductMarketMaker::new(
+            AssetInfo::new(asset(a), amount_a, a.to_string()),
+            AssetInfo::new(asset(b), amount_b, b.to_string()),
+        )
+    }
+
+    #[test]
+    fn must_find_multi_hop_route() {
+        let mut router = Router::new();
+        router.add_pool(pool(0, 1, 1000, 1000));
+        router.add_pool(pool(1, 2, 1000, 1000));
+        let q = router.quote_best_route(&amount(0, 100), &asset(2)).unwrap();
+        assert_eq!(q.hops.len(), 2);
+        assert_eq!(q.hops[0].amount_out, q.hops[1].amount_in);
+        assert_eq!(q.amount_out.asset(), &asset(2));
+        assert!(q.amount_out.units() > 0 && q.amount_out.units() < 100);
+        assert_eq!(
+            router.quote_best_route(&amount(0, 10), &asset(3)),
+            Err(Error::NoRouteFound)
+        );
+        assert_eq!(
+            router.quote(&amount(0, 0), &asset(2)),
+            Err(Error::InvalidOrderAmount)
+        );
+    }
+
+    #[test]
+    fn split_must_not_be_worse_than_single_route() {
+        let mut router = Router::new();
+        router.add_pool(pool(0, 1, 100, 100));
+        router.add_pool(pool(0, 1, 100, 100));
+        let single = router.quote_best_route(&amount(0, 50), &asset(1)).unwrap();
+        let split = router.quote(&amount(0, 50), &asset(1)).unwrap();
+        assert_eq!(split.routes.len(), 2);
+        assert_eq!(split.amount_in, amount(0, 50));
+        assert!(split.amount_out > single.amount_out);
+
+        // The quote is exactly what the execution gives.
+        assert_eq!(
+            router.execute(&split, &amount(0, 0)),
+            Err(AmountError::AssetMismatch.into())
+        );
+        let out = router.execute(&split, &split.amount_out).unwrap();
+        assert_eq!(out, split.amount_out);
+    }
+
+    #[test]
+    fn must_rollback_when_slippage_exceeded() {
+        let mut router = Router::new();
+        router.add_pool(pool(0, 1, 1000, 1000));
+        router.add_pool(pool(1, 2, 1000, 1000));
+        let q = router.quote(&amount(0, 100), &asset(2)).unwrap();
+        router.execute(&q, &amount(2, 0)).unwrap();
+        let before: Vec<_> = router
+            .pools()
+            .iter()
+            .map(|p| (p.base_asset().reserve(), p.quote_asset().reserve()))
+            .collect();
+
+        // The same quote is stale now, so it must give a worse output.
+        let r = router.execute(&q, &q.amount_out);
+        assert_eq!(r, Err(Error::SlippageExceeded));
+        let after: Vec<_> = router
+            .pools()
+            .iter()
+            .map(|p| (p.base_asset().reserve(), p.quote_asset().reserve()))
+            .collect();
+        assert_eq!(before, after);
+    }
+}
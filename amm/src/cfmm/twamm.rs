@@ -0,0 +1,346 @@
+//! Time-Weighted Average Market Maker (TWAMM).
+//!
+//! A large order executed at once pays a large price impact. A long-term
+//! order instead sells at a constant rate until its expiry, as if it was
+//! split into infinitely many infinitely small orders. Orders on both sides
+//! are aggregated into a sale rate per asset, and the virtual trades are
+//! settled lazily whenever the pool is touched, using
+//! `ConstantFunctionMarketMaker::execute_virtual_orders`.
+//!
+//! Proceeds are tracked with a cumulative "earnings per unit of sale rate"
+//! for each side, so that settling does not need to iterate over orders.
+//! What the pool could not sell, e.g. less than a unit, is returned to the
+//! sellers the same way.
+//!
+//! Orders are `Amount`s, but sale rates and proceeds accrue continuously, so
+//! they are fractional numbers of units.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::Amount;
+
+use super::{
+    twap::Timestamp, AssetIndex, ConstantFunctionMarketMaker, Error as CFMMError, OrderInfo,
+};
+
+pub type LongTermOrderId = u64;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LongTermOrder {
+    /// Asset which is sold by this order.
+    pub sell_index: AssetIndex,
+    /// Amount sold per second.
+    pub sale_rate: f64,
+    pub start: Timestamp,
+    pub expiry: Timestamp,
+    /// Earnings and unsold amount per sale rate of the side when the order
+    /// was created or last withdrawn.
+    earnings_snapshot: f64,
+    unsold_snapshot: f64,
+}
+
+/// What the user gets back from a long-term order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LongTermOrderProceeds {
+    /// Part of the sold asset which has not been sold yet.
+    pub unsold: f64,
+    /// Amount of the other asset bought so far.
+    pub bought: f64,
+}
+
+/// State of one side (sellers of one asset).
+#[derive(Clone, Debug, Default)]
+struct Side {
+    sale_rate: f64,
+    /// Amount of the other asset earned per unit of sale rate so far.
+    earnings_per_rate: f64,
+    /// Amount of the sold asset returned unsold per unit of sale rate so far.
+    unsold_per_rate: f64,
+    /// Sale rate which ends at each expiry.
+    expiring_rates: BTreeMap<Timestamp, f64>,
+    /// `earnings_per_rate` and `unsold_per_rate` at each past expiry.
+    earnings_at_expiry: HashMap<Timestamp, (f64, f64)>,
+}
+
+/// Pool which accepts long-term orders.
+#[derive(Clone, Debug)]
+pub struct Twamm<P> {
+    pool: P,
+    last_execution: Timestamp,
+    /// Sellers of the base asset, and the sellers of the quote asset.
+    sides: [Side; 2],
+    orders: HashMap<LongTermOrderId, LongTermOrder>,
+    next_id: LongTermOrderId,
+}
+
+fn side_index(index: AssetIndex) -> usize {
+    match index {
+        AssetIndex::Zero => 0,
+        AssetIndex::One => 1,
+    }
+}
+
+impl<P: ConstantFunctionMarketMaker> Twamm<P> {
+    pub fn new(pool: P, now: Timestamp) -> Self {
+        Self {
+            pool,
+            last_execution: now,
+            sides: [Side::default(), Side::default()],
+            orders: HashMap::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn pool(&self) -> &P {
+        &self.pool
+    }
+
+    pub fn order_info(&self, id: LongTermOrderId) -> Option<&LongTermOrder> {
+        self.orders.get(&id)
+    }
+
+    /// Current sale rate of the sellers of the asset.
+    pub fn sale_rate(&self, index: AssetIndex) -> f64 {
+        self.sides[side_index(index)].sale_rate
+    }
+
+    fn execute_period(&mut self, until: Timestamp) -> Result<(), CFMMError> {
+        let dt = (until - self.last_execution) as f64;
+        let base_in = self.sides[0].sale_rate * dt;
+        let quote_in = self.sides[1].sale_rate * dt;
+        if base_in > 0. || quote_in > 0. {
+            let trades = self.pool.execute_virtual_orders(base_in, quote_in)?;
+            let [base, quote] = &mut self.sides;
+            if base.sale_rate > 0. {
+                base.earnings_per_rate += trades.quote_out / base.sale_rate;
+                base.unsold_per_rate += trades.base_unsold / base.sale_rate;
+            }
+            if quote.sale_rate > 0. {
+                quote.earnings_per_rate += trades.base_out / quote.sale_rate;
+                quote.unsold_per_rate += trades.quote_unsold / quote.sale_rate;
+            }
+        }
+        self.last_execution = until;
+        Ok(())
+    }
+
+    /// Settle every virtual trade until `now`, stopping at each expiry in
+    /// between to update the sale rates.
+    pub fn execute_virtual_orders(&mut self, now: Timestamp) -> Result<(), CFMMError> {
+        if now < self.last_execution {
+            return Err(CFMMError::InvalidTimestamp);
+        }
+        loop {
+            let next_expiry = self
+                .sides
+                .iter()
+                .filter_map(|s| s.expiring_rates.keys().next().cloned())
+                .min()
+                .filter(|t| *t <= now);
+            let until = match next_expiry {
+                Some(t) => t,
+                None => break,
+            };
+            self.execute_period(until)?;
+            for side in self.sides.iter_mut() {
+                if let Some(rate) = side.expiring_rates.remove(&until) {
+                    side.sale_rate = (side.sale_rate - rate).max(0.);
+                    side.earnings_at_expiry
+                        .insert(until, (side.earnings_per_rate, side.unsold_per_rate));
+                }
+            }
+        }
+        self.execute_period(now)
+    }
+
+    /// Sell `amount` evenly until `now + duration`.
+    pub fn submit(
+        &mut self,
+        amount: &Amount,
+        duration: u64,
+        now: Timestamp,
+    ) -> Result<LongTermOrderId, CFMMError> {
+        if amount.is_zero() || duration == 0 {
+            return Err(CFMMError::InvalidOrderAmount);
+        }
+        let sell_index = self.pool.index_of(amount.asset())?;
+        self.execute_virtual_orders(now)?;
+        let sale_rate = amount.to_f64() / duration as f64;
+        let expiry = now + duration;
+        let side = &mut self.sides[side_index(sell_index)];
+        side.sale_rate += sale_rate;
+        *side.expiring_rates.entry(expiry).or_insert(0.) += sale_rate;
+        let id = self.next_id;
+        self.next_id += 1;
+        self.orders.insert(
+            id,
+            LongTermOrder {
+                sell_index,
+                sale_rate,
+                start: now,
+                expiry,
+                earnings_snapshot: side.earnings_per_rate,
+                unsold_snapshot: side.unsold_per_rate,
+            },
+        );
+        Ok(id)
+    }
+
+    /// Earnings and unsold amount per sale rate for the order, which stop
+    /// growing after the expiry.
+    fn earnings_for(&self, order: &LongTermOrder) -> (f64, f64) {
+        let side = &self.sides[side_index(order.sell_index)];
+        let current = (side.earnings_per_rate, side.unsold_per_rate);
+        if order.expiry <= self.last_execution {
+            side.earnings_at_expiry
+                .get(&order.expiry)
+                .cloned()
+                .unwrap_or(current)
+        } else {
+            current
+        }
+    }
+
+    /// What the order has bought and what has been returned unsold since
+    /// the snapshot.
+    fn proceeds_for(&self, order: &LongTermOrder) -> LongTermOrderProceeds {
+        let (earnings, unsold) = self.earnings_for(order);
+        LongTermOrderProceeds {
+            unsold: order.sale_rate * (unsold - order.unsold_snapshot),
+            bought: order.sale_rate * (earnings - order.earnings_snapshot),
+        }
+    }
+
+    /// Withdraw what the order has bought so far, and what could not be
+    /// sold. An expired order is removed.
+    pub fn withdraw(
+        &mut self,
+        id: LongTermOrderId,
+        now: Timestamp,
+    ) -> Result<LongTermOrderProceeds, CFMMError> {
+        self.execute_virtual_orders(now)?;
+        let order = self.orders.get(&id).ok_or(CFMMError::UnknownOrder)?;
+        let proceeds = self.proceeds_for(order);
+        let (earnings, unsold) = self.earnings_for(order);
+        if order.expiry <= now {
+            self.orders.remove(&id);
+        } else if let Some(o) = self.orders.get_mut(&id) {
+            o.earnings_snapshot = earnings;
+            o.unsold_snapshot = unsold;
+        }
+        Ok(proceeds)
+    }
+
+    /// Cancel the order, returns the unsold part and what it has bought.
+    pub fn cancel(
+        &mut self,
+        id: LongTermOrderId,
+        now: Timestamp,
+    ) -> Result<LongTermOrderProceeds, CFMMError> {
+        self.execute_virtual_orders(now)?;
+        let order = self.orders.remove(&id).ok_or(CFMMError::UnknownOrder)?;
+        let mut proceeds = self.proceeds_for(&order);
+        if order.expiry > now {
+            proceeds.unsold += order.sale_rate * (order.expiry - now) as f64;
+            let side = &mut self.sides[side_index(order.sell_index)];
+            side.sale_rate = (side.sale_rate - order.sale_rate).max(0.);
+            if let Some(rate) = side.expiring_rates.get_mut(&order.expiry) {
+                *rate -= order.sale_rate;
+                if *rate <= 0. {
+                    side.expiring_rates.remove(&order.expiry);
+                }
+            }
+        }
+        Ok(proceeds)
+    }
+
+    /// Execute a regular order, after settling the virtual trades.
+    pub fn order(&mut self, order: &OrderInfo, now: Timestamp) -> Result<f64, CFMMError> {
+        self.execute_virtual_orders(now)?;
+        self.pool.order(order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amplify::Wrapper;
+
+    use super::Twamm;
+    use crate::{
+        cfmm::{
+            cpmm::ConstantProductMarketMaker, generic::GenericMarketMaker, AssetIndex,
+            ConstantFunctionMarketMaker, Error,
+        },
+        Amount, AssetId, AssetInfo,
+    };
+
+    const UNIT: u64 = 100_000_000;
+
+    fn assets() -> (AssetInfo, AssetInfo) {
+        (
+            AssetInfo::new(AssetId::from_inner([0; 32]), 1000 * UNIT, "A".to_owned()),
+            AssetInfo::new(AssetId::from_inner([1; 32]), 4000 * UNIT, "B".to_owned()),
+        )
+    }
+
+    #[test]
+    fn closed_form_must_match_numeric_execution() {
+        let (base, quote) = assets();
+        let mut cpmm = ConstantProductMarketMaker::new(base.clone(), quote.clone());
+        let mut generic =
+            GenericMarketMaker::try_create(base, quote, |x: f64, y: f64| x * y).unwrap();
+        let (base_in, quote_in) = (100. * UNIT as f64, 150. * UNIT as f64);
+        let closed = cpmm.execute_virtual_orders(base_in, quote_in).unwrap();
+        let numeric = generic.execute_virtual_orders(base_in, quote_in).unwrap();
+        assert!((closed.base_out - numeric.base_out).abs() / closed.base_out < 1e-3);
+        assert!((closed.quote_out - numeric.quote_out).abs() / closed.quote_out < 1e-3);
+        // The outputs are what the rounded reserves moved.
+        let base_end = cpmm.base_asset().reserve();
+        assert_eq!(closed.base_out, 1000. * UNIT as f64 + base_in - base_end);
+        assert_eq!(closed.base_unsold, 0.);
+    }
+
+    #[test]
+    fn imbalance_smaller_than_a_unit_must_not_create_output() {
+        let base = AssetInfo::new(AssetId::from_inner([0; 32]), 1_000_000, "A".to_owned());
+        let quote = AssetInfo::new(AssetId::from_inner([1; 32]), 1_000_000, "B".to_owned());
+        let mut generic =
+            GenericMarketMaker::try_create(base, quote, |x: f64, y: f64| x * y).unwrap();
+        // Every step has an imbalance of less than a unit of the base asset.
+        let (base_in, quote_in) = (40., 1.);
+        let trades = generic.execute_virtual_orders(base_in, quote_in).unwrap();
+        let base_to_pool = generic.base_asset().reserve() - 1_000_000.;
+        let quote_from_pool = 1_000_000. - generic.quote_asset().reserve();
+        assert!(base_to_pool > 0.);
+        // Less than a unit is returned unsold, the rest went to the pool or
+        // to the other side. The quote paid out is what the other side sold
+        // plus what the pool paid.
+        assert!((0. ..1.).contains(&trades.base_unsold));
+        let base_in_total = base_to_pool + trades.base_out + trades.base_unsold;
+        assert!((base_in - base_in_total).abs() < 1e-9);
+        assert!((trades.quote_out - quote_in - quote_from_pool).abs() < 1e-9);
+    }
+
+    #[test]
+    fn cancel_must_return_unsold_part() {
+        let (base, quote) = assets();
+        let (base_id, quote_id) = (base.id().clone(), quote.id().clone());
+        let mut twamm = Twamm::new(ConstantProductMarketMaker::new(base, quote), 0);
+        assert_eq!(
+            twamm.submit(&Amount::new(AssetId::from_inner([2; 32]), 100), 100, 0),
+            Err(Error::UnknownAssetId)
+        );
+        let id = twamm.submit(&Amount::new(base_id, 100), 100, 0).unwrap();
+        let other = twamm.submit(&Amount::new(quote_id, 100), 50, 0).unwrap();
+        let proceeds = twamm.cancel(id, 40).unwrap();
+        assert!((proceeds.unsold - 60.).abs() < 1e-9);
+        assert!(proceeds.bought > 0.);
+        assert_eq!(twamm.sale_rate(AssetIndex::Zero), 0.);
+
+        // The other order expires at 50, so it earns nothing after that.
+        let proceeds = twamm.withdraw(other, 60).unwrap();
+        assert!(proceeds.bought > 0.);
+        assert!(twamm.order_info(other).is_none());
+        assert_eq!(twamm.sale_rate(AssetIndex::One), 0.);
+    }
+}
@@ -0,0 +1,261 @@
+//! Time-weighted average price (TWAP) of CFMM pools.
+//!
+//! Spot price of a pool can be moved arbitrarily by a single large order,
+//! so it is not a good price reference (e.g. for settling numeric DLCs).
+//! Like Uniswap V2, we keep an accumulator of `price * elapsed_time`, which
+//! is updated before every order. TWAP over any window is the difference of
+//! the accumulator at both ends divided by the length of the window.
+//!
+//! Observations are kept in a fixed-size ring buffer, so memory usage is
+//! bounded and lookups are a binary search.
+
+use super::{ConstantFunctionMarketMaker, Error as CFMMError, OrderInfo};
+
+/// Unix timestamp in seconds.
+pub type Timestamp = u64;
+
+/// Value of the accumulator at a point of time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Observation {
+    pub timestamp: Timestamp,
+    /// Sum of `spot_price * elapsed_seconds` until `timestamp`.
+    pub price_cumulative: f64,
+    /// Spot price right after `timestamp`, it does not change until the
+    /// next observation.
+    pub price: f64,
+}
+
+impl Observation {
+    /// Accumulator at `timestamp`, which must not be older than `self`.
+    fn cumulative_at(&self, timestamp: Timestamp) -> f64 {
+        self.price_cumulative + self.price * (timestamp - self.timestamp) as f64
+    }
+}
+
+/// Fixed-size ring buffer of observations, ordered by timestamp.
+#[derive(Clone, Debug)]
+pub struct ObservationBuffer {
+    observations: Vec<Observation>,
+    capacity: usize,
+    /// Index of the oldest observation once the buffer is full.
+    head: usize,
+}
+
+impl ObservationBuffer {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            observations: Vec::with_capacity(capacity),
+            capacity,
+            head: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.observations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.observations.is_empty()
+    }
+
+    /// `i`-th oldest observation.
+    pub fn get(&self, i: usize) -> Option<&Observation> {
+        if i >= self.len() {
+            return None;
+        }
+        self.observations.get((self.head + i) % self.len())
+    }
+
+    pub fn oldest(&self) -> Option<&Observation> {
+        self.get(0)
+    }
+
+    pub fn latest(&self) -> Option<&Observation> {
+        self.len().checked_sub(1).and_then(|i| self.get(i))
+    }
+
+    fn latest_mut(&mut self) -> Option<&mut Observation> {
+        let len = self.len();
+        if len == 0 {
+            return None;
+        }
+        self.observations.get_mut((self.head + len - 1) % len)
+    }
+
+    pub fn push(&mut self, observation: Observation) {
+        if self.observations.len() < self.capacity {
+            self.observations.push(observation);
+        } else {
+            self.observations[self.head] = observation;
+            self.head = (self.head + 1) % self.capacity;
+        }
+    }
+
+    /// Latest observation which is not newer than `timestamp`.
+    fn at_or_before(&self, timestamp: Timestamp) -> Option<&Observation> {
+        // binary search for the number of observations with
+        // `observation.timestamp <= timestamp`.
+        let (mut lo, mut hi) = (0, self.len());
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if self.get(mid)?.timestamp <= timestamp {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        lo.checked_sub(1).and_then(|i| self.get(i))
+    }
+}
+
+/// Wraps a CFMM pool and keeps track of its time-weighted average price.
+#[derive(Clone, Debug)]
+pub struct TimeWeightedPool<P> {
+    pool: P,
+    observations: ObservationBuffer,
+}
+
+impl<P: ConstantFunctionMarketMaker> TimeWeightedPool<P> {
+    /// `capacity` is the maximum number of observations to keep.
+    pub fn new(pool: P, capacity: usize, timestamp: Timestamp) -> Self {
+        let mut observations = ObservationBuffer::new(capacity);
+        observations.push(Observation {
+            timestamp,
+            price_cumulative: 0.,
+            price: pool.spot_price(),
+        });
+        Self { pool, observations }
+    }
+
+    pub fn pool(&self) -> &P {
+        &self.pool
+    }
+
+    pub fn observations(&self) -> &ObservationBuffer {
+        &self.observations
+    }
+
+    /// Record the current spot price at `timestamp`.
+    /// This must be called whenever the reserves have changed.
+    pub fn observe(&mut self, timestamp: Timestamp) -> Result<(), CFMMError> {
+        let price = self.pool.spot_price();
+        let latest = *self
+            .observations
+            .latest()
+            .expect("There is always at least one observation");
+        if timestamp < latest.timestamp {
+            return Err(CFMMError::InvalidTimestamp);
+        }
+        if timestamp == latest.timestamp {
+            // The price before this order lasted for zero seconds, so it
+            // does not affect the accumulator.
+            if let Some(o) = self.observations.latest_mut() {
+                o.price = price;
+            }
+            return Ok(());
+        }
+        self.observations.push(Observation {
+            timestamp,
+            price_cumulative: latest.cumulative_at(timestamp),
+            price,
+        });
+        Ok(())
+    }
+
+    /// Execute the order against the pool, and update the accumulator.
+    pub fn order(&mut self, order: &OrderInfo, timestamp: Timestamp) -> Result<f64, CFMMError> {
+        if let Some(latest) = self.observations.latest() {
+            if timestamp < latest.timestamp {
+                return Err(CFMMError::InvalidTimestamp);
+            }
+        }
+        let amount = self.pool.order(order)?;
+        self.observe(timestamp)?;
+        Ok(amount)
+    }
+
+    /// Value of the accumulator at `timestamp`.
+    pub fn price_cumulative_at(&self, timestamp: Timestamp) -> Result<f64, CFMMError> {
+        self.observations
+            .at_or_before(timestamp)
+            .map(|o| o.cumulative_at(timestamp))
+            .ok_or(CFMMError::ObservationTooOld)
+    }
+
+    /// Time-weighted average price between `start` and `end`.
+    /// If `start == end`, returns the spot price at that time.
+    pub fn twap(&self, start: Timestamp, end: Timestamp) -> Result<f64, CFMMError> {
+        if end < start {
+            return Err(CFMMError::InvalidTimestamp);
+        }
+        if start == end {
+            return self
+                .observations
+                .at_or_before(start)
+                .map(|o| o.price)
+                .ok_or(CFMMError::ObservationTooOld);
+        }
+        let c0 = self.price_cumulative_at(start)?;
+        let c1 = self.price_cumulative_at(end)?;
+        Ok((c1 - c0) / (end - start) as f64)
+    }
+
+    /// Time-weighted average price of the last `window` seconds until `now`.
+    pub fn twap_over(&self, window: u64, now: Timestamp) -> Result<f64, CFMMError> {
+        let start = now.checked_sub(window).ok_or(CFMMError::InvalidTimestamp)?;
+        self.twap(start, now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amplify::Wrapper;
+
+    use super::{Observation, ObservationBuffer, TimeWeightedPool};
+    use crate::{
+        cfmm::{cpmm::ConstantProductMarketMaker, AssetIndex, Error, OrderInfo, OrderType},
+        Amount, AssetId, AssetInfo,
+    };
+
+    #[test]
+    fn ring_buffer_must_drop_oldest() {
+        let mut buf = ObservationBuffer::new(3);
+        for t in 0..5 {
+            buf.push(Observation {
+                timestamp: t,
+                price_cumulative: 0.,
+                price: 0.,
+            });
+        }
+        assert_eq!(buf.len(), 3);
+        assert_eq!(buf.oldest().unwrap().timestamp, 2);
+        assert_eq!(buf.latest().unwrap().timestamp, 4);
+        assert_eq!(buf.at_or_before(3).unwrap().timestamp, 3);
+        assert!(buf.at_or_before(1).is_none());
+    }
+
+    #[test]
+    fn twap_must_be_weighted_by_time() {
+        let pool = ConstantProductMarketMaker::new(
+            AssetInfo::new(AssetId::from_inner([0; 32]), 100, "A".to_owned()),
+            AssetInfo::new(AssetId::from_inner([1; 32]), 100, "B".to_owned()),
+        );
+        let mut pool = TimeWeightedPool::new(pool, 8, 100);
+        let p0 = pool.twap(100, 100).unwrap();
+        let order = OrderInfo::new(
+            AssetIndex::Zero,
+            Amount::new(AssetId::from_inner([0; 32]), 100),
+            OrderType::Sell,
+        );
+        pool.order(&order, 110).unwrap();
+        let p1 = pool.pool().price();
+
+        // 10 seconds with `p0`, 30 seconds with `p1`.
+        let twap = pool.twap(100, 140).unwrap();
+        assert!((twap - (p0 * 10. + p1 * 30.) / 40.).abs() < 1e-12);
+        assert_eq!(pool.twap_over(30, 140).unwrap(), p1);
+        assert_eq!(pool.twap(90, 140), Err(Error::ObservationTooOld));
+        assert_eq!(pool.order(&order, 105), Err(Error::InvalidTimestamp));
+    }
+}
@@ -2,7 +2,7 @@ use noisy_float::prelude::*;
 
 use crate::AssetInfo;
 
-use super::ConstantFunctionMarketMaker;
+use super::{ConstantFunctionMarketMaker, Error as CFMMError};
 
 pub struct UniswapV3MarketMaker {
     local_asset_1: AssetInfo,
@@ -25,11 +25,15 @@ impl ConstantFunctionMarketMaker for UniswapV3MarketMaker {
         &mut self.local_asset_2
     }
 
-    fn price_for_order(&self, order: &super::OrderInfo) -> f64 {
+    fn spot_price(&self) -> f64 {
         todo!()
     }
 
-    fn order(&mut self, order: &super::OrderInfo) -> f64 {
+    fn price_for_order(&self, order: &super::OrderInfo) -> Result<f64, CFMMError> {
+        todo!()
+    }
+
+    fn order(&mut self, order: &super::OrderInfo) -> Result<f64, CFMMError> {
         todo!()
     }
 }
@@ -1,12 +1,15 @@
+use std::convert::TryFrom;
+
 use super::{
     lmsr::{cost_function_md, price_for_purchase, price_for_showing},
     AMMError, CostFunctionMarketMaker,
 };
+use crate::utils::FinitePositiveFloat;
 
 /// `b` value must have certain amount for sane numerical computing
 pub const MINIMAL_LIQUIDITY_A: f64 = 0.0001;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct LSLMScoringRule {
     total_securities: Vec<f64>,
     alpha: f64,
@@ -16,9 +19,8 @@ impl LSLMScoringRule {
     pub fn try_create(num_outcomes: usize, alpha: f64) -> Result<Self, AMMError> {
         if num_outcomes <= 1 {
             Err(AMMError::OutcomeLessThanTwo)
-        } else if !alpha.is_normal() || alpha.is_sign_negative() {
-            Err(AMMError::BogusLiquidityParam)
-        } else if alpha < MINIMAL_LIQUIDITY_A {
+        } else if !matches!(FinitePositiveFloat::try_from(alpha), Ok(v) if v.inner() >= MINIMAL_LIQUIDITY_A)
+        {
             Err(AMMError::BogusLiquidityParam)
         } else {
             Ok(Self {
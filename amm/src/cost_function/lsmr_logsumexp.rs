@@ -1,6 +1,7 @@
-use std::f64::consts::E;
+use std::{convert::TryFrom, f64::consts::E};
 
 use super::{AMMError, CostFunctionMarketMaker};
+use crate::utils::FinitePositiveFloat;
 
 /// `b` value must have certain amount for sane numerical computing
 pub const MINIMAL_LIQUIDITY_B: f64 = 0.0001;
@@ -25,10 +26,11 @@ fn cost_function_md(inputs: &[f64], b: f64) -> Option<f64> {
 }
 
 pub(crate) fn price_for_purchase(total_security: &[f64], purchase_vector: &[f64], b: f64) -> f64 {
-    let mut total_security_after = Vec::with_capacity(total_security.len());
-    for (i, q) in total_security.iter().enumerate() {
-        total_security_after[i] = *q + purchase_vector[i];
-    }
+    let total_security_after: Vec<f64> = total_security
+        .iter()
+        .zip(purchase_vector)
+        .map(|(q, p)| q + p)
+        .collect();
     let a = cost_function_md(total_security_after.as_ref(), b).expect("Failed");
     let b = cost_function_md(total_security, b).expect("Failed");
     a - b
@@ -39,7 +41,7 @@ pub(crate) fn price_for_showing(total_security: &[f64], security_index: usize, b
     l(&total_security[security_index]) / total_security.iter().map(l).sum::<f64>()
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct LMScoringRule {
     total_securities: Vec<f64>,
     liquidity: f64,
@@ -49,9 +51,8 @@ impl LMScoringRule {
     pub fn try_create(outcomes: usize, liquidity: f64) -> Result<Self, AMMError> {
         if outcomes <= 1 {
             Err(AMMError::OutcomeLessThanTwo)
-        } else if !liquidity.is_normal() || liquidity.is_sign_negative() {
-            Err(AMMError::BogusLiquidityParam)
-        } else if liquidity < MINIMAL_LIQUIDITY_B {
+        } else if !matches!(FinitePositiveFloat::try_from(liquidity), Ok(v) if v.inner() >= MINIMAL_LIQUIDITY_B)
+        {
             Err(AMMError::BogusLiquidityParam)
         } else {
             Ok(Self {
@@ -2,7 +2,12 @@ pub mod lmsr;
 pub mod ls_lmsr;
 pub mod lsmr_logsumexp;
 
-use crate::cfmm::Error as CFMMError;
+use std::convert::TryFrom;
+
+use crate::{
+    cfmm::Error as CFMMError,
+    utils::{FinitePositiveFloat, FloatError},
+};
 
 use amplify::{Display, Error, From};
 
@@ -17,9 +22,17 @@ pub enum AMMError {
     /// Liquidity parameter is either Nan, infinite, 0, Negative
     BogusLiquidityParam,
     /// Error when tried to purchase some securities
+    #[from]
     PurchaseError(PurchaseError),
     /// Error for funding the CFMM.
+    #[from]
     FundingError(CFMMError),
+    /// Fee must be a number in `[0, 1)`
+    BogusFeeParam,
+    /// Market maker has no liquidity yet
+    NoLiquidity,
+    /// Liquidity provider does not have enough pool shares
+    InsufficientShares,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Display, Error, From)]
@@ -40,19 +53,33 @@ pub enum PurchaseError {
 
     /// An user tried to purchase an asset with the same asset.
     CannotPurchaseWithSameAsset,
+
+    /// Outcome index is out of range.
+    UnknownOutcome,
+
+    /// Market maker does not have enough reserves to fill the order.
+    InsufficientLiquidity,
+
+    /// The result of the trade is worse than the limit specified by the user.
+    SlippageExceeded,
 }
-fn is_fine_purchase(purchase_vector: &[f64]) -> Result<(), PurchaseError> {
+impl From<FloatError> for PurchaseError {
+    fn from(err: FloatError) -> Self {
+        match err {
+            FloatError::Nan | FloatError::Infinite => PurchaseError::NonNormalPurchase,
+            FloatError::Negative => PurchaseError::NegativePurchase,
+            FloatError::Zero => PurchaseError::TooSmall,
+        }
+    }
+}
+
+pub(crate) fn is_fine_purchase(purchase_vector: &[f64]) -> Result<(), PurchaseError> {
     let mut all_zero = true;
     for p in purchase_vector {
         if all_zero {
             all_zero = p.abs() < MINIMAL_PURCHASE;
         }
-        if p.is_nan() || p.is_infinite() {
-            return Err(PurchaseError::NonNormalPurchase);
-        }
-        if p.is_sign_negative() {
-            return Err(PurchaseError::NegativePurchase);
-        }
+        FinitePositiveFloat::try_from(*p)?;
     }
     if all_zero {
         return Err(PurchaseError::TooSmall);
@@ -199,6 +226,20 @@ mod tests {
         }
     }
 
+    #[test]
+    fn price_for_purchase_must_be_the_change_of_cost() {
+        let msrs: Vec<Box<dyn CostFunctionMarketMaker>> = vec![
+            Box::new(LMSR::try_create(2, 10.).unwrap()),
+            Box::new(LogSumExpLMSR::try_create(2, 10.).unwrap()),
+        ];
+        for mut msr in msrs {
+            let before = msr.cost_function();
+            let price = msr.price_for_purchase(&[1., 0.]);
+            msr.purchase(&[1., 0.]).unwrap();
+            assert!((price - (msr.cost_function() - before)).abs() < 1e-9);
+        }
+    }
+
     #[test]
     fn must_reject_too_small_liquidity() {
         let liquidity = 7.212815578282739e-276;
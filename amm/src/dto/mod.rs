@@ -1,17 +1,121 @@
+use amplify::{From, Wrapper};
 use serde;
 
-#[derive(Clone, PartialEq, Debug, Eq, serde::Deserialize, serde::Serialize)]
+use crate::{
+    cfmm::{
+        fpmm::FixedProductMarketMaker, ConstantFunctionMarketMaker, Error as CFMMError, OrderInfo,
+        OrderType,
+    },
+    cost_function::{is_fine_purchase, AMMError, CostFunctionMarketMaker, PurchaseError},
+    Amount,
+};
+
+#[derive(
+    Clone, PartialEq, Debug, Eq, Hash, From, Wrapper, serde::Deserialize, serde::Serialize,
+)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct MarketId(u64);
 
+#[derive(async_graphql::InputObject)]
 #[cfg_attr(
     any(test, feature = "serde"),
     derive(serde::Deserialize, serde::Serialize),
     serde(rename_all = "camelCase")
 )]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct GetPriceForPurchase {
+    /// Securities of each outcome, by outcome index.
     pub purchase_vector: Vec<f64>,
 }
 
+impl GetPriceForPurchase {
+    /// Cost of the purchase, if it would be accepted by
+    /// `CostFunctionMarketMaker::purchase`.
+    pub fn price<M: CostFunctionMarketMaker + ?Sized>(&self, mm: &M) -> Result<f64, PurchaseError> {
+        check_vector(mm, &self.purchase_vector)?;
+        Ok(mm.price_for_purchase(&self.purchase_vector))
+    }
+}
+
+fn check_vector<M: CostFunctionMarketMaker + ?Sized>(
+    mm: &M,
+    vector: &[f64],
+) -> Result<(), PurchaseError> {
+    is_fine_purchase(vector)?;
+    if vector.len() != mm.total_securities().len() {
+        return Err(PurchaseError::WrongPurchaseLength);
+    }
+    Ok(())
+}
+
+/// Securities of one outcome bought or sold by a `Purchase` or a `Sell`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fill {
+    pub index: usize,
+    pub amount: f64,
+    /// Collateral paid for a purchase, or received for a sale.
+    pub cost: f64,
+}
+
+/// Move the securities of each outcome in turn, `sign` is `1.` to buy them
+/// and `-1.` to sell them. The cost function only depends on the final
+/// quantities, so the fills add up to the cost of moving all of them at once.
+fn fill<M: CostFunctionMarketMaker + ?Sized>(mm: &mut M, vector: &[f64], sign: f64) -> Vec<Fill> {
+    let mut fills = vec![];
+    for (index, amount) in vector.iter().enumerate() {
+        if *amount == 0. {
+            continue;
+        }
+        let mut single = vec![0.; vector.len()];
+        single[index] = sign * amount;
+        let cost = sign * mm.price_for_purchase(&single);
+        mm.total_securities_mut()[index] += sign * amount;
+        fills.push(Fill {
+            index,
+            amount: *amount,
+            cost,
+        });
+    }
+    fills
+}
+
+/// Move the securities of each outcome in turn through the fixed product
+/// market maker, which takes its fee from each of them. Nothing is moved
+/// unless all of them are.
+fn fill_fixed_product(
+    mm: &mut FixedProductMarketMaker,
+    vector: &[f64],
+    buy: bool,
+) -> Result<Vec<Fill>, AMMError> {
+    is_fine_purchase(vector)?;
+    if vector.len() != mm.balances().len() {
+        return Err(PurchaseError::WrongPurchaseLength.into());
+    }
+    let mut next = mm.clone();
+    let mut fills = vec![];
+    for (index, amount) in vector.iter().enumerate() {
+        if *amount == 0. {
+            continue;
+        }
+        let cost = if buy {
+            let investment = next.calc_buy_investment(*amount, index)?;
+            next.buy(investment, index, 0.)?;
+            investment
+        } else {
+            let return_amount = next.calc_sell_return(*amount, index)?;
+            next.sell(return_amount, index, *amount)?;
+            return_amount
+        };
+        fills.push(Fill {
+            index,
+            amount: *amount,
+            cost,
+        });
+    }
+    *mm = next;
+    Ok(fills)
+}
+
 #[cfg_attr(
     any(test, feature = "serde"),
     derive(serde::Deserialize, serde::Serialize),
@@ -19,11 +123,170 @@ pub struct GetPriceForPurchase {
 )]
 pub struct GetPricesAtThePoint {}
 
+#[derive(async_graphql::InputObject)]
 #[cfg_attr(
     any(test, feature = "serde"),
     derive(serde::Deserialize, serde::Serialize),
     serde(rename_all = "camelCase")
 )]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
 pub struct Purchase {
+    /// Securities of each outcome, by outcome index.
     pub purchase_vector: Vec<f64>,
 }
+
+impl Purchase {
+    /// Buy the securities from the market maker, one fill per outcome.
+    pub fn execute<M: CostFunctionMarketMaker + ?Sized>(
+        &self,
+        mm: &mut M,
+    ) -> Result<Vec<Fill>, PurchaseError> {
+        check_vector(mm, &self.purchase_vector)?;
+        Ok(fill(mm, &self.purchase_vector, 1.))
+    }
+
+    /// Buy the securities from the fixed product market maker, one fill per
+    /// outcome.
+    pub fn execute_fixed_product(
+        &self,
+        mm: &mut FixedProductMarketMaker,
+    ) -> Result<Vec<Fill>, AMMError> {
+        fill_fixed_product(mm, &self.purchase_vector, true)
+    }
+}
+
+/// Securities of each outcome sold back to the market maker.
+#[derive(async_graphql::InputObject)]
+#[cfg_attr(
+    any(test, feature = "serde"),
+    derive(serde::Deserialize, serde::Serialize),
+    serde(rename_all = "camelCase")
+)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+pub struct Sell {
+    /// Securities of each outcome, by outcome index.
+    pub sell_vector: Vec<f64>,
+}
+
+impl Sell {
+    /// Sell the securities to the market maker, one fill per outcome. This
+    /// does not check that the seller holds them.
+    pub fn execute<M: CostFunctionMarketMaker + ?Sized>(
+        &self,
+        mm: &mut M,
+    ) -> Result<Vec<Fill>, PurchaseError> {
+        check_vector(mm, &self.sell_vector)?;
+        Ok(fill(mm, &self.sell_vector, -1.))
+    }
+
+    /// Sell the securities to the fixed product market maker, one fill per
+    /// outcome. This does not check that the seller holds them.
+    pub fn execute_fixed_product(
+        &self,
+        mm: &mut FixedProductMarketMaker,
+    ) -> Result<Vec<Fill>, AMMError> {
+        fill_fixed_product(mm, &self.sell_vector, false)
+    }
+}
+
+/// Order against a CFMM pool, the asset of `amount` selects the side.
+#[cfg_attr(
+    any(test, feature = "serde"),
+    derive(serde::Deserialize, serde::Serialize),
+    serde(rename_all = "camelCase")
+)]
+pub struct PlaceOrder {
+    pub amount: Amount,
+    pub order_type: OrderType,
+}
+
+impl PlaceOrder {
+    pub fn to_order<P: ConstantFunctionMarketMaker>(
+        &self,
+        pool: &P,
+    ) -> Result<OrderInfo, CFMMError> {
+        let index = pool.index_of(self.amount.asset())?;
+        Ok(OrderInfo::new(index, self.amount.clone(), self.order_type))
+    }
+}
+
+#[cfg_attr(
+    any(test, feature = "serde"),
+    derive(serde::Deserialize, serde::Serialize),
+    serde(rename_all = "camelCase")
+)]
+pub struct PoolReserves {
+    pub base: Amount,
+    pub quote: Amount,
+}
+
+impl PoolReserves {
+    pub fn of<P: ConstantFunctionMarketMaker>(pool: &P) -> Self {
+        Self {
+            base: pool.base_asset().amount().clone(),
+            quote: pool.quote_asset().amount().clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Purchase, Sell};
+    use crate::{
+        cfmm::fpmm::FixedProductMarketMaker,
+        cost_function::{lmsr::LMScoringRule, AMMError, CostFunctionMarketMaker, PurchaseError},
+    };
+
+    #[test]
+    fn fills_must_add_up_to_the_whole_trade() {
+        let mut lmsr = LMScoringRule::try_create(3, 10.).unwrap();
+        let purchase_vector = vec![2., 0., 5.];
+        let total = lmsr.price_for_purchase(&purchase_vector);
+        let fills = Purchase { purchase_vector }.execute(&mut lmsr).unwrap();
+        assert_eq!(fills.len(), 2);
+        assert_eq!((fills[1].index, fills[1].amount), (2, 5.));
+        let cost: f64 = fills.iter().map(|f| f.cost).sum();
+        assert!((cost - total).abs() < 1e-9);
+        assert_eq!(lmsr.total_securities(), &[2., 0., 5.]);
+
+        let sell_vector = vec![2., 0., 5.];
+        let fills = Sell { sell_vector }.execute(&mut lmsr).unwrap();
+        let received: f64 = fills.iter().map(|f| f.cost).sum();
+        assert!((received - total).abs() < 1e-9);
+        assert_eq!(lmsr.total_securities(), &[0., 0., 0.]);
+
+        let sell_vector = vec![1., 1.];
+        assert_eq!(
+            Sell { sell_vector }.execute(&mut lmsr),
+            Err(PurchaseError::WrongPurchaseLength)
+        );
+    }
+
+    #[test]
+    fn fixed_product_fills_must_trade_the_exact_securities() {
+        let mut fpmm = FixedProductMarketMaker::try_create(2, 0.02).unwrap();
+        fpmm.add_liquidity("lp".to_owned(), 100.).unwrap();
+        let purchase_vector = vec![10., 0.];
+        let fills = Purchase { purchase_vector }
+            .execute_fixed_product(&mut fpmm)
+            .unwrap();
+        assert_eq!(fills.len(), 1);
+        assert_eq!((fills[0].index, fills[0].amount), (0, 10.));
+        assert!(fills[0].cost > 0. && fills[0].cost < 10.);
+        assert!(fpmm.prices()[0] > 0.5);
+
+        let sell_vector = vec![10., 0.];
+        let sold = Sell { sell_vector }
+            .execute_fixed_product(&mut fpmm)
+            .unwrap();
+        assert!(sold[0].cost < fills[0].cost);
+
+        let balances = fpmm.balances().to_vec();
+        let purchase_vector = vec![1., 1., 1.];
+        assert_eq!(
+            Purchase { purchase_vector }.execute_fixed_product(&mut fpmm),
+            Err(AMMError::PurchaseError(PurchaseError::WrongPurchaseLength))
+        );
+        assert_eq!(fpmm.balances(), &balances[..]);
+    }
+}
@@ -4,6 +4,7 @@ use sea_orm::entity::prelude::*;
 use serde;
 use std::convert::TryInto;
 
+/// Event attested by an oracle, e.g. "who wins the election".
 #[derive(
     Clone,
     Debug,
@@ -14,20 +15,29 @@ use std::convert::TryInto;
     DeriveEntityModel,
     SimpleObject,
 )]
-#[sea_orm(table_name = "market")]
+#[sea_orm(table_name = "decision")]
 #[graphql(concrete(name = "decision", params()))]
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
+    pub market_id: i32,
     pub name: String,
-    pub is_past: bool,
     pub oracle_public_key: Vec<u8>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
-    #[sea_orm(has_many = "super::market::Entity")]
+    #[sea_orm(
+        belongs_to = "super::market::Entity",
+        from = "Column::MarketId",
+        to = "super::market::Column::Id",
+        on_delete = "Cascade"
+    )]
     Market,
+    #[sea_orm(has_many = "super::outcome::Entity")]
+    Outcomes,
+    #[sea_orm(has_one = "super::resolution::Entity")]
+    Resolution,
 }
 
 impl Related<super::market::Entity> for Entity {
@@ -36,4 +46,16 @@ impl Related<super::market::Entity> for Entity {
     }
 }
 
+impl Related<super::outcome::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Outcomes.def()
+    }
+}
+
+impl Related<super::resolution::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Resolution.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
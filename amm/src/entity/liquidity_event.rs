@@ -0,0 +1,58 @@
+use sea_orm::entity::prelude::*;
+use serde;
+use std::convert::TryInto;
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    serde::Deserialize,
+    serde::Serialize,
+    EnumIter,
+    DeriveActiveEnum,
+)]
+#[sea_orm(rs_type = "String", db_type = "String(Some(8))")]
+pub enum Kind {
+    #[sea_orm(string_value = "add")]
+    Add,
+    #[sea_orm(string_value = "remove")]
+    Remove,
+}
+
+/// Funding added to or withdrawn from a market by a liquidity provider.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize, DeriveEntityModel)]
+#[sea_orm(table_name = "liquidity_event")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub market_id: i32,
+    pub provider: String,
+    pub kind: Kind,
+    /// Collateral moved by the event.
+    pub amount: f64,
+    /// Pool shares minted (for `Add`) or burnt (for `Remove`).
+    pub shares: f64,
+    /// Unix timestamp in seconds.
+    pub created_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::market::Entity",
+        from = "Column::MarketId",
+        to = "super::market::Column::Id",
+        on_delete = "Cascade"
+    )]
+    Market,
+}
+
+impl Related<super::market::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Market.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
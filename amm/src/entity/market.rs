@@ -2,6 +2,42 @@ use sea_orm::entity::prelude::*;
 use serde;
 use std::convert::TryInto;
 
+/// Lifecycle of a market, see `crate::lifecycle` for the legal transitions.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    serde::Deserialize,
+    serde::Serialize,
+    EnumIter,
+    DeriveActiveEnum,
+)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[sea_orm(rs_type = "String", db_type = "String(Some(16))")]
+pub enum Status {
+    /// Being set up, not tradable yet.
+    #[sea_orm(string_value = "draft")]
+    Draft,
+    #[sea_orm(string_value = "open")]
+    Open,
+    /// Trading is suspended, e.g. while investigating an incident.
+    #[sea_orm(string_value = "halted")]
+    Halted,
+    /// Trading is over, waiting for the oracle.
+    #[sea_orm(string_value = "closed")]
+    Closed,
+    /// Attestation received, payouts are being settled.
+    #[sea_orm(string_value = "resolving")]
+    Resolving,
+    #[sea_orm(string_value = "resolved")]
+    Resolved,
+    /// Market is void and every trade is refunded.
+    #[sea_orm(string_value = "cancelled")]
+    Cancelled,
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize, DeriveEntityModel)]
 #[sea_orm(table_name = "market")]
 pub struct Model {
@@ -9,13 +45,25 @@ pub struct Model {
     pub id: i32,
 
     pub name: String,
-    pub is_active: bool,
+    pub status: Status,
+    /// Serialized `crate::repository::MarketMakerState`.
+    pub market_maker: Option<Json>,
+    /// Incremented on every update of `market_maker`, for optimistic locking.
+    pub version: i64,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(has_many = "super::decision::Entity")]
     Decisions,
+    #[sea_orm(has_many = "super::trade::Entity")]
+    Trades,
+    #[sea_orm(has_many = "super::position::Entity")]
+    Positions,
+    #[sea_orm(has_many = "super::liquidity_event::Entity")]
+    LiquidityEvents,
+    #[sea_orm(has_many = "super::market_transition::Entity")]
+    Transitions,
 }
 
 impl Related<super::decision::Entity> for Entity {
@@ -24,10 +72,34 @@ impl Related<super::decision::Entity> for Entity {
     }
 }
 
+impl Related<super::trade::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Trades.def()
+    }
+}
+
+impl Related<super::position::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Positions.def()
+    }
+}
+
+impl Related<super::liquidity_event::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::LiquidityEvents.def()
+    }
+}
+
+impl Related<super::market_transition::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Transitions.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
 
 impl Entity {
     pub fn get_active_markets() -> Select<Entity> {
-        Self::find().filter(Column::IsActive.eq(true))
+        Self::find().filter(Column::Status.eq(Status::Open))
     }
 }
@@ -0,0 +1,38 @@
+use sea_orm::entity::prelude::*;
+use serde;
+use std::convert::TryInto;
+
+use super::market::Status;
+
+/// Audit record of a change of the status of a market.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize, DeriveEntityModel)]
+#[sea_orm(table_name = "market_transition")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub market_id: i32,
+    pub from_status: Status,
+    pub to_status: Status,
+    pub reason: Option<String>,
+    /// Unix timestamp in seconds.
+    pub created_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::market::Entity",
+        from = "Column::MarketId",
+        to = "super::market::Column::Id",
+        on_delete = "Cascade"
+    )]
+    Market,
+}
+
+impl Related<super::market::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Market.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -1,2 +1,8 @@
 pub mod decision;
+pub mod liquidity_event;
 pub mod market;
+pub mod market_transition;
+pub mod outcome;
+pub mod position;
+pub mod resolution;
+pub mod trade;
@@ -0,0 +1,58 @@
+use sea_orm::entity::prelude::*;
+use serde;
+use std::convert::TryInto;
+
+/// One of the possible outcomes of a decision, and the value the oracle
+/// signs for it. Exactly one of `oracle_value` and the range is set, see
+/// `crate::outcomes::OracleValue`.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize, DeriveEntityModel)]
+#[sea_orm(table_name = "outcome")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub decision_id: i32,
+    /// Index of the outcome in the purchase vector of the market maker, and
+    /// the order in which outcomes are displayed.
+    pub index: i32,
+    pub label: String,
+    /// String signed by the oracle, for enumerated events.
+    pub oracle_value: Option<String>,
+    /// Inclusive range of the value signed by the oracle, for numeric events.
+    pub range_start: Option<i64>,
+    pub range_end: Option<i64>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::decision::Entity",
+        from = "Column::DecisionId",
+        to = "super::decision::Column::Id",
+        on_delete = "Cascade"
+    )]
+    Decision,
+    #[sea_orm(has_many = "super::trade::Entity")]
+    Trades,
+    #[sea_orm(has_many = "super::position::Entity")]
+    Positions,
+}
+
+impl Related<super::decision::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Decision.def()
+    }
+}
+
+impl Related<super::trade::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Trades.def()
+    }
+}
+
+impl Related<super::position::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Positions.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,48 @@
+use sea_orm::entity::prelude::*;
+use serde;
+use std::convert::TryInto;
+
+/// Securities of one outcome held by a trader. There is at most one row per
+/// market, outcome and trader.
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize, DeriveEntityModel)]
+#[sea_orm(table_name = "position")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub market_id: i32,
+    pub outcome_id: i32,
+    pub trader: String,
+    pub amount: f64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::market::Entity",
+        from = "Column::MarketId",
+        to = "super::market::Column::Id",
+        on_delete = "Cascade"
+    )]
+    Market,
+    #[sea_orm(
+        belongs_to = "super::outcome::Entity",
+        from = "Column::OutcomeId",
+        to = "super::outcome::Column::Id",
+        on_delete = "Cascade"
+    )]
+    Outcome,
+}
+
+impl Related<super::market::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Market.def()
+    }
+}
+
+impl Related<super::outcome::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Outcome.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
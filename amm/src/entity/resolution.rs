@@ -0,0 +1,49 @@
+use sea_orm::entity::prelude::*;
+use serde;
+use std::convert::TryInto;
+
+/// Outcome attested by the oracle for a decision.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Deserialize, serde::Serialize, DeriveEntityModel)]
+#[sea_orm(table_name = "resolution")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(unique)]
+    pub decision_id: i32,
+    pub outcome_id: i32,
+    /// Serialized oracle attestation.
+    pub attestation: Vec<u8>,
+    /// Unix timestamp in seconds.
+    pub resolved_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::decision::Entity",
+        from = "Column::DecisionId",
+        to = "super::decision::Column::Id",
+        on_delete = "Cascade"
+    )]
+    Decision,
+    #[sea_orm(
+        belongs_to = "super::outcome::Entity",
+        from = "Column::OutcomeId",
+        to = "super::outcome::Column::Id"
+    )]
+    Outcome,
+}
+
+impl Related<super::decision::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Decision.def()
+    }
+}
+
+impl Related<super::outcome::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Outcome.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
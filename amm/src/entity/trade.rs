@@ -0,0 +1,74 @@
+use sea_orm::entity::prelude::*;
+use serde;
+use std::convert::TryInto;
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    PartialEq,
+    Eq,
+    serde::Deserialize,
+    serde::Serialize,
+    EnumIter,
+    DeriveActiveEnum,
+)]
+#[cfg_attr(feature = "openapi", derive(utoipa::ToSchema))]
+#[sea_orm(rs_type = "String", db_type = "String(Some(4))")]
+pub enum Side {
+    #[sea_orm(string_value = "buy")]
+    Buy,
+    #[sea_orm(string_value = "sell")]
+    Sell,
+}
+
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize, DeriveEntityModel)]
+#[sea_orm(table_name = "trade")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    pub market_id: i32,
+    pub outcome_id: i32,
+    pub trader: String,
+    pub side: Side,
+    /// Number of outcome securities.
+    pub amount: f64,
+    /// Collateral paid (for `Buy`) or received (for `Sell`).
+    pub cost: f64,
+    /// Price of every outcome after the trade, by outcome index.
+    pub prices: Json,
+    /// Unix timestamp in seconds.
+    pub created_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::market::Entity",
+        from = "Column::MarketId",
+        to = "super::market::Column::Id",
+        on_delete = "Cascade"
+    )]
+    Market,
+    #[sea_orm(
+        belongs_to = "super::outcome::Entity",
+        from = "Column::OutcomeId",
+        to = "super::outcome::Column::Id",
+        on_delete = "Cascade"
+    )]
+    Outcome,
+}
+
+impl Related<super::market::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Market.def()
+    }
+}
+
+impl Related<super::outcome::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Outcome.def()
+    }
+}
+
+impl ActiveModelBehavior for ActiveModel {}
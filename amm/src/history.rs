@@ -0,0 +1,365 @@
+//! Trade history, and the time series built from it for price charts.
+//!
+//! Every executed trade is stored in `entity::trade` with the price of every
+//! outcome right after it. Trades are aggregated in Rust rather than in SQL,
+//! so that the queries stay portable between database backends.
+//!
+//! Time series are bucketed by `interval` seconds, aligned to the Unix epoch,
+//! and a range holds at most `MAX_BUCKETS` buckets. Candles omit the buckets
+//! without any trade, as there is no price to open them at. Open interest is
+//! a level rather than a flow, so every bucket has a value, which a bucket
+//! without trades carries over from the previous one.
+
+use amplify::{Display, Error, From};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, QueryOrder, Set,
+};
+
+use crate::entity::{
+    decision, outcome,
+    trade::{self, Side},
+};
+
+/// Largest number of buckets in a time series.
+pub const MAX_BUCKETS: i64 = 10_000;
+
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum Error {
+    /// Interval must be a positive number of seconds
+    InvalidInterval,
+    /// Range holds more buckets than the maximum
+    TooManyBuckets,
+    /// Outcome does not belong to the market
+    UnknownOutcome,
+    /// Trade has no price for the outcome
+    MissingPrice,
+    /// Database error: {0}
+    #[from]
+    Db(sea_orm::DbErr),
+    /// Stored prices are invalid: {0}
+    #[from]
+    Serialization(serde_json::Error),
+}
+
+/// Trade to record, after it was executed by the market maker.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NewTrade {
+    pub market_id: i32,
+    pub outcome_id: i32,
+    pub trader: String,
+    pub side: Side,
+    pub amount: f64,
+    pub cost: f64,
+    /// Price of every outcome after the trade, by outcome index.
+    pub prices: Vec<f64>,
+    /// Unix timestamp in seconds.
+    pub created_at: i64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Candle {
+    /// Start of the bucket, Unix timestamp in seconds.
+    pub start: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    /// Securities of the outcome traded in the bucket, both sides.
+    pub volume: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SeriesPoint {
+    /// Start of the bucket, Unix timestamp in seconds.
+    pub start: i64,
+    /// Value at the end of the bucket.
+    pub value: f64,
+}
+
+pub async fn record_trade<C: ConnectionTrait>(
+    db: &C,
+    trade: NewTrade,
+) -> Result<trade::Model, Error> {
+    let model = trade::ActiveModel {
+        market_id: Set(trade.market_id),
+        outcome_id: Set(trade.outcome_id),
+        trader: Set(trade.trader),
+        side: Set(trade.side),
+        amount: Set(trade.amount),
+        cost: Set(trade.cost),
+        prices: Set(serde_json::to_value(&trade.prices)?),
+        created_at: Set(trade.created_at),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+    Ok(model)
+}
+
+/// Trades of the market in `[from, to)`, oldest first.
+pub async fn trades<C: ConnectionTrait>(
+    db: &C,
+    market_id: i32,
+    from: i64,
+    to: i64,
+) -> Result<Vec<trade::Model>, Error> {
+    let trades = trade::Entity::find()
+        .filter(trade::Column::MarketId.eq(market_id))
+        .filter(trade::Column::CreatedAt.gte(from))
+        .filter(trade::Column::CreatedAt.lt(to))
+        .order_by_asc(trade::Column::CreatedAt)
+        .order_by_asc(trade::Column::Id)
+        .all(db)
+        .await?;
+    Ok(trades)
+}
+
+fn bucket_start(time: i64, interval: i64) -> i64 {
+    time - time.rem_euclid(interval)
+}
+
+/// Fails unless `[from, to)` holds at most `MAX_BUCKETS` buckets of a valid
+/// interval.
+fn check_range(interval: i64, from: i64, to: i64) -> Result<(), Error> {
+    if interval <= 0 {
+        return Err(Error::InvalidInterval);
+    }
+    if to > from {
+        let buckets = (to - 1 - bucket_start(from, interval)) / interval + 1;
+        if buckets > MAX_BUCKETS {
+            return Err(Error::TooManyBuckets);
+        }
+    }
+    Ok(())
+}
+
+/// Index of the outcome in the price vector, if it belongs to the market.
+async fn outcome_index<C: ConnectionTrait>(
+    db: &C,
+    market_id: i32,
+    outcome_id: i32,
+) -> Result<usize, Error> {
+    match outcome::Entity::find_by_id(outcome_id)
+        .find_also_related(decision::Entity)
+        .one(db)
+        .await?
+    {
+        Some((outcome, Some(decision))) if decision.market_id == market_id => {
+            Ok(outcome.index as usize)
+        }
+        _ => Err(Error::UnknownOutcome),
+    }
+}
+
+/// OHLC candles of the price of the outcome in `[from, to)`.
+///
+/// The price moves with trades on any outcome of the market, but only trades
+/// on this outcome count towards the volume.
+pub async fn candles<C: ConnectionTrait>(
+    db: &C,
+    market_id: i32,
+    outcome_id: i32,
+    interval: i64,
+    from: i64,
+    to: i64,
+) -> Result<Vec<Candle>, Error> {
+    check_range(interval, from, to)?;
+    let index = outcome_index(db, market_id, outcome_id).await?;
+    let mut candles: Vec<Candle> = vec![];
+    for trade in trades(db, market_id, from, to).await? {
+        let prices: Vec<f64> = serde_json::from_value(trade.prices)?;
+        let price = *prices.get(index).ok_or(Error::MissingPrice)?;
+        let volume = if trade.outcome_id == outcome_id {
+            trade.amount
+        } else {
+            0.
+        };
+        let start = bucket_start(trade.created_at, interval);
+        match candles.last_mut() {
+            Some(candle) if candle.start == start => {
+                candle.high = candle.high.max(price);
+                candle.low = candle.low.min(price);
+                candle.close = price;
+                candle.volume += volume;
+            }
+            _ => candles.push(Candle {
+                start,
+                open: price,
+                high: price,
+                low: price,
+                close: price,
+                volume,
+            }),
+        }
+    }
+    Ok(candles)
+}
+
+/// Securities of the outcome held by traders, at the end of each bucket in
+/// `[from, to)`. Trades before `from` are included in the first value, and
+/// buckets without trades carry the previous value.
+pub async fn open_interest<C: ConnectionTrait>(
+    db: &C,
+    market_id: i32,
+    outcome_id: i32,
+    interval: i64,
+    from: i64,
+    to: i64,
+) -> Result<Vec<SeriesPoint>, Error> {
+    check_range(interval, from, to)?;
+    outcome_index(db, market_id, outcome_id).await?;
+    let trades = trade::Entity::find()
+        .filter(trade::Column::MarketId.eq(market_id))
+        .filter(trade::Column::OutcomeId.eq(outcome_id))
+        .filter(trade::Column::CreatedAt.lt(to))
+        .order_by_asc(trade::Column::CreatedAt)
+        .order_by_asc(trade::Column::Id)
+        .all(db)
+        .await?;
+    let mut trades = trades.into_iter().peekable();
+    let mut open_interest = 0.;
+    let mut points = vec![];
+    let mut start = bucket_start(from, interval);
+    while start < to {
+        let end = start.saturating_add(interval);
+        while let Some(trade) = trades.next_if(|t| t.created_at < end) {
+            open_interest += match trade.side {
+                Side::Buy => trade.amount,
+                Side::Sell => -trade.amount,
+            };
+        }
+        points.push(SeriesPoint {
+            start,
+            value: open_interest,
+        });
+        start = end;
+    }
+    Ok(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use migration::memory_db;
+    use sea_orm::{ActiveModelTrait, DatabaseConnection, Set};
+
+    use super::{candles, open_interest, record_trade, Error, NewTrade, MAX_BUCKETS};
+    use crate::entity::{
+        decision,
+        market::{self, Status},
+        outcome,
+        trade::Side,
+    };
+
+    async fn setup() -> (DatabaseConnection, i32, i32, i32) {
+        let db = memory_db().await.unwrap();
+        let market = market::ActiveModel {
+            name: Set("m".to_owned()),
+            status: Set(Status::Open),
+            version: Set(0),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+        let decision = decision::ActiveModel {
+            market_id: Set(market.id),
+            name: Set("d".to_owned()),
+            oracle_public_key: Set(vec![]),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+        let mut ids = vec![];
+        for (index, name) in ["yes", "no"].iter().enumerate() {
+            let outcome = outcome::ActiveModel {
+                decision_id: Set(decision.id),
+                index: Set(index as i32),
+                label: Set(name.to_string()),
+                oracle_value: Set(Some(name.to_string())),
+                ..Default::default()
+            }
+            .insert(&db)
+            .await
+            .unwrap();
+            ids.push(outcome.id);
+        }
+        (db, market.id, ids[0], ids[1])
+    }
+
+    fn trade(
+        market_id: i32,
+        outcome_id: i32,
+        side: Side,
+        amount: f64,
+        yes: f64,
+        at: i64,
+    ) -> NewTrade {
+        NewTrade {
+            market_id,
+            outcome_id,
+            trader: "alice".to_owned(),
+            side,
+            amount,
+            cost: amount * yes,
+            prices: vec![yes, 1. - yes],
+            created_at: at,
+        }
+    }
+
+    #[tokio::test]
+    async fn trades_must_be_aggregated_into_candles() {
+        let (db, market_id, yes, no) = setup().await;
+        for t in [
+            trade(market_id, yes, Side::Buy, 10., 0.6, 5),
+            trade(market_id, yes, Side::Buy, 5., 0.7, 30),
+            trade(market_id, no, Side::Buy, 20., 0.4, 59),
+            trade(market_id, yes, Side::Sell, 3., 0.5, 61),
+        ] {
+            record_trade(&db, t).await.unwrap();
+        }
+
+        let c = candles(&db, market_id, yes, 60, 0, 120).await.unwrap();
+        assert_eq!(c.len(), 2);
+        assert_eq!(
+            (c[0].start, c[0].open, c[0].high, c[0].low),
+            (0, 0.6, 0.7, 0.4)
+        );
+        assert_eq!((c[0].close, c[0].volume), (0.4, 15.));
+        assert_eq!((c[1].start, c[1].open, c[1].volume), (60, 0.5, 3.));
+
+        let oi = open_interest(&db, market_id, yes, 60, 60, 120)
+            .await
+            .unwrap();
+        assert_eq!(oi.len(), 1);
+        assert_eq!(oi[0].value, 12.);
+        // Buckets without trades are reported too.
+        let oi = open_interest(&db, market_id, yes, 60, -60, 240)
+            .await
+            .unwrap();
+        let values = oi.iter().map(|p| (p.start, p.value)).collect::<Vec<_>>();
+        assert_eq!(
+            values,
+            vec![(-60, 0.), (0, 15.), (60, 12.), (120, 12.), (180, 12.)]
+        );
+
+        assert!(matches!(
+            candles(&db, market_id + 1, yes, 60, 0, 120).await,
+            Err(Error::UnknownOutcome)
+        ));
+        let too_long = 60 * MAX_BUCKETS + 1;
+        assert!(matches!(
+            open_interest(&db, market_id, yes, 60, 0, too_long).await,
+            Err(Error::TooManyBuckets)
+        ));
+        assert!(matches!(
+            candles(&db, market_id, yes, 60, 0, too_long).await,
+            Err(Error::TooManyBuckets)
+        ));
+        let oi = open_interest(&db, market_id, yes, 60, 0, too_long - 1)
+            .await
+            .unwrap();
+        assert_eq!(oi.len() as i64, MAX_BUCKETS);
+    }
+}
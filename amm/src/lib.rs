@@ -19,22 +19,84 @@
 //! It has been mostly researched in the context of DeFi (Decentralized
 //! Finance).
 //!
+pub mod amount;
+pub mod arbitrage;
 pub mod cfmm;
 pub mod cost_function;
+pub mod matching;
+pub mod orderbook;
+pub mod outcomes;
+pub mod registry;
+pub mod repository;
 pub mod utils;
 
 pub mod dto;
 pub mod entity;
+pub mod history;
+pub mod lifecycle;
+
+use std::fmt;
 
 use amplify::{From, Wrapper};
-use noisy_float::types::R64;
+use bitcoin::hashes::hex::ToHex;
+
+pub use amount::{Amount, AmountError};
 
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, From, Wrapper)]
+#[derive(
+    Clone,
+    Debug,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    From,
+    Wrapper,
+    serde::Deserialize,
+    serde::Serialize,
+)]
 pub struct AssetId([u8; 32]);
 
-#[derive(Clone, Debug, PartialEq, PartialOrd)]
+impl fmt::Display for AssetId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0.to_hex())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, PartialOrd, serde::Deserialize, serde::Serialize)]
 pub struct AssetInfo {
     id: AssetId,
-    amount: R64,
+    amount: Amount,
     ticker: String,
 }
+
+impl AssetInfo {
+    pub fn new(id: AssetId, units: u64, ticker: String) -> Self {
+        let amount = Amount::new(id.clone(), units);
+        Self { id, amount, ticker }
+    }
+
+    pub fn id(&self) -> &AssetId {
+        &self.id
+    }
+
+    pub fn amount(&self) -> &Amount {
+        &self.amount
+    }
+
+    /// Amount as `f64`, for pricing.
+    pub fn reserve(&self) -> f64 {
+        self.amount.to_f64()
+    }
+
+    /// Set the amount from the result of pricing, rounded to the nearest
+    /// unit.
+    pub fn set_reserve(&mut self, value: f64) -> Result<(), AmountError> {
+        self.amount = Amount::from_f64(self.id.clone(), value)?;
+        Ok(())
+    }
+
+    pub fn ticker(&self) -> &str {
+        &self.ticker
+    }
+}
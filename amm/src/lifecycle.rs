@@ -0,0 +1,186 @@
+//! Lifecycle of a market.
+//!
+//! ```text
+//! Draft ──> Open <──> Halted
+//!             │         │
+//!             └─> Closed <┘ ──> Resolving ──> Resolved
+//! ```
+//!
+//! Every status except `Resolved` can also go to `Cancelled`. `Resolved` and
+//! `Cancelled` are final.
+//!
+//! Each transition is recorded in `entity::market_transition` together with
+//! its time, in the same database transaction as the change of status.
+
+use amplify::{Display, Error, From};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter,
+    QueryOrder, Set, TransactionTrait,
+};
+
+use crate::entity::{
+    market::{self, Status},
+    market_transition,
+};
+
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum Error {
+    /// Market does not exist
+    NotFound,
+    /// Transition is not allowed from the current status
+    IllegalTransition,
+    /// Status of the market was changed concurrently
+    StaleState,
+    /// Database error: {0}
+    #[from]
+    Db(sea_orm::DbErr),
+}
+
+impl Status {
+    pub fn can_transition_to(self, to: Status) -> bool {
+        use Status::*;
+        matches!(
+            (self, to),
+            (Draft, Open)
+                | (Open, Halted)
+                | (Halted, Open)
+                | (Open, Closed)
+                | (Halted, Closed)
+                | (Closed, Resolving)
+                | (Resolving, Resolved)
+                | (Draft | Open | Halted | Closed | Resolving, Cancelled)
+        )
+    }
+
+    pub fn is_final(self) -> bool {
+        matches!(self, Status::Resolved | Status::Cancelled)
+    }
+}
+
+/// Move the market to `to`, and record the transition at `now` (Unix
+/// timestamp in seconds).
+pub async fn transition<C: TransactionTrait>(
+    db: &C,
+    market_id: i32,
+    to: Status,
+    reason: Option<String>,
+    now: i64,
+) -> Result<market::Model, Error> {
+    let txn = db.begin().await?;
+    let mut model = market::Entity::find_by_id(market_id)
+        .one(&txn)
+        .await?
+        .ok_or(Error::NotFound)?;
+    let from = model.status;
+    if !from.can_transition_to(to) {
+        return Err(Error::IllegalTransition);
+    }
+    // Bumping the version makes any trade which loaded the market maker
+    // before this transition fail to save.
+    let result = market::Entity::update_many()
+        .col_expr(market::Column::Status, Expr::value(to))
+        .col_expr(market::Column::Version, Expr::value(model.version + 1))
+        .filter(market::Column::Id.eq(market_id))
+        .filter(market::Column::Version.eq(model.version))
+        .exec(&txn)
+        .await?;
+    if result.rows_affected == 0 {
+        return Err(Error::StaleState);
+    }
+    market_transition::ActiveModel {
+        market_id: Set(market_id),
+        from_status: Set(from),
+        to_status: Set(to),
+        reason: Set(reason),
+        created_at: Set(now),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await?;
+    txn.commit().await?;
+    model.status = to;
+    model.version += 1;
+    Ok(model)
+}
+
+/// Every transition of the market, oldest first.
+pub async fn transitions<C: ConnectionTrait>(
+    db: &C,
+    market_id: i32,
+) -> Result<Vec<market_transition::Model>, Error> {
+    let transitions = market_transition::Entity::find()
+        .filter(market_transition::Column::MarketId.eq(market_id))
+        .order_by_asc(market_transition::Column::Id)
+        .all(db)
+        .await?;
+    Ok(transitions)
+}
+
+#[cfg(test)]
+mod tests {
+    use migration::memory_db;
+
+    use super::{transition, transitions, Error};
+    use crate::{
+        cost_function::{lmsr::LMScoringRule, CostFunctionMarketMaker},
+        entity::market::Status,
+        repository::{self, MarketMakerState},
+    };
+
+    fn buy(state: &mut MarketMakerState) -> Result<(), repository::Error> {
+        match state {
+            MarketMakerState::Lmsr(mm) => mm.purchase(&[1., 0.]).unwrap(),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn only_open_markets_must_be_traded() {
+        let db = memory_db().await.unwrap();
+        let lmsr = LMScoringRule::try_create(2, 10.).unwrap();
+        let market = repository::create(&db, "m".to_owned(), MarketMakerState::Lmsr(lmsr))
+            .await
+            .unwrap();
+        let id = market.market_id;
+
+        assert!(matches!(
+            repository::trade(&db, id, buy).await,
+            Err(repository::Error::NotOpen)
+        ));
+        assert!(matches!(
+            transition(&db, id, Status::Resolved, None, 1).await,
+            Err(Error::IllegalTransition)
+        ));
+        transition(&db, id, Status::Open, None, 2).await.unwrap();
+        repository::trade(&db, id, buy).await.unwrap();
+
+        // A trade priced before the halt must not be saved after it.
+        let mut stale = repository::load(&db, id).await.unwrap();
+        buy(&mut stale.state).unwrap();
+        transition(&db, id, Status::Halted, Some("incident".to_owned()), 3)
+            .await
+            .unwrap();
+        assert!(matches!(
+            repository::save(&db, &mut stale).await,
+            Err(repository::Error::StaleState)
+        ));
+        assert!(matches!(
+            repository::trade(&db, id, buy).await,
+            Err(repository::Error::NotOpen)
+        ));
+
+        let audit = transitions(&db, id).await.unwrap();
+        assert_eq!(audit.len(), 2);
+        assert_eq!(
+            (
+                audit[1].from_status,
+                audit[1].to_status,
+                audit[1].created_at
+            ),
+            (Status::Open, Status::Halted, 3)
+        );
+        assert_eq!(audit[1].reason.as_deref(), Some("incident"));
+    }
+}
@@ -0,0 +1,635 @@
+//! Peer-to-peer order matching in front of a market maker.
+//!
+//! Every trade against a market maker adds to its inventory risk, even
+//! when two traders want the opposite sides. `HybridMatcher` keeps a book
+//! of limit orders and fills an incoming order in price order: a resting
+//! order is crossed as soon as it is at or better than the marginal price
+//! of the market maker, otherwise the market maker fills until its marginal
+//! price reaches the resting order. So the market maker takes only what the
+//! book can not fill at a better price.
+//!
+//! A remainder which the market maker can not fill within the limit price
+//! rests on the book, market orders (without limit) never rest. The limit
+//! bounds the marginal price of the market maker, so a resting order never
+//! crosses the book or the market maker.
+
+use amplify::{Display, Error, From};
+
+use crate::{
+    cfmm::{
+        fpmm::FixedProductMarketMaker, ConstantFunctionMarketMaker, Error as CFMMError, OrderType,
+    },
+    cost_function::{AMMError, CostFunctionMarketMaker, PurchaseError, MINIMAL_PURCHASE},
+    orderbook::{quote_order, spot_price_after},
+    utils::FinitePositiveFloat,
+};
+
+pub type MatchOrderId = u64;
+
+pub type TraderId = String;
+
+/// Number of bisection steps for the largest amount the market maker fills
+/// before its marginal price passes a bound.
+const BISECTION_STEPS: usize = 100;
+
+#[derive(Clone, Debug, PartialEq, Eq, Display, Error, From)]
+#[display(doc_comments)]
+pub enum Error {
+    /// Order amount must be a positive finite number
+    InvalidOrderAmount,
+    /// Limit price must be a positive finite number
+    InvalidLimitPrice,
+    /// Unknown resting order
+    UnknownOrder,
+    /// Error from the constant function market maker
+    #[from]
+    Pool(CFMMError),
+    /// Error from the cost function market maker
+    #[from]
+    Purchase(PurchaseError),
+    /// Error from the fixed product market maker
+    #[from]
+    FixedProduct(AMMError),
+}
+
+/// A market maker which the matcher falls back to.
+/// Prices are in the counter asset per unit of the traded asset.
+pub trait Venue {
+    fn marginal_price(&self) -> f64;
+    /// Marginal price after trading `amount`.
+    fn marginal_price_after(&self, order_type: OrderType, amount: f64) -> Result<f64, Error>;
+    /// Amount of the counter asset paid (buy) or received (sell).
+    fn quote(&self, order_type: OrderType, amount: f64) -> Result<f64, Error>;
+    /// Amount traded and the amount of the counter asset paid (buy) or
+    /// received (sell).
+    fn execute(&mut self, order_type: OrderType, amount: f64) -> Result<(f64, f64), Error>;
+    /// Largest amount up to `amount` which the venue can trade, e.g. whole
+    /// units of an asset.
+    fn tradable(&self, amount: f64) -> f64 {
+        amount
+    }
+}
+
+impl<V: Venue + ?Sized> Venue for Box<V> {
+    fn marginal_price(&self) -> f64 {
+        (**self).marginal_price()
+    }
+
+    fn marginal_price_after(&self, order_type: OrderType, amount: f64) -> Result<f64, Error> {
+        (**self).marginal_price_after(order_type, amount)
+    }
+
+    fn quote(&self, order_type: OrderType, amount: f64) -> Result<f64, Error> {
+        (**self).quote(order_type, amount)
+    }
+
+    fn execute(&mut self, order_type: OrderType, amount: f64) -> Result<(f64, f64), Error> {
+        (**self).execute(order_type, amount)
+    }
+
+    fn tradable(&self, amount: f64) -> f64 {
+        (**self).tradable(amount)
+    }
+}
+
+/// Trades the quote asset of a pool, priced in its base asset.
+#[derive(Clone, Debug)]
+pub struct PoolVenue<P>(pub P);
+
+fn signed(order_type: OrderType, amount: f64) -> f64 {
+    match order_type {
+        OrderType::Buy => amount,
+        OrderType::Sell => -amount,
+    }
+}
+
+impl<P: ConstantFunctionMarketMaker + Clone> Venue for PoolVenue<P> {
+    fn marginal_price(&self) -> f64 {
+        self.0.spot_price()
+    }
+
+    fn marginal_price_after(&self, order_type: OrderType, amount: f64) -> Result<f64, Error> {
+        spot_price_after(&self.0, signed(order_type, amount))
+            .ok_or(Error::Pool(CFMMError::InsufficientLiquidity))
+    }
+
+    fn quote(&self, order_type: OrderType, amount: f64) -> Result<f64, Error> {
+        let order = quote_order(&self.0, signed(order_type, amount))?;
+        Ok(self.0.price_for_order(&order)?)
+    }
+
+    fn execute(&mut self, order_type: OrderType, amount: f64) -> Result<(f64, f64), Error> {
+        let order = quote_order(&self.0, signed(order_type, amount))?;
+        let cost = self.0.order(&order)?;
+        Ok((order.quantity(), cost))
+    }
+
+    /// Pools trade whole units.
+    fn tradable(&self, amount: f64) -> f64 {
+        amount.floor()
+    }
+}
+
+/// Trades one outcome of a cost function market maker, priced in the
+/// collateral.
+#[derive(Clone, Debug)]
+pub struct OutcomeVenue<M> {
+    pub mm: M,
+    pub outcome: usize,
+}
+
+impl<M: CostFunctionMarketMaker + Clone> OutcomeVenue<M> {
+    pub fn try_create(mm: M, outcome: usize) -> Result<Self, Error> {
+        if outcome >= mm.total_securities().len() {
+            return Err(PurchaseError::UnknownOutcome.into());
+        }
+        Ok(Self { mm, outcome })
+    }
+
+    /// Market maker after the trade, and the collateral paid or received.
+    fn after(&self, order_type: OrderType, amount: f64) -> Result<(M, f64), Error> {
+        if order_type == OrderType::Sell && amount > self.mm.total_securities()[self.outcome] {
+            // Nobody holds more than what has been issued.
+            return Err(PurchaseError::InsufficientLiquidity.into());
+        }
+        let mut after = self.mm.clone();
+        after.total_securities_mut()[self.outcome] += signed(order_type, amount);
+        let cost = (after.cost_function() - self.mm.cost_function()).abs();
+        Ok((after, cost))
+    }
+}
+
+impl<M: CostFunctionMarketMaker + Clone> Venue for OutcomeVenue<M> {
+    fn marginal_price(&self) -> f64 {
+        self.mm.price_for_showing(self.outcome)
+    }
+
+    fn marginal_price_after(&self, order_type: OrderType, amount: f64) -> Result<f64, Error> {
+        let (after, _) = self.after(order_type, amount)?;
+        Ok(after.price_for_showing(self.outcome))
+    }
+
+    fn quote(&self, order_type: OrderType, amount: f64) -> Result<f64, Error> {
+        self.after(order_type, amount).map(|(_, cost)| cost)
+    }
+
+    fn execute(&mut self, order_type: OrderType, amount: f64) -> Result<(f64, f64), Error> {
+        let (after, cost) = self.after(order_type, amount)?;
+        if order_type == OrderType::Buy {
+            let mut purchase = vec![0.; self.mm.total_securities().len()];
+            purchase[self.outcome] = amount;
+            self.mm.purchase(&purchase)?;
+        } else {
+            self.mm = after;
+        }
+        Ok((amount, cost))
+    }
+}
+
+/// Trades one outcome of a fixed product market maker, priced in the
+/// collateral. Quotes include the fee of the market maker.
+#[derive(Clone, Debug)]
+pub struct FixedProductVenue {
+    pub mm: FixedProductMarketMaker,
+    pub outcome: usize,
+}
+
+impl FixedProductVenue {
+    pub fn try_create(mm: FixedProductMarketMaker, outcome: usize) -> Result<Self, Error> {
+        if outcome >= mm.balances().len() {
+            return Err(PurchaseError::UnknownOutcome.into());
+        }
+        Ok(Self { mm, outcome })
+    }
+
+    fn collateral_for(&self, order_type: OrderType, amount: f64) -> Result<f64, Error> {
+        FinitePositiveFloat::try_non_zero(amount).map_err(|_| Error::InvalidOrderAmount)?;
+        Ok(match order_type {
+            OrderType::Buy => self.mm.calc_buy_investment(amount, self.outcome)?,
+            OrderType::Sell => self.mm.calc_sell_return(amount, self.outcome)?,
+        })
+    }
+}
+
+impl Venue for FixedProductVenue {
+    fn marginal_price(&self) -> f64 {
+        self.mm.prices()[self.outcome]
+    }
+
+    fn marginal_price_after(&self, order_type: OrderType, amount: f64) -> Result<f64, Error> {
+        let mut after = self.clone();
+        after.execute(order_type, amount)?;
+        Ok(after.marginal_price())
+    }
+
+    fn quote(&self, order_type: OrderType, amount: f64) -> Result<f64, Error> {
+        self.collateral_for(order_type, amount)
+    }
+
+    fn execute(&mut self, order_type: OrderType, amount: f64) -> Result<(f64, f64), Error> {
+        let collateral = self.collateral_for(order_type, amount)?;
+        let traded = match order_type {
+            OrderType::Buy => self.mm.buy(collateral, self.outcome, 0.)?,
+            OrderType::Sell => self.mm.sell(collateral, self.outcome, amount)?,
+        };
+        Ok((traded, collateral))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct RestingOrder {
+    pub id: MatchOrderId,
+    pub trader: TraderId,
+    pub order_type: OrderType,
+    /// Amount which has not been filled yet.
+    pub amount: f64,
+    pub price: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FillSource {
+    /// Crossed with a resting order of another trader.
+    Peer {
+        maker: MatchOrderId,
+        trader: TraderId,
+    },
+    MarketMaker,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fill {
+    pub source: FillSource,
+    pub amount: f64,
+    /// Average price of the fill.
+    pub price: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Execution {
+    pub id: MatchOrderId,
+    pub fills: Vec<Fill>,
+    /// Amount which has been put on the book.
+    pub resting: f64,
+}
+
+impl Execution {
+    pub fn filled(&self) -> f64 {
+        self.fills.iter().map(|f| f.amount).sum()
+    }
+
+    pub fn filled_by_peers(&self) -> f64 {
+        self.fills
+            .iter()
+            .filter(|f| f.source != FillSource::MarketMaker)
+            .map(|f| f.amount)
+            .sum()
+    }
+}
+
+/// Whether `price` is at or better than `bound` for the side.
+fn is_better_or_equal(order_type: OrderType, price: f64, bound: f64) -> bool {
+    match order_type {
+        OrderType::Buy => price <= bound,
+        OrderType::Sell => price >= bound,
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct HybridMatcher<V> {
+    venue: V,
+    /// Best price first, then the oldest first.
+    bids: Vec<RestingOrder>,
+    /// Best price first, then the oldest first.
+    asks: Vec<RestingOrder>,
+    next_id: MatchOrderId,
+}
+
+impl<V: Venue> HybridMatcher<V> {
+    pub fn new(venue: V) -> Self {
+        Self {
+            venue,
+            bids: vec![],
+            asks: vec![],
+            next_id: 0,
+        }
+    }
+
+    pub fn venue(&self) -> &V {
+        &self.venue
+    }
+
+    pub fn bids(&self) -> &[RestingOrder] {
+        &self.bids
+    }
+
+    pub fn asks(&self) -> &[RestingOrder] {
+        &self.asks
+    }
+
+    /// Amount up to `amount` which the venue fills after `filled`, before
+    /// its marginal price passes `bound`. Only what the venue can trade.
+    fn venue_amount_within(
+        &self,
+        order_type: OrderType,
+        filled: f64,
+        amount: f64,
+        bound: f64,
+    ) -> f64 {
+        let within = |a: f64| {
+            let price = if a > 0. {
+                self.venue.marginal_price_after(order_type, a)
+            } else {
+                Ok(self.venue.marginal_price())
+            };
+            matches!(price, Ok(p) if is_better_or_equal(order_type, p, bound))
+        };
+        if !within(filled) {
+            return 0.;
+        }
+        let (mut lo, mut hi) = (filled, filled + amount);
+        if within(hi) {
+            lo = hi;
+        } else {
+            for _ in 0..BISECTION_STEPS {
+                let mid = 0.5 * (lo + hi);
+                if within(mid) {
+                    lo = mid;
+                } else {
+                    hi = mid;
+                }
+            }
+        }
+        let amount = self.venue.tradable(lo) - filled;
+        // Too small for the quote to be meaningful.
+        if amount < MINIMAL_PURCHASE {
+            return 0.;
+        }
+        amount
+    }
+
+    fn rest(&mut self, order: RestingOrder) {
+        let book = match order.order_type {
+            OrderType::Buy => &mut self.bids,
+            OrderType::Sell => &mut self.asks,
+        };
+        let position = book
+            .iter()
+            .position(|o| {
+                o.price != order.price && is_better_or_equal(order.order_type, order.price, o.price)
+            })
+            .unwrap_or(book.len());
+        book.insert(position, order);
+    }
+
+    /// Match the order with resting orders, then with the market maker.
+    /// The book is left untouched when the market maker fails.
+    pub fn submit(
+        &mut self,
+        trader: TraderId,
+        order_type: OrderType,
+        amount: f64,
+        limit: Option<f64>,
+    ) -> Result<Execution, Error> {
+        FinitePositiveFloat::try_non_zero(amount).map_err(|_| Error::InvalidOrderAmount)?;
+        if let Some(l) = limit {
+            FinitePositiveFloat::try_non_zero(l).map_err(|_| Error::InvalidLimitPrice)?;
+        }
+        let id = self.next_id;
+        self.next_id += 1;
+        let mut remaining = amount;
+        let mut fills = vec![];
+
+        let book = match order_type {
+            OrderType::Buy => &self.asks,
+            OrderType::Sell => &self.bids,
+        };
+        // Amount for the venue, which is executed at once after the walk,
+        // so a failure of the venue leaves everything untouched.
+        let mut to_venue = 0.;
+        // Resting orders crossed, from the front of the book.
+        let mut crossed = vec![];
+        while remaining > 0. {
+            let maker = book.get(crossed.len()).filter(
+                |maker| !matches!(limit, Some(l) if !is_better_or_equal(order_type, maker.price, l)),
+            );
+            // The venue fills up to the next resting order, or up to the
+            // limit once the book is exhausted.
+            let bound = match (maker, limit) {
+                (Some(maker), _) => maker.price,
+                (None, Some(l)) => l,
+                (None, None) => {
+                    to_venue = self.venue.tradable(to_venue + remaining);
+                    break;
+                }
+            };
+            let step = self.venue_amount_within(order_type, to_venue, remaining, bound);
+            to_venue += step;
+            remaining -= step;
+            let maker = match maker {
+                Some(maker) if remaining > 0. => maker,
+                _ => break,
+            };
+            let fill = remaining.min(maker.amount);
+            fills.push(Fill {
+                source: FillSource::Peer {
+                    maker: maker.id,
+                    trader: maker.trader.clone(),
+                },
+                amount: fill,
+                price: maker.price,
+            });
+            crossed.push(fill);
+            remaining -= fill;
+        }
+
+        // What the venue can not trade rests, or stays unfilled.
+        remaining = amount - fills.iter().map(|f| f.amount).sum::<f64>();
+        if to_venue > 0. {
+            let (executed, cost) = self.venue.execute(order_type, to_venue)?;
+            fills.push(Fill {
+                source: FillSource::MarketMaker,
+                amount: executed,
+                price: cost / executed,
+            });
+            remaining -= executed;
+        }
+
+        let book = match order_type {
+            OrderType::Buy => &mut self.asks,
+            OrderType::Sell => &mut self.bids,
+        };
+        for (maker, fill) in book.iter_mut().zip(&crossed) {
+            maker.amount -= fill;
+        }
+        let emptied = book.iter().take_while(|o| o.amount <= 0.).count();
+        book.drain(..emptied);
+
+        let mut resting = 0.;
+        if let (Some(price), true) = (limit, remaining > 0.) {
+            resting = remaining;
+            self.rest(RestingOrder {
+                id,
+                trader,
+                order_type,
+                amount: remaining,
+                price,
+            });
+        }
+        Ok(Execution { id, fills, resting })
+    }
+
+    /// Remove the resting order, returns its unfilled amount.
+    pub fn cancel(&mut self, id: MatchOrderId) -> Result<f64, Error> {
+        for book in [&mut self.bids, &mut self.asks] {
+            if let Some(position) = book.iter().position(|o| o.id == id) {
+                return Ok(book.remove(position).amount);
+            }
+        }
+        Err(Error::UnknownOrder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amplify::Wrapper;
+
+    use super::{
+        FillSource, FixedProductVenue, HybridMatcher, OutcomeVenue, PoolVenue, RestingOrder, Venue,
+    };
+    use crate::{
+        cfmm::{
+            cpmm::ConstantProductMarketMaker, fpmm::FixedProductMarketMaker,
+            ConstantFunctionMarketMaker, OrderType,
+        },
+        cost_function::lmsr::LMScoringRule,
+        AssetId, AssetInfo,
+    };
+
+    fn pool_matcher() -> HybridMatcher<PoolVenue<ConstantProductMarketMaker>> {
+        HybridMatcher::new(PoolVenue(ConstantProductMarketMaker::new(
+            AssetInfo::new(AssetId::from_inner([0; 32]), 4000, "A".to_owned()),
+            AssetInfo::new(AssetId::from_inner([1; 32]), 1000, "B".to_owned()),
+        )))
+    }
+
+    fn assert_not_crossed<V: Venue>(matcher: &HybridMatcher<V>) {
+        if let (Some(bid), Some(ask)) = (matcher.bids().first(), matcher.asks().first()) {
+            assert!(bid.price < ask.price, "{} >= {}", bid.price, ask.price);
+        }
+    }
+
+    #[test]
+    fn pool_and_peers_must_be_filled_in_price_order() {
+        let mut matcher = pool_matcher();
+        // The pool pays less than 4.02 when selling, so the order rests.
+        let ask = matcher
+            .submit("alice".to_owned(), OrderType::Sell, 10., Some(4.02))
+            .unwrap();
+        assert_eq!(ask.resting, 10.);
+        assert!(ask.fills.is_empty());
+        // The pool fills 2 units before its price passes 4.02, then the ask,
+        // then the pool again up to 4.05. The rest is a bid below the pool.
+        let buy = matcher
+            .submit("bob".to_owned(), OrderType::Buy, 20., Some(4.05))
+            .unwrap();
+        assert_eq!(buy.filled_by_peers(), 10.);
+        assert_eq!(buy.fills[0].price, 4.02);
+        assert_eq!(buy.fills[1].source, FillSource::MarketMaker);
+        assert_eq!(buy.fills[1].amount, 6.);
+        assert_eq!(buy.resting, 4.);
+        assert!(matcher.asks().is_empty());
+        assert!(matcher.venue().marginal_price() <= 4.05);
+        assert_eq!(matcher.venue().0.quote_asset().amount().units(), 994);
+        // The bid is above the pool, so it is crossed first.
+        let sell = matcher
+            .submit("carol".to_owned(), OrderType::Sell, 5., None)
+            .unwrap();
+        assert_eq!(sell.filled_by_peers(), 4.);
+        assert_eq!(sell.fills[1].amount, 1.);
+        assert!(matcher.bids().is_empty());
+    }
+
+    #[test]
+    fn book_must_never_be_crossed() {
+        let mut matcher = pool_matcher();
+        let orders = [
+            (OrderType::Sell, 10., Some(4.02)),
+            (OrderType::Buy, 7., Some(3.98)),
+            (OrderType::Buy, 20., Some(4.05)),
+            (OrderType::Sell, 3., Some(4.01)),
+            (OrderType::Sell, 12., Some(4.06)),
+            (OrderType::Buy, 5., None),
+            (OrderType::Buy, 30., Some(4.1)),
+            (OrderType::Sell, 40., Some(3.9)),
+            (OrderType::Sell, 2.5, None),
+        ];
+        for (i, (order_type, amount, limit)) in orders.iter().enumerate() {
+            matcher
+                .submit(i.to_string(), *order_type, *amount, *limit)
+                .unwrap();
+            assert_not_crossed(&matcher);
+        }
+    }
+
+    #[test]
+    fn pool_must_fill_whole_units_before_the_book_is_touched() {
+        let mut matcher = pool_matcher();
+        matcher.rest(RestingOrder {
+            id: 100,
+            trader: "alice".to_owned(),
+            order_type: OrderType::Sell,
+            amount: 2.5,
+            price: 3.9,
+        });
+        // The pool can not fill the remainder, so the ask is not crossed.
+        assert!(matcher
+            .submit("bob".to_owned(), OrderType::Buy, 2000., None)
+            .is_err());
+        assert_eq!(matcher.asks()[0].amount, 2.5);
+        // Less than a unit is left for the pool, which stays unfilled.
+        let buy = matcher
+            .submit("bob".to_owned(), OrderType::Buy, 2.9, None)
+            .unwrap();
+        assert_eq!(buy.filled(), 2.5);
+        assert_eq!(buy.resting, 0.);
+        assert!(matcher.asks().is_empty());
+        assert_eq!(matcher.venue().0.quote_asset().amount().units(), 1000);
+        // The pool fills one unit, the rest of the limit order rests.
+        let buy = matcher
+            .submit("bob".to_owned(), OrderType::Buy, 1.25, Some(10.))
+            .unwrap();
+        assert_eq!(buy.fills[0].amount, 1.);
+        assert_eq!(buy.resting, 0.25);
+        assert_eq!(matcher.venue().0.quote_asset().amount().units(), 999);
+    }
+
+    #[test]
+    fn remainder_must_rest_when_the_market_maker_is_too_expensive() {
+        let lmsr = LMScoringRule::try_create(2, 10.).unwrap();
+        let mut matcher = HybridMatcher::new(OutcomeVenue::try_create(lmsr, 0).unwrap());
+        let execution = matcher
+            .submit("alice".to_owned(), OrderType::Buy, 100., Some(0.6))
+            .unwrap();
+        let filled = execution.filled();
+        assert!(filled > 0. && filled < 100.);
+        assert!(execution.fills[0].price <= 0.6 + 1e-9);
+        assert_eq!(execution.resting, 100. - filled);
+        assert_eq!(matcher.cancel(execution.id), Ok(100. - filled));
+        assert!(matcher.bids().is_empty());
+    }
+
+    #[test]
+    fn fixed_product_venue_must_trade_the_exact_amount_of_tokens() {
+        let mut fpmm = FixedProductMarketMaker::try_create(3, 0.02).unwrap();
+        fpmm.add_liquidity("lp".to_owned(), 100.).unwrap();
+        let mut venue = FixedProductVenue::try_create(fpmm, 1).unwrap();
+        let cost = venue.quote(OrderType::Buy, 10.).unwrap();
+        assert_eq!(venue.execute(OrderType::Buy, 10.).unwrap().1, cost);
+        assert!((venue.mm.balances()[1] - (100. + 0.98 * cost - 10.)).abs() < 1e-9);
+        let (sold, received) = venue.execute(OrderType::Sell, 10.).unwrap();
+        assert!((sold - 10.).abs() < 1e-9);
+        assert!(received < cost);
+        // Selling more tokens never returns more than the pool holds.
+        assert!(venue.quote(OrderType::Sell, 1000.).unwrap() < 100.);
+    }
+}
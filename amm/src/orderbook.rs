@@ -0,0 +1,332 @@
+//! Synthetic order book view of AMM curves.
+//!
+//! An AMM has no resting orders, but the liquidity along its curve can be
+//! shown as if it was an order book. Level `k` on the ask side is at the
+//! price `mid * (1 + k * step)` and its cumulative size is the amount one
+//! has to buy to move the marginal price there. The bid side is the same
+//! for selling, at `mid * (1 - k * step)`.
+//!
+//! For a `CostFunctionMarketMaker` the book is for one outcome, priced in
+//! the collateral. For a `ConstantFunctionMarketMaker` the book is for the
+//! quote asset, priced in the base asset (see `spot_price`).
+
+use amplify::{Display, Error};
+
+use crate::{
+    cfmm::{AssetIndex, ConstantFunctionMarketMaker, Error as CFMMError, OrderInfo, OrderType},
+    cost_function::CostFunctionMarketMaker,
+};
+
+/// The bracket of the size is expanded up to `2^MAX_EXPANSIONS` times the
+/// initial guess.
+const MAX_EXPANSIONS: usize = 128;
+
+/// Number of bisection steps for finding a size.
+const BISECTION_STEPS: usize = 100;
+
+#[derive(Clone, Debug, PartialEq, Eq, Display, Error)]
+#[display(doc_comments)]
+pub enum Error {
+    /// Order book must have at least one level, and the step must be in `(0, 1)`
+    InvalidLevels,
+    /// Outcome index is out of range
+    UnknownOutcome,
+    /// Current price of the market maker is not a positive finite number
+    NoPrice,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Side {
+    Bid,
+    Ask,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Level {
+    pub price: f64,
+    /// Amount available between the previous level and this one.
+    pub size: f64,
+    /// Amount to trade to move the price from the mid to this level.
+    pub cumulative_size: f64,
+    /// Amount of the counter asset paid (asks) or received (bids) for
+    /// `cumulative_size`.
+    pub cumulative_cost: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct OrderBook {
+    pub mid_price: f64,
+    /// Best bid first. Levels which the curve can not reach are omitted.
+    pub bids: Vec<Level>,
+    /// Best ask first. Levels which the curve can not reach are omitted.
+    pub asks: Vec<Level>,
+}
+
+/// Smallest size which moves the price by `target` (as a fraction of the
+/// mid price, in the direction of the trade).
+/// `moved` returns how far a trade of the given size moves the price, or
+/// `None` if the curve can not fill it. It must be increasing in the size.
+fn solve_size<F: Fn(f64) -> Option<f64>>(moved: F, target: f64, max_size: f64) -> Option<f64> {
+    let reached = |size: f64| !matches!(moved(size), Some(m) if m < target);
+    let mut lo = 0.;
+    let mut hi = 1f64.min(max_size);
+    for _ in 0..MAX_EXPANSIONS {
+        if reached(hi) || hi >= max_size {
+            break;
+        }
+        lo = hi;
+        hi = (hi * 2.).min(max_size);
+    }
+    if !reached(hi) {
+        return None;
+    }
+    for _ in 0..BISECTION_STEPS {
+        let mid = 0.5 * (lo + hi);
+        if reached(mid) {
+            hi = mid;
+        } else {
+            lo = mid;
+        }
+    }
+    // The curve stopped filling before the price got there.
+    moved(hi).map(|_| hi)
+}
+
+fn check_levels(levels: usize, step: f64) -> Result<(), Error> {
+    if levels == 0 || !(step > 0. && step < 1.) {
+        return Err(Error::InvalidLevels);
+    }
+    Ok(())
+}
+
+fn check_price(price: f64) -> Result<f64, Error> {
+    if price.is_finite() && price > 0. {
+        Ok(price)
+    } else {
+        Err(Error::NoPrice)
+    }
+}
+
+/// Build one side of the book. `size_for` returns the size and the cost to
+/// move the price by the given fraction.
+fn build_side<F: Fn(f64) -> Option<(f64, f64)>>(
+    mid_price: f64,
+    side: Side,
+    levels: usize,
+    step: f64,
+    size_for: F,
+) -> Vec<Level> {
+    let mut result: Vec<Level> = Vec::with_capacity(levels);
+    for k in 1..=levels {
+        let change = step * k as f64;
+        if side == Side::Bid && change >= 1. {
+            break;
+        }
+        let (cumulative_size, cumulative_cost) = match size_for(change) {
+            Some(s) => s,
+            None => break,
+        };
+        let previous = result.last().map_or(0., |l| l.cumulative_size);
+        let price = match side {
+            Side::Ask => mid_price * (1. + change),
+            Side::Bid => mid_price * (1. - change),
+        };
+        result.push(Level {
+            price,
+            size: cumulative_size - previous,
+            cumulative_size,
+            cumulative_cost,
+        });
+    }
+    result
+}
+
+/// Marginal price of the outcome after trading `amount` of it, negative
+/// amount being a sale.
+fn outcome_price_after<M: CostFunctionMarketMaker + Clone>(
+    mm: &M,
+    outcome: usize,
+    amount: f64,
+) -> Option<(f64, f64)> {
+    let mut after = mm.clone();
+    after.total_securities_mut()[outcome] += amount;
+    let price = after.price_for_showing(outcome);
+    let cost = after.cost_function() - mm.cost_function();
+    if price.is_finite() && cost.is_finite() {
+        Some((price, cost))
+    } else {
+        None
+    }
+}
+
+/// Amount of the outcome to trade to move its price by `change` (e.g.
+/// `0.01` for +1%, `-0.01` for -1%), with the collateral paid or received.
+pub fn size_to_move_outcome<M: CostFunctionMarketMaker + Clone>(
+    mm: &M,
+    outcome: usize,
+    change: f64,
+) -> Result<Option<(f64, f64)>, Error> {
+    if outcome >= mm.total_securities().len() {
+        return Err(Error::UnknownOutcome);
+    }
+    let mid_price = check_price(mm.price_for_showing(outcome))?;
+    let sign = change.signum();
+    let size = solve_size(
+        |size| {
+            outcome_price_after(mm, outcome, sign * size)
+                .map(|(price, _)| sign * (price / mid_price - 1.))
+        },
+        change.abs(),
+        f64::INFINITY,
+    );
+    Ok(size.and_then(|size| {
+        outcome_price_after(mm, outcome, sign * size).map(|(_, cost)| (size, cost.abs()))
+    }))
+}
+
+/// Order book of an outcome of a cost function market maker.
+pub fn outcome_order_book<M: CostFunctionMarketMaker + Clone>(
+    mm: &M,
+    outcome: usize,
+    levels: usize,
+    step: f64,
+) -> Result<OrderBook, Error> {
+    check_levels(levels, step)?;
+    if outcome >= mm.total_securities().len() {
+        return Err(Error::UnknownOutcome);
+    }
+    let mid_price = check_price(mm.price_for_showing(outcome))?;
+    let size_for = |change: f64| size_to_move_outcome(mm, outcome, change).ok().flatten();
+    Ok(OrderBook {
+        mid_price,
+        bids: build_side(mid_price, Side::Bid, levels, step, |c| size_for(-c)),
+        asks: build_side(mid_price, Side::Ask, levels, step, size_for),
+    })
+}
+
+/// Order of the quote asset, negative amount being a sale.
+pub(crate) fn quote_order<P: ConstantFunctionMarketMaker>(
+    pool: &P,
+    amount: f64,
+) -> Result<OrderInfo, CFMMError> {
+    let order_type = if amount > 0. {
+        OrderType::Buy
+    } else {
+        OrderType::Sell
+    };
+    OrderInfo::try_from_f64(pool, AssetIndex::One, amount.abs(), order_type)
+}
+
+/// Spot price of the pool after trading `amount` of the quote asset,
+/// negative amount being a sale.
+pub(crate) fn spot_price_after<P: ConstantFunctionMarketMaker + Clone>(
+    pool: &P,
+    amount: f64,
+) -> Option<f64> {
+    let mut after = pool.clone();
+    after.order(&quote_order(pool, amount).ok()?).ok()?;
+    let price = after.spot_price();
+    if price.is_finite() {
+        Some(price)
+    } else {
+        None
+    }
+}
+
+/// Amount of the quote asset to trade to move the spot price by `change`
+/// (e.g. `0.01` for +1%, `-0.01` for -1%), with the base asset paid or
+/// received.
+pub fn size_to_move_pool<P: ConstantFunctionMarketMaker + Clone>(
+    pool: &P,
+    change: f64,
+) -> Result<Option<(f64, f64)>, Error> {
+    let mid_price = check_price(pool.spot_price())?;
+    let sign = change.signum();
+    // The pool can not sell more than its reserve.
+    let max_size = if sign > 0. {
+        pool.quote_asset().reserve()
+    } else {
+        f64::INFINITY
+    };
+    let size = solve_size(
+        |size| spot_price_after(pool, sign * size).map(|price| sign * (price / mid_price - 1.)),
+        change.abs(),
+        max_size,
+    );
+    Ok(size.and_then(|size| {
+        quote_order(pool, sign * size)
+            .and_then(|order| pool.price_for_order(&order))
+            .ok()
+            .map(|cost| (size, cost))
+    }))
+}
+
+/// Order book of the quote asset of a constant function market maker.
+pub fn pool_order_book<P: ConstantFunctionMarketMaker + Clone>(
+    pool: &P,
+    levels: usize,
+    step: f64,
+) -> Result<OrderBook, Error> {
+    check_levels(levels, step)?;
+    let mid_price = check_price(pool.spot_price())?;
+    let size_for = |change: f64| size_to_move_pool(pool, change).ok().flatten();
+    Ok(OrderBook {
+        mid_price,
+        bids: build_side(mid_price, Side::Bid, levels, step, |c| size_for(-c)),
+        asks: build_side(mid_price, Side::Ask, levels, step, size_for),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use amplify::Wrapper;
+
+    use super::{outcome_order_book, pool_order_book, size_to_move_pool, Error};
+    use crate::{cfmm::cpmm::ConstantProductMarketMaker, cost_function::lmsr::LMScoringRule};
+    use crate::{AssetId, AssetInfo};
+
+    const UNIT: u64 = 100_000_000;
+
+    #[test]
+    fn pool_book_must_follow_the_curve() {
+        let pool = ConstantProductMarketMaker::new(
+            AssetInfo::new(AssetId::from_inner([0; 32]), 4000 * UNIT, "A".to_owned()),
+            AssetInfo::new(AssetId::from_inner([1; 32]), 1000 * UNIT, "B".to_owned()),
+        );
+        let y = (1000 * UNIT) as f64;
+        let book = pool_order_book(&pool, 5, 0.01).unwrap();
+        assert_eq!(book.mid_price, 4.);
+        assert_eq!(book.asks.len(), 5);
+        assert_eq!(book.bids.len(), 5);
+        // For x * y = k, the price is x / y = k / y^2.
+        for (k, level) in book.asks.iter().enumerate() {
+            let expected = y - y / (1. + 0.01 * (k + 1) as f64).sqrt();
+            // Reserves are whole units, so the size is exact up to one unit.
+            assert!((level.cumulative_size - expected).abs() < 1.);
+        }
+        for (k, level) in book.bids.iter().enumerate() {
+            let expected = y / (1. - 0.01 * (k + 1) as f64).sqrt() - y;
+            assert!((level.cumulative_size - expected).abs() < 1.);
+            assert!(level.size > 0.);
+        }
+        let (size, _) = size_to_move_pool(&pool, 0.01).unwrap().unwrap();
+        assert!((size - book.asks[0].cumulative_size).abs() < 1e-9);
+    }
+
+    #[test]
+    fn outcome_book_must_stop_at_unreachable_price() {
+        let lmsr = LMScoringRule::try_create(2, 10.).unwrap();
+        let book = outcome_order_book(&lmsr, 0, 4, 0.3).unwrap();
+        assert_eq!(book.mid_price, 0.5);
+        // The price of an outcome can not go over 1, nor below 0.
+        assert_eq!(book.asks.len(), 3);
+        assert_eq!(book.bids.len(), 3);
+        let expected = 10. * (0.65f64 / 0.35).ln();
+        assert!((book.asks[0].cumulative_size - expected).abs() < 1e-6);
+        assert!((book.bids[0].cumulative_size - expected).abs() < 1e-6);
+        assert_eq!(
+            outcome_order_book(&lmsr, 2, 4, 0.5),
+            Err(Error::UnknownOutcome)
+        );
+    }
+}
@@ -0,0 +1,582 @@
+//! Named outcomes of a decision, and how they map to what the oracle signs.
+//!
+//! The market maker only knows outcomes by their index. Each outcome also has
+//! a label for display, and the value the oracle attests when it happens:
+//! either an exact string for enumerated events, or an inclusive range of
+//! values for numeric (digit decomposition) events.
+//!
+//! A decision can only be created when its outcomes cover every value the
+//! oracle announced exactly once, so that any attestation resolves to exactly
+//! one outcome. A decision is only resolved by an attestation carrying the
+//! signatures of its oracle.
+
+use std::convert::{TryFrom, TryInto};
+
+use amplify::{Display, Error, From};
+use bitcoin::{
+    hashes::sha256,
+    secp256k1::{schnorr::Signature, Message, Secp256k1, XOnlyPublicKey},
+};
+use sea_orm::{
+    ActiveModelTrait, ColumnTrait, EntityTrait, QueryFilter, QueryOrder, Set, TransactionTrait,
+};
+
+use crate::entity::{
+    decision,
+    market::{self, Status},
+    outcome, resolution,
+};
+
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum Error {
+    /// A decision requires at least two outcomes
+    OutcomeLessThanTwo,
+    /// Outcome value does not match the kind of the oracle event
+    WrongValueKind,
+    /// Two outcomes map to the same oracle value
+    OverlappingOutcomes,
+    /// Some values announced by the oracle are not mapped to any outcome
+    UncoveredOracleValue,
+    /// Outcome maps to a value the oracle never signs
+    UnknownOracleValue,
+    /// Range start must not be greater than its end
+    InvalidRange,
+    /// Attested digits are not valid for the event
+    InvalidDigits,
+    /// Attested value does not match any outcome
+    NoMatchingOutcome,
+    /// Decision does not exist
+    UnknownDecision,
+    /// Stored outcome must have either an oracle value or a range
+    InvalidStoredOutcome,
+    /// Attestation is malformed
+    InvalidAttestation,
+    /// Attestation is not signed by the oracle of the decision
+    InvalidSignature,
+    /// Market of the decision is not being resolved
+    NotResolving,
+    /// Database error: {0}
+    #[from]
+    Db(sea_orm::DbErr),
+}
+
+/// Set of values the oracle announced it will sign.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AnnouncedOutcomes {
+    Enumerated(Vec<String>),
+    /// Every integer in `[min, max]`.
+    Numeric {
+        min: i64,
+        max: i64,
+    },
+}
+
+impl AnnouncedOutcomes {
+    /// Values of a digit decomposition event with `nb_digits` digits in
+    /// `base`, prefixed by a `+` or `-` sign when `is_signed`.
+    pub fn digit_decomposition(base: u32, nb_digits: u32, is_signed: bool) -> Result<Self, Error> {
+        if base < 2 {
+            return Err(Error::InvalidDigits);
+        }
+        let max = (base as i64)
+            .checked_pow(nb_digits)
+            .ok_or(Error::InvalidDigits)?
+            - 1;
+        let min = if is_signed { -max } else { 0 };
+        Ok(AnnouncedOutcomes::Numeric { min, max })
+    }
+}
+
+/// Value the oracle signs for an outcome.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OracleValue {
+    Exact(String),
+    /// Every integer in `[start, end]`.
+    Range {
+        start: i64,
+        end: i64,
+    },
+}
+
+/// Value signed by the oracle.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AttestedValue {
+    Enumerated(String),
+    Numeric(i64),
+}
+
+impl AttestedValue {
+    /// Decode the digits signed for a digit decomposition event, most
+    /// significant first, after the sign when `is_signed`.
+    pub fn from_digits(base: u32, is_signed: bool, outcomes: &[String]) -> Result<Self, Error> {
+        let (negative, digits) = match (is_signed, outcomes.split_first()) {
+            (true, Some((sign, digits))) if sign == "+" => (false, digits),
+            (true, Some((sign, digits))) if sign == "-" => (true, digits),
+            (true, _) => return Err(Error::InvalidDigits),
+            (false, _) => (false, outcomes),
+        };
+        let mut value: i64 = 0;
+        for digit in digits {
+            let digit = digit.parse::<u32>().map_err(|_| Error::InvalidDigits)?;
+            if digit >= base {
+                return Err(Error::InvalidDigits);
+            }
+            value = value
+                .checked_mul(base as i64)
+                .and_then(|v| v.checked_add(digit as i64))
+                .ok_or(Error::InvalidDigits)?;
+        }
+        Ok(AttestedValue::Numeric(if negative {
+            -value
+        } else {
+            value
+        }))
+    }
+}
+
+/// Attestation of an event by its oracle: the outcome strings it signed, one
+/// for enumerated events and the sign and digits for digit decomposition
+/// events, with the BIP340 Schnorr signature of the SHA256 of each of them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Attestation {
+    pub oracle_public_key: XOnlyPublicKey,
+    pub signatures: Vec<Signature>,
+    pub outcomes: Vec<String>,
+}
+
+/// Reads the big endian integers and length prefixed fields of a serialized
+/// attestation.
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.0.len() < len {
+            return Err(Error::InvalidAttestation);
+        }
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn u16(&mut self) -> Result<usize, Error> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]) as usize)
+    }
+}
+
+impl Attestation {
+    /// Parse the oracle public key, then the signatures and the outcomes,
+    /// each list prefixed by its length as a big endian `u16`, and each
+    /// outcome by its length in bytes.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut reader = Reader(bytes);
+        let oracle_public_key =
+            XOnlyPublicKey::from_slice(reader.take(32)?).map_err(|_| Error::InvalidAttestation)?;
+        let signatures = (0..reader.u16()?)
+            .map(|_| Signature::from_slice(reader.take(64)?).map_err(|_| Error::InvalidAttestation))
+            .collect::<Result<Vec<_>, _>>()?;
+        let outcomes = (0..reader.u16()?)
+            .map(|_| {
+                let len = reader.u16()?;
+                String::from_utf8(reader.take(len)?.to_vec()).map_err(|_| Error::InvalidAttestation)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if !reader.0.is_empty() {
+            return Err(Error::InvalidAttestation);
+        }
+        Ok(Attestation {
+            oracle_public_key,
+            signatures,
+            outcomes,
+        })
+    }
+
+    /// Inverse of `from_bytes`.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let len = |len: usize| -> Result<[u8; 2], Error> {
+            let len: u16 = len.try_into().map_err(|_| Error::InvalidAttestation)?;
+            Ok(len.to_be_bytes())
+        };
+        let mut bytes = self.oracle_public_key.serialize().to_vec();
+        bytes.extend(len(self.signatures.len())?);
+        for signature in &self.signatures {
+            bytes.extend(signature.as_ref());
+        }
+        bytes.extend(len(self.outcomes.len())?);
+        for outcome in &self.outcomes {
+            bytes.extend(len(outcome.len())?);
+            bytes.extend(outcome.as_bytes());
+        }
+        Ok(bytes)
+    }
+
+    /// Check that the oracle with the x-only `oracle_public_key` signed
+    /// every outcome.
+    pub fn verify(&self, oracle_public_key: &[u8]) -> Result<(), Error> {
+        if self.oracle_public_key.serialize()[..] != *oracle_public_key
+            || self.outcomes.is_empty()
+            || self.signatures.len() != self.outcomes.len()
+        {
+            return Err(Error::InvalidSignature);
+        }
+        let secp = Secp256k1::verification_only();
+        for (signature, outcome) in self.signatures.iter().zip(&self.outcomes) {
+            let message = Message::from_hashed_data::<sha256::Hash>(outcome.as_bytes());
+            secp.verify_schnorr(signature, &message, &self.oracle_public_key)
+                .map_err(|_| Error::InvalidSignature)?;
+        }
+        Ok(())
+    }
+
+    /// Value attested for an event with the given outcomes. The base of a
+    /// digit decomposition event follows from the number of digits, as its
+    /// outcomes cover `[min, base^digits - 1]`.
+    pub fn value(&self, outcomes: &[OutcomeSpec]) -> Result<AttestedValue, Error> {
+        let mut max = None;
+        for outcome in outcomes {
+            match outcome.value {
+                OracleValue::Exact(_) => {
+                    return match self.outcomes.as_slice() {
+                        [value] => Ok(AttestedValue::Enumerated(value.clone())),
+                        _ => Err(Error::InvalidAttestation),
+                    }
+                }
+                OracleValue::Range { end, .. } => max = max.max(Some(end)),
+            }
+        }
+        let values = max
+            .and_then(|max| max.checked_add(1))
+            .ok_or(Error::InvalidDigits)?;
+        let is_signed = matches!(self.outcomes.first().map(String::as_str), Some("+" | "-"));
+        let nb_digits = self.outcomes.len() - is_signed as usize;
+        if nb_digits == 0 {
+            return Err(Error::InvalidDigits);
+        }
+        let base = (values as f64).powf(1. / nb_digits as f64).round() as u32;
+        if (base as i64).checked_pow(nb_digits as u32) != Some(values) {
+            return Err(Error::InvalidDigits);
+        }
+        AttestedValue::from_digits(base, is_signed, &self.outcomes)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutcomeSpec {
+    pub label: String,
+    pub value: OracleValue,
+}
+
+impl TryFrom<&outcome::Model> for OutcomeSpec {
+    type Error = Error;
+
+    fn try_from(model: &outcome::Model) -> Result<Self, Self::Error> {
+        let value = match (&model.oracle_value, model.range_start, model.range_end) {
+            (Some(value), None, None) => OracleValue::Exact(value.clone()),
+            (None, Some(start), Some(end)) => OracleValue::Range { start, end },
+            _ => return Err(Error::InvalidStoredOutcome),
+        };
+        Ok(OutcomeSpec {
+            label: model.label.clone(),
+            value,
+        })
+    }
+}
+
+/// Check that every value announced by the oracle maps to exactly one
+/// outcome.
+pub fn validate_outcomes(
+    outcomes: &[OutcomeSpec],
+    announced: &AnnouncedOutcomes,
+) -> Result<(), Error> {
+    if outcomes.len() < 2 {
+        return Err(Error::OutcomeLessThanTwo);
+    }
+    match announced {
+        AnnouncedOutcomes::Enumerated(values) => {
+            let mut mapped = Vec::with_capacity(outcomes.len());
+            for outcome in outcomes {
+                match &outcome.value {
+                    OracleValue::Exact(value) if !values.contains(value) => {
+                        return Err(Error::UnknownOracleValue)
+                    }
+                    OracleValue::Exact(value) if mapped.contains(&value) => {
+                        return Err(Error::OverlappingOutcomes)
+                    }
+                    OracleValue::Exact(value) => mapped.push(value),
+                    OracleValue::Range { .. } => return Err(Error::WrongValueKind),
+                }
+            }
+            if values.iter().any(|v| !mapped.contains(&v)) {
+                return Err(Error::UncoveredOracleValue);
+            }
+        }
+        AnnouncedOutcomes::Numeric { min, max } => {
+            let mut ranges = Vec::with_capacity(outcomes.len());
+            for outcome in outcomes {
+                match outcome.value {
+                    OracleValue::Range { start, end } if start > end => {
+                        return Err(Error::InvalidRange)
+                    }
+                    OracleValue::Range { start, end } if start < *min || end > *max => {
+                        return Err(Error::UnknownOracleValue)
+                    }
+                    OracleValue::Range { start, end } => ranges.push((start, end)),
+                    OracleValue::Exact(_) => return Err(Error::WrongValueKind),
+                }
+            }
+            ranges.sort_unstable();
+            // Sorted ranges must start at `min`, end at `max` and touch each
+            // other without overlapping.
+            let mut next = *min;
+            for (start, end) in ranges {
+                if start < next {
+                    return Err(Error::OverlappingOutcomes);
+                }
+                if start > next {
+                    return Err(Error::UncoveredOracleValue);
+                }
+                next = end.saturating_add(1);
+            }
+            if next <= *max {
+                return Err(Error::UncoveredOracleValue);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Index of the outcome which the attested value resolves to.
+pub fn winning_index(outcomes: &[OutcomeSpec], attested: &AttestedValue) -> Result<usize, Error> {
+    outcomes
+        .iter()
+        .position(|outcome| match (&outcome.value, attested) {
+            (OracleValue::Exact(value), AttestedValue::Enumerated(attested)) => value == attested,
+            (OracleValue::Range { start, end }, AttestedValue::Numeric(attested)) => {
+                start <= attested && attested <= end
+            }
+            _ => false,
+        })
+        .ok_or(Error::NoMatchingOutcome)
+}
+
+/// Create a decision of the market with its outcomes, in the given order.
+pub async fn create_decision<C: TransactionTrait>(
+    db: &C,
+    market_id: i32,
+    name: String,
+    oracle_public_key: Vec<u8>,
+    announced: &AnnouncedOutcomes,
+    outcomes: &[OutcomeSpec],
+) -> Result<(decision::Model, Vec<outcome::Model>), Error> {
+    validate_outcomes(outcomes, announced)?;
+    let txn = db.begin().await?;
+    let decision = decision::ActiveModel {
+        market_id: Set(market_id),
+        name: Set(name),
+        oracle_public_key: Set(oracle_public_key),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await?;
+    let mut models = Vec::with_capacity(outcomes.len());
+    for (index, spec) in outcomes.iter().enumerate() {
+        let (oracle_value, range_start, range_end) = match &spec.value {
+            OracleValue::Exact(value) => (Some(value.clone()), None, None),
+            OracleValue::Range { start, end } => (None, Some(*start), Some(*end)),
+        };
+        let model = outcome::ActiveModel {
+            decision_id: Set(decision.id),
+            index: Set(index as i32),
+            label: Set(spec.label.clone()),
+            oracle_value: Set(oracle_value),
+            range_start: Set(range_start),
+            range_end: Set(range_end),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+        models.push(model);
+    }
+    txn.commit().await?;
+    Ok((decision, models))
+}
+
+/// Record the outcome attested by the oracle for the decision, at `now`
+/// (Unix timestamp in seconds). The attestation must be signed by the oracle
+/// of the decision, and its market must be `Status::Resolving`.
+pub async fn resolve<C: TransactionTrait>(
+    db: &C,
+    decision_id: i32,
+    attestation: &Attestation,
+    now: i64,
+) -> Result<resolution::Model, Error> {
+    let txn = db.begin().await?;
+    let (decision, market) = decision::Entity::find_by_id(decision_id)
+        .find_also_related(market::Entity)
+        .one(&txn)
+        .await?
+        .ok_or(Error::UnknownDecision)?;
+    if market.map(|m| m.status) != Some(Status::Resolving) {
+        return Err(Error::NotResolving);
+    }
+    attestation.verify(&decision.oracle_public_key)?;
+    let models = outcome::Entity::find()
+        .filter(outcome::Column::DecisionId.eq(decision_id))
+        .order_by_asc(outcome::Column::Index)
+        .all(&txn)
+        .await?;
+    let specs = models
+        .iter()
+        .map(OutcomeSpec::try_from)
+        .collect::<Result<Vec<_>, _>>()?;
+    let winner = &models[winning_index(&specs, &attestation.value(&specs)?)?];
+    let resolution = resolution::ActiveModel {
+        decision_id: Set(decision_id),
+        outcome_id: Set(winner.id),
+        attestation: Set(attestation.to_bytes()?),
+        resolved_at: Set(now),
+        ..Default::default()
+    }
+    .insert(&txn)
+    .await?;
+    txn.commit().await?;
+    Ok(resolution)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{
+        hashes::sha256,
+        secp256k1::{KeyPair, Message, Secp256k1, XOnlyPublicKey},
+    };
+    use migration::memory_db;
+    use sea_orm::{ActiveModelTrait, IntoActiveModel, Set};
+
+    use super::{
+        create_decision, resolve, validate_outcomes, winning_index, AnnouncedOutcomes, Attestation,
+        AttestedValue, Error, OracleValue, OutcomeSpec,
+    };
+    use crate::entity::market::{self, Status};
+
+    /// Attestation of the outcomes by the oracle with the secret key `[key; 32]`.
+    fn attest(key: u8, outcomes: &[&str]) -> Attestation {
+        let secp = Secp256k1::new();
+        let keypair = KeyPair::from_seckey_slice(&secp, &[key; 32]).unwrap();
+        Attestation {
+            oracle_public_key: XOnlyPublicKey::from_keypair(&keypair).0,
+            signatures: outcomes
+                .iter()
+                .map(|o| {
+                    let message = Message::from_hashed_data::<sha256::Hash>(o.as_bytes());
+                    secp.sign_schnorr_no_aux_rand(&message, &keypair)
+                })
+                .collect(),
+            outcomes: outcomes.iter().map(|o| o.to_string()).collect(),
+        }
+    }
+
+    fn range(label: &str, start: i64, end: i64) -> OutcomeSpec {
+        OutcomeSpec {
+            label: label.to_owned(),
+            value: OracleValue::Range { start, end },
+        }
+    }
+
+    #[test]
+    fn numeric_outcomes_must_cover_the_announced_range() {
+        // 2 binary digits, so the oracle signs a value in [0, 3].
+        let announced = AnnouncedOutcomes::digit_decomposition(2, 2, false).unwrap();
+        let outcomes = vec![range("low", 0, 1), range("high", 2, 3)];
+        validate_outcomes(&outcomes, &announced).unwrap();
+        assert!(matches!(
+            validate_outcomes(&[range("low", 0, 1), range("high", 3, 3)], &announced),
+            Err(Error::UncoveredOracleValue)
+        ));
+        assert!(matches!(
+            validate_outcomes(&[range("low", 0, 2), range("high", 2, 3)], &announced),
+            Err(Error::OverlappingOutcomes)
+        ));
+
+        let digits = ["1", "0"].iter().map(|d| d.to_string()).collect::<Vec<_>>();
+        let attested = AttestedValue::from_digits(2, false, &digits).unwrap();
+        assert_eq!(attested, AttestedValue::Numeric(2));
+        assert_eq!(winning_index(&outcomes, &attested).unwrap(), 1);
+        assert_eq!(attest(1, &["1", "0"]).value(&outcomes).unwrap(), attested);
+        assert!(matches!(
+            attest(1, &["1", "0", "1"]).value(&outcomes),
+            Err(Error::InvalidDigits)
+        ));
+    }
+
+    #[tokio::test]
+    async fn enumerated_outcomes_must_resolve_from_attestation() {
+        let db = memory_db().await.unwrap();
+        let market = market::ActiveModel {
+            name: Set("m".to_owned()),
+            status: Set(Status::Open),
+            version: Set(0),
+            ..Default::default()
+        }
+        .insert(&db)
+        .await
+        .unwrap();
+        let announced = AnnouncedOutcomes::Enumerated(vec!["YES".to_owned(), "NO".to_owned()]);
+        let exact = |label: &str, value: &str| OutcomeSpec {
+            label: label.to_owned(),
+            value: OracleValue::Exact(value.to_owned()),
+        };
+
+        assert!(matches!(
+            create_decision(
+                &db,
+                market.id,
+                "d".to_owned(),
+                vec![],
+                &announced,
+                &[exact("Yes", "YES"), exact("Maybe", "MAYBE")]
+            )
+            .await,
+            Err(Error::UnknownOracleValue)
+        ));
+        let (decision, outcomes) = create_decision(
+            &db,
+            market.id,
+            "d".to_owned(),
+            attest(1, &[]).oracle_public_key.serialize().to_vec(),
+            &announced,
+            &[exact("No", "NO"), exact("Yes", "YES")],
+        )
+        .await
+        .unwrap();
+        let yes = attest(1, &["YES"]);
+        let bytes = yes.to_bytes().unwrap();
+        assert_eq!(Attestation::from_bytes(&bytes).unwrap(), yes);
+        assert!(matches!(
+            Attestation::from_bytes(&bytes[1..]),
+            Err(Error::InvalidAttestation)
+        ));
+        assert!(matches!(
+            resolve(&db, decision.id, &yes, 10).await,
+            Err(Error::NotResolving)
+        ));
+        let mut resolving = market.into_active_model();
+        resolving.status = Set(Status::Resolving);
+        resolving.update(&db).await.unwrap();
+        // Signed by another oracle, or with a signature of another outcome.
+        assert!(matches!(
+            resolve(&db, decision.id, &attest(2, &["YES"]), 10).await,
+            Err(Error::InvalidSignature)
+        ));
+        let mut forged = attest(1, &["NO"]);
+        forged.outcomes = yes.outcomes.clone();
+        assert!(matches!(
+            resolve(&db, decision.id, &forged, 10).await,
+            Err(Error::InvalidSignature)
+        ));
+        let resolution = resolve(&db, decision.id, &yes, 10).await.unwrap();
+        assert_eq!(resolution.outcome_id, outcomes[1].id);
+        assert_eq!(resolution.attestation, bytes);
+        assert_eq!(outcomes[1].label, "Yes");
+    }
+}
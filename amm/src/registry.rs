@@ -0,0 +1,168 @@
+//! Registry of the assets known to the market makers.
+//!
+//! `AssetId` is an opaque 32-byte identifier and `AssetInfo` only carries a
+//! ticker. The registry keeps what is needed to show an asset to users:
+//! decimals, display name and issuer.
+//!
+//! The id of an outcome token is derived from the market and the outcome
+//! index, so that anyone can recompute which token represents which outcome:
+//!
+//! ```text
+//! SHA256("amm/outcome" || market_id (u64, big endian) || outcome_index (u32, big endian))
+//! ```
+
+use std::collections::BTreeMap;
+
+use amplify::{Display, Error, Wrapper};
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+
+use crate::{dto::MarketId, AssetId, AssetInfo};
+
+/// Domain separation tag for outcome token ids.
+pub const OUTCOME_ASSET_TAG: &[u8] = b"amm/outcome";
+
+/// Amounts are stored in `u64` of the smallest unit, and `10^19` overflows.
+pub const MAX_DECIMALS: u8 = 18;
+
+#[derive(Clone, Debug, PartialEq, Eq, Display, Error)]
+#[display(doc_comments)]
+pub enum Error {
+    /// Asset is already registered
+    DuplicateAsset,
+    /// Asset is not registered
+    UnknownAsset,
+    /// Decimals must not be more than 18
+    InvalidDecimals,
+}
+
+impl AssetId {
+    /// Id of the token for the outcome of the market.
+    pub fn for_outcome(market_id: &MarketId, outcome_index: u32) -> Self {
+        let mut engine = sha256::Hash::engine();
+        engine.input(OUTCOME_ASSET_TAG);
+        engine.input(&market_id.as_inner().to_be_bytes());
+        engine.input(&outcome_index.to_be_bytes());
+        AssetId::from_inner(sha256::Hash::from_engine(engine).into_inner())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssetMetadata {
+    pub id: AssetId,
+    pub ticker: String,
+    pub name: String,
+    /// Number of decimal digits of the smallest unit.
+    pub decimals: u8,
+    pub issuer: String,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct AssetRegistry {
+    assets: BTreeMap<AssetId, AssetMetadata>,
+}
+
+impl AssetRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, metadata: AssetMetadata) -> Result<(), Error> {
+        if metadata.decimals > MAX_DECIMALS {
+            return Err(Error::InvalidDecimals);
+        }
+        if self.assets.contains_key(&metadata.id) {
+            return Err(Error::DuplicateAsset);
+        }
+        self.assets.insert(metadata.id.clone(), metadata);
+        Ok(())
+    }
+
+    /// Register the tokens for every outcome of the market, the name of the
+    /// outcome is used for both the ticker and the name.
+    pub fn register_outcomes(
+        &mut self,
+        market_id: &MarketId,
+        outcomes: &[String],
+        decimals: u8,
+        issuer: &str,
+    ) -> Result<Vec<AssetId>, Error> {
+        if decimals > MAX_DECIMALS {
+            return Err(Error::InvalidDecimals);
+        }
+        let ids = (0..outcomes.len() as u32)
+            .map(|i| AssetId::for_outcome(market_id, i))
+            .collect::<Vec<_>>();
+        if ids.iter().any(|id| self.assets.contains_key(id)) {
+            return Err(Error::DuplicateAsset);
+        }
+        for (id, outcome) in ids.iter().zip(outcomes) {
+            self.assets.insert(
+                id.clone(),
+                AssetMetadata {
+                    id: id.clone(),
+                    ticker: outcome.clone(),
+                    name: outcome.clone(),
+                    decimals,
+                    issuer: issuer.to_owned(),
+                },
+            );
+        }
+        Ok(ids)
+    }
+
+    pub fn get(&self, id: &AssetId) -> Result<&AssetMetadata, Error> {
+        self.assets.get(id).ok_or(Error::UnknownAsset)
+    }
+
+    pub fn remove(&mut self, id: &AssetId) -> Result<AssetMetadata, Error> {
+        self.assets.remove(id).ok_or(Error::UnknownAsset)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &AssetMetadata> {
+        self.assets.values()
+    }
+
+    /// `AssetInfo` for a market maker, with the ticker of the asset.
+    pub fn asset_info(&self, id: &AssetId, units: u64) -> Result<AssetInfo, Error> {
+        let metadata = self.get(id)?;
+        Ok(AssetInfo::new(id.clone(), units, metadata.ticker.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use amplify::Wrapper;
+
+    use super::{AssetRegistry, Error};
+    use crate::{dto::MarketId, AssetId};
+
+    #[test]
+    fn outcome_asset_id_must_be_deterministic() {
+        let id = AssetId::for_outcome(&MarketId::from_inner(7), 1);
+        assert_eq!(
+            id.to_string(),
+            "00cd1df4f61987b26284189e8305121930b265bcd130b1ed3deb478afabbf2df"
+        );
+        assert_ne!(id, AssetId::for_outcome(&MarketId::from_inner(7), 0));
+        assert_ne!(id, AssetId::for_outcome(&MarketId::from_inner(1), 7));
+    }
+
+    #[test]
+    fn outcomes_must_be_registered_once() {
+        let mut registry = AssetRegistry::new();
+        let market = MarketId::from_inner(1);
+        let outcomes = vec!["yes".to_owned(), "no".to_owned()];
+        let ids = registry
+            .register_outcomes(&market, &outcomes, 8, "oracle")
+            .unwrap();
+        assert_eq!(registry.get(&ids[1]).unwrap().ticker, "no");
+        assert_eq!(
+            registry.register_outcomes(&market, &outcomes, 8, "oracle"),
+            Err(Error::DuplicateAsset)
+        );
+        assert_eq!(
+            registry.register_outcomes(&MarketId::from_inner(2), &outcomes, 19, "oracle"),
+            Err(Error::InvalidDecimals)
+        );
+    }
+}
@@ -0,0 +1,318 @@
+//! Persistence of market maker state.
+//!
+//! The state of a market maker is stored as JSON in the `market_maker`
+//! column of `entity::market`, so that it survives a restart of the server.
+//!
+//! Every save bumps the `version` column, and is only applied if the version
+//! is still the one which was loaded. When two trades on the same market
+//! race, the second one fails with `Error::StaleState` instead of silently
+//! overwriting the first one, and `update` retries it on the fresh state.
+//! Changes of the status of the market bump the version too, so a trade
+//! which loaded the state before the market was halted is never saved.
+
+use amplify::{Display, Error, From};
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter, Set,
+};
+
+use crate::{
+    cfmm::{cpmm::ConstantProductMarketMaker, fpmm::FixedProductMarketMaker},
+    cost_function::{lmsr, ls_lmsr::LSLMScoringRule, lsmr_logsumexp, CostFunctionMarketMaker},
+    entity::market::{self, Status},
+};
+
+/// Number of times `update` reloads the state after a concurrent update.
+pub const MAX_RETRIES: usize = 8;
+
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum Error {
+    /// Market does not exist
+    NotFound,
+    /// Market has no market maker
+    NoMarketMaker,
+    /// Market was updated concurrently, reload it and try again
+    StaleState,
+    /// Market is not open for trading
+    NotOpen,
+    /// Status of the market does not allow the operation
+    WrongStatus,
+    /// Database error: {0}
+    #[from]
+    Db(sea_orm::DbErr),
+    /// Stored market maker state is invalid: {0}
+    #[from]
+    Serialization(serde_json::Error),
+}
+
+/// Every kind of market maker which can be persisted.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MarketMakerState {
+    Lmsr(lmsr::LMScoringRule),
+    LogSumExpLmsr(lsmr_logsumexp::LMScoringRule),
+    LsLmsr(LSLMScoringRule),
+    Fpmm(FixedProductMarketMaker),
+    Cpmm(ConstantProductMarketMaker),
+}
+
+impl MarketMakerState {
+    /// Market maker priced by a cost function, which can quote any purchase
+    /// vector.
+    pub fn as_cost_function(&self) -> Option<&dyn CostFunctionMarketMaker> {
+        match self {
+            MarketMakerState::Lmsr(mm) => Some(mm),
+            MarketMakerState::LogSumExpLmsr(mm) => Some(mm),
+            MarketMakerState::LsLmsr(mm) => Some(mm),
+            MarketMakerState::Fpmm(_) | MarketMakerState::Cpmm(_) => None,
+        }
+    }
+
+    pub fn as_cost_function_mut(&mut self) -> Option<&mut dyn CostFunctionMarketMaker> {
+        match self {
+            MarketMakerState::Lmsr(mm) => Some(mm),
+            MarketMakerState::LogSumExpLmsr(mm) => Some(mm),
+            MarketMakerState::LsLmsr(mm) => Some(mm),
+            MarketMakerState::Fpmm(_) | MarketMakerState::Cpmm(_) => None,
+        }
+    }
+
+    /// Current price of every outcome, by outcome index. A `Cpmm` has a single
+    /// price, of its quote asset in its base asset (base reserve over quote
+    /// reserve).
+    pub fn prices(&self) -> Vec<f64> {
+        match self {
+            MarketMakerState::Lmsr(mm) => showing_prices(mm),
+            MarketMakerState::LogSumExpLmsr(mm) => showing_prices(mm),
+            MarketMakerState::LsLmsr(mm) => showing_prices(mm),
+            MarketMakerState::Fpmm(mm) => mm.prices(),
+            MarketMakerState::Cpmm(mm) => vec![mm.price()],
+        }
+    }
+}
+
+fn showing_prices<M: CostFunctionMarketMaker>(mm: &M) -> Vec<f64> {
+    (0..mm.total_securities().len())
+        .map(|i| mm.price_for_showing(i))
+        .collect()
+}
+
+/// Market maker loaded from the database, with the version it was loaded at.
+#[derive(Clone, Debug)]
+pub struct StoredMarketMaker {
+    pub market_id: i32,
+    pub version: i64,
+    pub status: Status,
+    pub state: MarketMakerState,
+}
+
+/// Create a market with its market maker. The market starts as
+/// `Status::Draft`, and must be opened with `crate::lifecycle::transition`.
+pub async fn create<C: ConnectionTrait>(
+    db: &C,
+    name: String,
+    state: MarketMakerState,
+) -> Result<StoredMarketMaker, Error> {
+    let model = market::ActiveModel {
+        name: Set(name),
+        status: Set(Status::Draft),
+        market_maker: Set(Some(serde_json::to_value(&state)?)),
+        version: Set(0),
+        ..Default::default()
+    }
+    .insert(db)
+    .await?;
+    Ok(StoredMarketMaker {
+        market_id: model.id,
+        version: model.version,
+        status: model.status,
+        state,
+    })
+}
+
+pub async fn load<C: ConnectionTrait>(db: &C, market_id: i32) -> Result<StoredMarketMaker, Error> {
+    let model = market::Entity::find_by_id(market_id)
+        .one(db)
+        .await?
+        .ok_or(Error::NotFound)?;
+    let state = model.market_maker.ok_or(Error::NoMarketMaker)?;
+    Ok(StoredMarketMaker {
+        market_id,
+        version: model.version,
+        status: model.status,
+        state: serde_json::from_value(state)?,
+    })
+}
+
+/// Save the state if nobody else saved it since it was loaded, and bump the
+/// version.
+pub async fn save<C: ConnectionTrait>(db: &C, stored: &mut StoredMarketMaker) -> Result<(), Error> {
+    let result = market::Entity::update_many()
+        .col_expr(
+            market::Column::MarketMaker,
+            Expr::value(serde_json::to_value(&stored.state)?),
+        )
+        .col_expr(market::Column::Version, Expr::value(stored.version + 1))
+        .filter(market::Column::Id.eq(stored.market_id))
+        .filter(market::Column::Version.eq(stored.version))
+        .exec(db)
+        .await?;
+    if result.rows_affected == 0 {
+        let exists = market::Entity::find_by_id(stored.market_id)
+            .one(db)
+            .await?
+            .is_some();
+        return Err(if exists {
+            Error::StaleState
+        } else {
+            Error::NotFound
+        });
+    }
+    stored.version += 1;
+    Ok(())
+}
+
+/// Load the market maker, apply `f` and save the result. If the market was
+/// updated in between, `f` is applied again on the fresh state.
+///
+/// When `f` fails nothing is saved. This does not check the status of the
+/// market, use `trade` for trades and `update_in` for other changes which
+/// depend on it.
+pub async fn update<C, F, T, E>(db: &C, market_id: i32, f: F) -> Result<T, E>
+where
+    C: ConnectionTrait,
+    F: FnMut(&mut MarketMakerState) -> Result<T, E>,
+    E: From<Error>,
+{
+    apply(db, market_id, |_| Ok(()), f).await
+}
+
+/// Same as `update`, but fails with `Error::WrongStatus` unless the status of
+/// the market is one of `allowed`.
+pub async fn update_in<C, F, T, E>(db: &C, market_id: i32, allowed: &[Status], f: F) -> Result<T, E>
+where
+    C: ConnectionTrait,
+    F: FnMut(&mut MarketMakerState) -> Result<T, E>,
+    E: From<Error>,
+{
+    let check = |status| {
+        if allowed.contains(&status) {
+            Ok(())
+        } else {
+            Err(Error::WrongStatus)
+        }
+    };
+    apply(db, market_id, check, f).await
+}
+
+/// Same as `update`, but fails with `Error::NotOpen` unless the market is
+/// `Status::Open`.
+pub async fn trade<C, F, T, E>(db: &C, market_id: i32, f: F) -> Result<T, E>
+where
+    C: ConnectionTrait,
+    F: FnMut(&mut MarketMakerState) -> Result<T, E>,
+    E: From<Error>,
+{
+    let check = |status| {
+        if status == Status::Open {
+            Ok(())
+        } else {
+            Err(Error::NotOpen)
+        }
+    };
+    apply(db, market_id, check, f).await
+}
+
+/// Apply `f` to the market maker, unless `check` rejects the status the
+/// state was loaded in.
+async fn apply<C, G, F, T, E>(db: &C, market_id: i32, check: G, mut f: F) -> Result<T, E>
+where
+    C: ConnectionTrait,
+    G: Fn(Status) -> Result<(), Error>,
+    F: FnMut(&mut MarketMakerState) -> Result<T, E>,
+    E: From<Error>,
+{
+    for _ in 0..MAX_RETRIES {
+        let mut stored = load(db, market_id).await?;
+        check(stored.status)?;
+        let value = f(&mut stored.state)?;
+        match save(db, &mut stored).await {
+            Ok(()) => return Ok(value),
+            Err(Error::StaleState) => continue,
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Err(Error::StaleState.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use migration::memory_db;
+
+    use super::{create, load, save, update, update_in, Error, MarketMakerState};
+    use crate::{
+        cost_function::{lmsr::LMScoringRule, CostFunctionMarketMaker},
+        entity::market::Status,
+    };
+
+    fn purchase(state: &mut MarketMakerState, purchase_vector: &[f64]) -> Result<(), Error> {
+        match state {
+            MarketMakerState::Lmsr(mm) => mm.purchase(purchase_vector).unwrap(),
+            _ => unreachable!(),
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stale_state_must_not_be_saved() {
+        let db = memory_db().await.unwrap();
+        let lmsr = LMScoringRule::try_create(2, 10.).unwrap();
+        let created = create(&db, "m".to_owned(), MarketMakerState::Lmsr(lmsr))
+            .await
+            .unwrap();
+
+        let mut first = load(&db, created.market_id).await.unwrap();
+        let mut second = load(&db, created.market_id).await.unwrap();
+        purchase(&mut first.state, &[1., 0.]).unwrap();
+        purchase(&mut second.state, &[0., 1.]).unwrap();
+        save(&db, &mut first).await.unwrap();
+        assert!(matches!(
+            save(&db, &mut second).await,
+            Err(Error::StaleState)
+        ));
+
+        // `update` retries on the fresh state, so both trades are applied.
+        update(&db, created.market_id, |state| purchase(state, &[0., 1.]))
+            .await
+            .unwrap();
+        let stored = load(&db, created.market_id).await.unwrap();
+        assert_eq!(stored.version, 2);
+        match stored.state {
+            MarketMakerState::Lmsr(mm) => assert_eq!(mm.total_securities(), &[1., 1.]),
+            _ => unreachable!(),
+        }
+    }
+
+    #[tokio::test]
+    async fn updates_must_be_gated_by_status() {
+        let db = memory_db().await.unwrap();
+        let lmsr = LMScoringRule::try_create(2, 10.).unwrap();
+        let created = create(&db, "m".to_owned(), MarketMakerState::Lmsr(lmsr))
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            update_in(&db, created.market_id, &[Status::Open], |state| {
+                purchase(state, &[1., 0.])
+            })
+            .await,
+            Err(Error::WrongStatus)
+        ));
+        update_in(&db, created.market_id, &[Status::Draft], |state| {
+            purchase(state, &[1., 0.])
+        })
+        .await
+        .unwrap();
+        assert_eq!(load(&db, created.market_id).await.unwrap().version, 1);
+    }
+}
@@ -1,23 +1,43 @@
 use std::convert::TryFrom;
 
-#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+use amplify::{Display, Error};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Display, Error)]
+#[display(doc_comments)]
+pub enum FloatError {
+    /// Value is nan
+    Nan,
+    /// Value is infinite
+    Infinite,
+    /// Value is negative
+    Negative,
+    /// Value is zero
+    Zero,
+}
+
+/// Float which is neither NaN, infinite nor negative.
+///
+/// Arithmetic re-validates the result (e.g. `0 / 0` or `1 - 2`), so every
+/// operation returns `Result`.
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd, serde::Deserialize, serde::Serialize)]
+#[serde(try_from = "f64", into = "f64")]
 pub struct FinitePositiveFloat(pub(crate) f64);
 
-fn validate(value: f64) -> Result<(), String> {
+fn validate(value: f64) -> Result<(), FloatError> {
     if value.is_nan() {
-        return Err("Value is nan".to_owned());
+        return Err(FloatError::Nan);
     }
     if value.is_infinite() {
-        return Err("Value is infinite".to_owned());
+        return Err(FloatError::Infinite);
     }
     if value.is_sign_negative() {
-        return Err("Value is negative".to_owned());
+        return Err(FloatError::Negative);
     }
     Ok(())
 }
 
 impl TryFrom<f64> for FinitePositiveFloat {
-    type Error = String;
+    type Error = FloatError;
 
     fn try_from(value: f64) -> Result<Self, Self::Error> {
         validate(value)?;
@@ -25,30 +45,89 @@ impl TryFrom<f64> for FinitePositiveFloat {
     }
 }
 
+impl From<FinitePositiveFloat> for f64 {
+    fn from(value: FinitePositiveFloat) -> Self {
+        value.0
+    }
+}
+
 impl std::ops::Mul for FinitePositiveFloat {
-    type Output = Self;
+    type Output = Result<Self, FloatError>;
 
     fn mul(self, rhs: Self) -> Self::Output {
-        FinitePositiveFloat(self.0 * rhs.0)
+        FinitePositiveFloat::try_from(self.0 * rhs.0)
     }
 }
 impl std::ops::Add for FinitePositiveFloat {
-    type Output = Self;
+    type Output = Result<Self, FloatError>;
 
     fn add(self, rhs: Self) -> Self::Output {
-        FinitePositiveFloat(self.0 + rhs.0)
+        FinitePositiveFloat::try_from(self.0 + rhs.0)
+    }
+}
+impl std::ops::Sub for FinitePositiveFloat {
+    type Output = Result<Self, FloatError>;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        // `x - x` is `+0`, so only a real negative result is rejected.
+        FinitePositiveFloat::try_from(self.0 - rhs.0)
     }
 }
 impl std::ops::Div for FinitePositiveFloat {
-    type Output = Self;
+    type Output = Result<Self, FloatError>;
 
     fn div(self, rhs: Self) -> Self::Output {
-        FinitePositiveFloat(self.0 / rhs.0)
+        FinitePositiveFloat::try_from(self.0 / rhs.0)
     }
 }
 
 impl FinitePositiveFloat {
+    /// Same as `try_from`, but zero is rejected too.
+    pub fn try_non_zero(value: f64) -> Result<Self, FloatError> {
+        let value = Self::try_from(value)?;
+        if value.is_zero() {
+            return Err(FloatError::Zero);
+        }
+        Ok(value)
+    }
+
     pub fn inner(&self) -> f64 {
         self.0
     }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0.
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+
+    use super::{FinitePositiveFloat, FloatError};
+
+    #[test]
+    fn operations_must_keep_the_invariants() {
+        let zero = FinitePositiveFloat::try_from(0.).unwrap();
+        let one = FinitePositiveFloat::try_from(1.).unwrap();
+        let two = FinitePositiveFloat::try_from(2.).unwrap();
+        assert_eq!(zero / zero, Err(FloatError::Nan));
+        assert_eq!(one / zero, Err(FloatError::Infinite));
+        assert_eq!(one - two, Err(FloatError::Negative));
+        assert_eq!((two - one).unwrap().inner(), 1.);
+        assert_eq!((one - one).unwrap().inner(), 0.);
+        assert_eq!(FinitePositiveFloat::try_non_zero(0.), Err(FloatError::Zero));
+        assert_eq!(
+            FinitePositiveFloat::try_from(-0.),
+            Err(FloatError::Negative)
+        );
+    }
+
+    #[test]
+    fn deserialization_must_validate() {
+        let value: FinitePositiveFloat = serde_json::from_str("1.5").unwrap();
+        assert_eq!(value.inner(), 1.5);
+        assert_eq!(serde_json::to_string(&value).unwrap(), "1.5");
+        assert!(serde_json::from_str::<FinitePositiveFloat>("-1.5").is_err());
+    }
 }
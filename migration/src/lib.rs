@@ -0,0 +1,42 @@
+pub use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ConnectOptions, Database, DatabaseConnection};
+
+mod m20231001_000001_create_tables;
+mod m20231002_000001_add_market_state;
+mod m20231003_000001_add_trade_prices;
+mod m20231004_000001_add_lifecycle;
+mod m20231005_000001_add_outcome_oracle_values;
+
+pub struct Migrator;
+
+#[async_trait::async_trait]
+impl MigratorTrait for Migrator {
+    fn migrations() -> Vec<Box<dyn MigrationTrait>> {
+        vec![
+            Box::new(m20231001_000001_create_tables::Migration),
+            Box::new(m20231002_000001_add_market_state::Migration),
+            Box::new(m20231003_000001_add_trade_prices::Migration),
+            Box::new(m20231004_000001_add_lifecycle::Migration),
+            Box::new(m20231005_000001_add_outcome_oracle_values::Migration),
+        ]
+    }
+}
+
+/// URL of a SQLite database which only lives as long as the process.
+pub const MEMORY_DB_URL: &str = "sqlite::memory:";
+
+/// Connection options of the in-memory database. Every connection to
+/// `sqlite::memory:` opens a new empty database, so the pool must keep
+/// exactly one connection alive.
+pub fn memory_connect_options() -> ConnectOptions {
+    let mut options = ConnectOptions::new(MEMORY_DB_URL.to_owned());
+    options.max_connections(1).min_connections(1);
+    options
+}
+
+/// In-memory database with every migration applied, for tests.
+pub async fn memory_db() -> Result<DatabaseConnection, DbErr> {
+    let db = Database::connect(memory_connect_options()).await?;
+    Migrator::up(&db, None).await?;
+    Ok(db)
+}
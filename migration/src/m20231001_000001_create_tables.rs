@@ -0,0 +1,320 @@
+//! Initial schema, matching `amm::entity`.
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(Market::Table)
+                    .if_not_exists()
+                    .col(&mut pk(Market::Id))
+                    .col(ColumnDef::new(Market::Name).string().not_null())
+                    .col(ColumnDef::new(Market::IsActive).boolean().not_null())
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Decision::Table)
+                    .if_not_exists()
+                    .col(&mut pk(Decision::Id))
+                    .col(ColumnDef::new(Decision::MarketId).integer().not_null())
+                    .col(ColumnDef::new(Decision::Name).string().not_null())
+                    .col(ColumnDef::new(Decision::IsPast).boolean().not_null())
+                    .col(
+                        ColumnDef::new(Decision::OraclePublicKey)
+                            .binary()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Decision::Table, Decision::MarketId)
+                            .to(Market::Table, Market::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Outcome::Table)
+                    .if_not_exists()
+                    .col(&mut pk(Outcome::Id))
+                    .col(ColumnDef::new(Outcome::DecisionId).integer().not_null())
+                    .col(ColumnDef::new(Outcome::Index).integer().not_null())
+                    .col(ColumnDef::new(Outcome::Name).string().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Outcome::Table, Outcome::DecisionId)
+                            .to(Decision::Table, Decision::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-outcome-decision-index")
+                    .table(Outcome::Table)
+                    .col(Outcome::DecisionId)
+                    .col(Outcome::Index)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Trade::Table)
+                    .if_not_exists()
+                    .col(&mut pk(Trade::Id))
+                    .col(ColumnDef::new(Trade::MarketId).integer().not_null())
+                    .col(ColumnDef::new(Trade::OutcomeId).integer().not_null())
+                    .col(ColumnDef::new(Trade::Trader).string().not_null())
+                    .col(ColumnDef::new(Trade::Side).string_len(4).not_null())
+                    .col(ColumnDef::new(Trade::Amount).double().not_null())
+                    .col(ColumnDef::new(Trade::Cost).double().not_null())
+                    .col(ColumnDef::new(Trade::CreatedAt).big_integer().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Trade::Table, Trade::MarketId)
+                            .to(Market::Table, Market::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Trade::Table, Trade::OutcomeId)
+                            .to(Outcome::Table, Outcome::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-trade-market-created-at")
+                    .table(Trade::Table)
+                    .col(Trade::MarketId)
+                    .col(Trade::CreatedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Position::Table)
+                    .if_not_exists()
+                    .col(&mut pk(Position::Id))
+                    .col(ColumnDef::new(Position::MarketId).integer().not_null())
+                    .col(ColumnDef::new(Position::OutcomeId).integer().not_null())
+                    .col(ColumnDef::new(Position::Trader).string().not_null())
+                    .col(ColumnDef::new(Position::Amount).double().not_null())
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Position::Table, Position::MarketId)
+                            .to(Market::Table, Market::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Position::Table, Position::OutcomeId)
+                            .to(Outcome::Table, Outcome::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx-position-market-outcome-trader")
+                    .table(Position::Table)
+                    .col(Position::MarketId)
+                    .col(Position::OutcomeId)
+                    .col(Position::Trader)
+                    .unique()
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(LiquidityEvent::Table)
+                    .if_not_exists()
+                    .col(&mut pk(LiquidityEvent::Id))
+                    .col(
+                        ColumnDef::new(LiquidityEvent::MarketId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(LiquidityEvent::Provider).string().not_null())
+                    .col(
+                        ColumnDef::new(LiquidityEvent::Kind)
+                            .string_len(8)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(LiquidityEvent::Amount).double().not_null())
+                    .col(ColumnDef::new(LiquidityEvent::Shares).double().not_null())
+                    .col(
+                        ColumnDef::new(LiquidityEvent::CreatedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(LiquidityEvent::Table, LiquidityEvent::MarketId)
+                            .to(Market::Table, Market::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(Resolution::Table)
+                    .if_not_exists()
+                    .col(&mut pk(Resolution::Id))
+                    .col(
+                        ColumnDef::new(Resolution::DecisionId)
+                            .integer()
+                            .not_null()
+                            .unique_key(),
+                    )
+                    .col(ColumnDef::new(Resolution::OutcomeId).integer().not_null())
+                    .col(ColumnDef::new(Resolution::Attestation).binary().not_null())
+                    .col(
+                        ColumnDef::new(Resolution::ResolvedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Resolution::Table, Resolution::DecisionId)
+                            .to(Decision::Table, Decision::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(Resolution::Table, Resolution::OutcomeId)
+                            .to(Outcome::Table, Outcome::Id),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for table in [
+            Resolution::Table.into_iden(),
+            LiquidityEvent::Table.into_iden(),
+            Position::Table.into_iden(),
+            Trade::Table.into_iden(),
+            Outcome::Table.into_iden(),
+            Decision::Table.into_iden(),
+            Market::Table.into_iden(),
+        ] {
+            manager
+                .drop_table(Table::drop().table(table).to_owned())
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+fn pk<T: IntoIden>(column: T) -> ColumnDef {
+    ColumnDef::new(column)
+        .integer()
+        .not_null()
+        .auto_increment()
+        .primary_key()
+        .to_owned()
+}
+
+#[derive(DeriveIden)]
+enum Market {
+    Table,
+    Id,
+    Name,
+    IsActive,
+}
+
+#[derive(DeriveIden)]
+enum Decision {
+    Table,
+    Id,
+    MarketId,
+    Name,
+    IsPast,
+    OraclePublicKey,
+}
+
+#[derive(DeriveIden)]
+enum Outcome {
+    Table,
+    Id,
+    DecisionId,
+    Index,
+    Name,
+}
+
+#[derive(DeriveIden)]
+enum Trade {
+    Table,
+    Id,
+    MarketId,
+    OutcomeId,
+    Trader,
+    Side,
+    Amount,
+    Cost,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Position {
+    Table,
+    Id,
+    MarketId,
+    OutcomeId,
+    Trader,
+    Amount,
+}
+
+#[derive(DeriveIden)]
+enum LiquidityEvent {
+    Table,
+    Id,
+    MarketId,
+    Provider,
+    Kind,
+    Amount,
+    Shares,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum Resolution {
+    Table,
+    Id,
+    DecisionId,
+    OutcomeId,
+    Attestation,
+    ResolvedAt,
+}
@@ -0,0 +1,60 @@
+//! State of the market maker, stored next to the market.
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite can only add one column per statement.
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Market::Table)
+                    .add_column(ColumnDef::new(Market::MarketMaker).json())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Market::Table)
+                    .add_column(
+                        ColumnDef::new(Market::Version)
+                            .big_integer()
+                            .not_null()
+                            .default(0),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Market::Table)
+                    .drop_column(Market::Version)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Market::Table)
+                    .drop_column(Market::MarketMaker)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Market {
+    Table,
+    MarketMaker,
+    Version,
+}
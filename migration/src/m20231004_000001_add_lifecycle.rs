@@ -0,0 +1,247 @@
+//! Explicit lifecycle of markets, in place of `market.is_active` and
+//! `decision.is_past`, with an audit table of every transition.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use sea_orm_migration::prelude::*;
+
+/// Reason of the transitions which record the statuses given by this
+/// migration.
+const MIGRATION_REASON: &str = "migrated from is_active and is_past";
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Market::Table)
+                    .add_column(
+                        ColumnDef::new(Market::Status)
+                            .string_len(16)
+                            .not_null()
+                            .default("draft"),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        // Markets whose event is past are not tradable any more.
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(Market::Table)
+                    .value(Market::Status, "closed")
+                    .and_where(
+                        Expr::col(Market::Id).in_subquery(
+                            Query::select()
+                                .column(Decision::MarketId)
+                                .from(Decision::Table)
+                                .and_where(Expr::col(Decision::IsPast).eq(true))
+                                .to_owned(),
+                        ),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(Market::Table)
+                    .value(Market::Status, "open")
+                    .and_where(Expr::col(Market::IsActive).eq(true))
+                    .and_where(Expr::col(Market::Status).eq("draft"))
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_table(
+                Table::create()
+                    .table(MarketTransition::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(MarketTransition::Id)
+                            .integer()
+                            .not_null()
+                            .auto_increment()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(MarketTransition::MarketId)
+                            .integer()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(MarketTransition::FromStatus)
+                            .string_len(16)
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(MarketTransition::ToStatus)
+                            .string_len(16)
+                            .not_null(),
+                    )
+                    .col(ColumnDef::new(MarketTransition::Reason).string())
+                    .col(
+                        ColumnDef::new(MarketTransition::CreatedAt)
+                            .big_integer()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .from(MarketTransition::Table, MarketTransition::MarketId)
+                            .to(Market::Table, Market::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        // Every market starts as a draft, so the audit of the markets which
+        // are not drafts any more starts with the status given here.
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+        manager
+            .exec_stmt(
+                Query::insert()
+                    .into_table(MarketTransition::Table)
+                    .columns([
+                        MarketTransition::MarketId,
+                        MarketTransition::FromStatus,
+                        MarketTransition::ToStatus,
+                        MarketTransition::Reason,
+                        MarketTransition::CreatedAt,
+                    ])
+                    .select_from(
+                        Query::select()
+                            .column(Market::Id)
+                            .expr(Expr::val("draft"))
+                            .column(Market::Status)
+                            .expr(Expr::val(MIGRATION_REASON))
+                            .expr(Expr::val(now))
+                            .from(Market::Table)
+                            .and_where(Expr::col(Market::Status).ne("draft"))
+                            .to_owned(),
+                    )
+                    .map_err(|e| DbErr::Migration(e.to_string()))?
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Market::Table)
+                    .drop_column(Market::IsActive)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Decision::Table)
+                    .drop_column(Decision::IsPast)
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(MarketTransition::Table).to_owned())
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Decision::Table)
+                    .add_column(
+                        ColumnDef::new(Decision::IsPast)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Market::Table)
+                    .add_column(
+                        ColumnDef::new(Market::IsActive)
+                            .boolean()
+                            .not_null()
+                            .default(false),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(Market::Table)
+                    .value(Market::IsActive, true)
+                    .and_where(Expr::col(Market::Status).eq("open"))
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .exec_stmt(
+                Query::update()
+                    .table(Decision::Table)
+                    .value(Decision::IsPast, true)
+                    .and_where(
+                        Expr::col(Decision::MarketId).in_subquery(
+                            Query::select()
+                                .column(Market::Id)
+                                .from(Market::Table)
+                                .and_where(Expr::col(Market::Status).is_in([
+                                    "closed",
+                                    "resolving",
+                                    "resolved",
+                                ]))
+                                .to_owned(),
+                        ),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Market::Table)
+                    .drop_column(Market::Status)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Market {
+    Table,
+    Id,
+    IsActive,
+    Status,
+}
+
+#[derive(DeriveIden)]
+enum Decision {
+    Table,
+    MarketId,
+    IsPast,
+}
+
+#[derive(DeriveIden)]
+enum MarketTransition {
+    Table,
+    Id,
+    MarketId,
+    FromStatus,
+    ToStatus,
+    Reason,
+    CreatedAt,
+}
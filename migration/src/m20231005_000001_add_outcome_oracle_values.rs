@@ -0,0 +1,66 @@
+//! Label of each outcome, and the value the oracle signs for it.
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Outcome::Table)
+                    .rename_column(Outcome::Name, Outcome::Label)
+                    .to_owned(),
+            )
+            .await?;
+        for mut column in [
+            ColumnDef::new(Outcome::OracleValue).string().to_owned(),
+            ColumnDef::new(Outcome::RangeStart).big_integer().to_owned(),
+            ColumnDef::new(Outcome::RangeEnd).big_integer().to_owned(),
+        ] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Outcome::Table)
+                        .add_column(&mut column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in [Outcome::RangeEnd, Outcome::RangeStart, Outcome::OracleValue] {
+            manager
+                .alter_table(
+                    Table::alter()
+                        .table(Outcome::Table)
+                        .drop_column(column)
+                        .to_owned(),
+                )
+                .await?;
+        }
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(Outcome::Table)
+                    .rename_column(Outcome::Label, Outcome::Name)
+                    .to_owned(),
+            )
+            .await
+    }
+}
+
+#[derive(DeriveIden)]
+enum Outcome {
+    Table,
+    Name,
+    Label,
+    OracleValue,
+    RangeStart,
+    RangeEnd,
+}